@@ -1,6 +1,9 @@
+use alloc::borrow::Cow;
+use alloc::collections::BTreeMap;
 use alloc::string::String;
 use axerrno::AxResult;
 use axfs::fops::{Directory, File};
+use kspin::SpinNoIrq;
 
 pub use axfs::fops::DirEntry as AxDirEntry;
 pub use axfs::fops::FileAttr as AxFileAttr;
@@ -12,6 +15,41 @@ pub use axio::SeekFrom as AxSeekFrom;
 #[cfg(feature = "myfs")]
 pub use axfs::fops::{Disk as AxDisk, MyFileSystemIf};
 
+/// Working directories of the tasks that called [`ax_chdir`], by task id.
+///
+/// Other tasks use the global current directory of `axfs`. Entries are
+/// removed by [`forget_task_cwd`] when their task exits.
+static TASK_CWD: SpinNoIrq<BTreeMap<u64, String>> = SpinNoIrq::new(BTreeMap::new());
+
+fn current_task_id() -> u64 {
+    #[cfg(feature = "multitask")]
+    {
+        axtask::current().id().as_u64()
+    }
+    #[cfg(not(feature = "multitask"))]
+    {
+        0
+    }
+}
+
+/// Forgets the working directory of the task `id`, which is exiting.
+#[cfg(feature = "multitask")]
+pub(crate) fn forget_task_cwd(id: u64) {
+    TASK_CWD.lock().remove(&id);
+}
+
+/// Makes a relative path absolute against the working directory of the
+/// current task, if it has one.
+fn task_path(path: &str) -> Cow<'_, str> {
+    if path.starts_with('/') {
+        return Cow::Borrowed(path);
+    }
+    match TASK_CWD.lock().get(&current_task_id()) {
+        Some(cwd) => Cow::Owned(alloc::format!("{}/{}", cwd.trim_end_matches('/'), path)),
+        None => Cow::Borrowed(path),
+    }
+}
+
 /// A handle to an opened file.
 pub struct AxFileHandle(File);
 
@@ -19,11 +57,11 @@ pub struct AxFileHandle(File);
 pub struct AxDirHandle(Directory);
 
 pub fn ax_open_file(path: &str, opts: &AxOpenOptions) -> AxResult<AxFileHandle> {
-    Ok(AxFileHandle(File::open(path, opts)?))
+    Ok(AxFileHandle(File::open(&task_path(path), opts)?))
 }
 
 pub fn ax_open_dir(path: &str, opts: &AxOpenOptions) -> AxResult<AxDirHandle> {
-    Ok(AxDirHandle(Directory::open_dir(path, opts)?))
+    Ok(AxDirHandle(Directory::open_dir(&task_path(path), opts)?))
 }
 
 pub fn ax_read_file(file: &mut AxFileHandle, buf: &mut [u8]) -> AxResult<usize> {
@@ -63,19 +101,19 @@ pub fn ax_read_dir(dir: &mut AxDirHandle, dirents: &mut [AxDirEntry]) -> AxResul
 }
 
 pub fn ax_create_dir(path: &str) -> AxResult {
-    axfs::api::create_dir(path)
+    axfs::api::create_dir(&task_path(path))
 }
 
 pub fn ax_remove_dir(path: &str) -> AxResult {
-    axfs::api::remove_dir(path)
+    axfs::api::remove_dir(&task_path(path))
 }
 
 pub fn ax_remove_file(path: &str) -> AxResult {
-    axfs::api::remove_file(path)
+    axfs::api::remove_file(&task_path(path))
 }
 
 pub fn ax_rename(old: &str, new: &str) -> AxResult {
-    axfs::api::rename(old, new)
+    axfs::api::rename(&task_path(old), &task_path(new))
 }
 
 pub fn ax_current_dir() -> AxResult<String> {
@@ -85,3 +123,20 @@ pub fn ax_current_dir() -> AxResult<String> {
 pub fn ax_set_current_dir(path: &str) -> AxResult {
     axfs::api::set_current_dir(path)
 }
+
+pub fn ax_getcwd() -> AxResult<String> {
+    match TASK_CWD.lock().get(&current_task_id()) {
+        Some(cwd) => Ok(cwd.clone()),
+        None => axfs::api::current_dir(),
+    }
+}
+
+pub fn ax_chdir(path: &str) -> AxResult {
+    let path = axfs::api::canonicalize(&task_path(path))?;
+    let mut opts = AxOpenOptions::new();
+    opts.read(true);
+    // fails with `NotADirectory` on anything but a directory
+    Directory::open_dir(&path, &opts)?;
+    TASK_CWD.lock().insert(current_task_id(), path);
+    Ok(())
+}
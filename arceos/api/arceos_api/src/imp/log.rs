@@ -0,0 +1,29 @@
+use core::fmt;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use crate::log::AxLogLevel;
+
+/// Most verbose level printed by [`ax_log`].
+static MAX_LEVEL: AtomicU8 = AtomicU8::new(AxLogLevel::Info as u8);
+
+pub fn ax_log(level: AxLogLevel, args: fmt::Arguments) {
+    if level != AxLogLevel::Off && level <= ax_log_get_level() {
+        // the message may be lost, as there is nowhere to report the error
+        let _ = axlog::print_fmt(format_args!("[{}] {}\n", level.as_str(), args));
+    }
+}
+
+pub fn ax_log_set_level(level: AxLogLevel) {
+    MAX_LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn ax_log_get_level() -> AxLogLevel {
+    match MAX_LEVEL.load(Ordering::Relaxed) {
+        0 => AxLogLevel::Off,
+        1 => AxLogLevel::Error,
+        2 => AxLogLevel::Warn,
+        3 => AxLogLevel::Info,
+        4 => AxLogLevel::Debug,
+        _ => AxLogLevel::Trace,
+    }
+}
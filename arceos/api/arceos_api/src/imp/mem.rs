@@ -12,6 +12,125 @@ cfg_alloc! {
     }
 }
 
+cfg_paging! {
+    use alloc::{collections::BTreeMap, sync::Arc};
+    use axerrno::{ax_err, AxResult};
+    use kspin::SpinNoIrq;
+    use memory_addr::{align_up_4k, VirtAddr, VirtAddrRange, PAGE_SIZE_4K};
+
+    pub use axhal::paging::MappingFlags as AxMapFlags;
+
+    /// Regions mapped by [`ax_mem_map`], from their base address to their
+    /// size, so that [`ax_mem_unmap`] cannot unmap anything else.
+    static MAPPED: SpinNoIrq<BTreeMap<usize, usize>> = SpinNoIrq::new(BTreeMap::new());
+
+    pub fn ax_mem_map(size: usize, flags: AxMapFlags) -> AxResult<usize> {
+        if size == 0 {
+            return ax_err!(InvalidInput, "ax_mem_map: zero size");
+        }
+        let Some(size) = size.checked_next_multiple_of(PAGE_SIZE_4K) else {
+            return ax_err!(InvalidInput, "ax_mem_map: size too large");
+        };
+        // locked before the address space, as in `ax_mem_unmap`
+        let mut mapped = MAPPED.lock();
+        let mut aspace = axmm::kernel_aspace().lock();
+        let limit = VirtAddrRange::new(aspace.base(), aspace.end());
+        let start = aspace
+            .find_free_area(aspace.base(), size, limit)
+            .ok_or(axerrno::AxError::NoMemory)?;
+        aspace.map_alloc(start, size, flags, true)?;
+        mapped.insert(start.as_usize(), size);
+        Ok(start.as_usize())
+    }
+
+    pub fn ax_mem_unmap(addr: usize, size: usize) -> AxResult {
+        // sizes too large to align are not those of any region
+        let size = size.checked_next_multiple_of(PAGE_SIZE_4K);
+        let mut mapped = MAPPED.lock();
+        if size.is_none() || mapped.get(&addr) != size.as_ref() {
+            return ax_err!(InvalidInput, "ax_mem_unmap: not a region mapped by ax_mem_map");
+        }
+        let size = mapped[&addr];
+        axmm::kernel_aspace().lock().unmap(VirtAddr::from(addr), size)?;
+        mapped.remove(&addr);
+        Ok(())
+    }
+
+    /// A shared memory region, freed with its last handle.
+    #[derive(Clone)]
+    pub struct AxShmHandle(Arc<ShmRegion>);
+
+    struct ShmRegion {
+        /// Address of the pages in the linear mapping.
+        start: VirtAddr,
+        size: usize,
+        /// Base address of the mapping of each task, by task ID.
+        ///
+        /// Never locked while holding the lock of the kernel address space.
+        mappings: SpinNoIrq<BTreeMap<u64, VirtAddr>>,
+    }
+
+    impl Drop for ShmRegion {
+        fn drop(&mut self) {
+            let mappings = core::mem::take(&mut *self.mappings.lock());
+            let mut aspace = axmm::kernel_aspace().lock();
+            for (_, vaddr) in mappings {
+                aspace.unmap(vaddr, self.size).ok();
+            }
+            axalloc::global_allocator()
+                .dealloc_pages(self.start.as_usize(), self.size / PAGE_SIZE_4K);
+        }
+    }
+
+    fn current_task_id() -> u64 {
+        #[cfg(feature = "multitask")]
+        let id = axtask::current().id().as_u64();
+        #[cfg(not(feature = "multitask"))]
+        let id = 0;
+        id
+    }
+
+    pub fn ax_shm_create(size: usize) -> AxShmHandle {
+        let size = align_up_4k(size.max(1));
+        let start = axalloc::global_allocator()
+            .alloc_pages(size / PAGE_SIZE_4K, PAGE_SIZE_4K)
+            .expect("ax_shm_create: out of memory");
+        unsafe { core::ptr::write_bytes(start as *mut u8, 0, size) };
+        AxShmHandle(Arc::new(ShmRegion {
+            start: VirtAddr::from(start),
+            size,
+            mappings: SpinNoIrq::new(BTreeMap::new()),
+        }))
+    }
+
+    pub fn ax_shm_map(handle: &AxShmHandle) -> AxResult<usize> {
+        let region = &handle.0;
+        let mut mappings = region.mappings.lock();
+        let task_id = current_task_id();
+        if let Some(vaddr) = mappings.get(&task_id) {
+            return Ok(vaddr.as_usize());
+        }
+        let mut aspace = axmm::kernel_aspace().lock();
+        let limit = VirtAddrRange::new(aspace.base(), aspace.end());
+        let vaddr = aspace
+            .find_free_area(aspace.base(), region.size, limit)
+            .ok_or(axerrno::AxError::NoMemory)?;
+        let paddr = axhal::mem::virt_to_phys(region.start);
+        let flags = AxMapFlags::READ | AxMapFlags::WRITE;
+        aspace.map_linear(vaddr, paddr, region.size, flags)?;
+        mappings.insert(task_id, vaddr);
+        Ok(vaddr.as_usize())
+    }
+
+    pub fn ax_shm_unmap(handle: &AxShmHandle) -> AxResult {
+        let region = &handle.0;
+        let Some(vaddr) = region.mappings.lock().remove(&current_task_id()) else {
+            return ax_err!(BadState, "ax_shm_unmap: not mapped by the current task");
+        };
+        axmm::kernel_aspace().lock().unmap(vaddr, region.size)
+    }
+}
+
 cfg_dma! {
     pub use axdma::DMAInfo;
 
@@ -1,6 +1,15 @@
+mod log;
 mod mem;
+mod stdio;
 mod task;
 
+cfg_task! {
+    mod sync;
+    mod timer;
+    pub use sync::*;
+    pub use timer::*;
+}
+
 cfg_fs! {
     mod fs;
     pub use fs::*;
@@ -16,33 +25,60 @@ cfg_display! {
     pub use display::*;
 }
 
-mod stdio {
-    use core::fmt;
+mod time {
+    pub use axhal::time::{
+        monotonic_time as ax_monotonic_time, monotonic_time_nanos as ax_monotonic_time_nanos,
+        wall_time as ax_wall_time, TimeValue as AxTimeValue,
+    };
 
-    pub fn ax_console_read_byte() -> Option<u8> {
-        axhal::console::getchar().map(|c| if c == b'\r' { b'\n' } else { c })
+    pub fn ax_clock_getres() -> core::time::Duration {
+        // one timer tick, but at least a nanosecond for timers beyond 1 GHz
+        core::time::Duration::from_nanos(axhal::time::ticks_to_nanos(1).max(1))
     }
 
-    pub fn ax_console_write_bytes(buf: &[u8]) -> crate::AxResult<usize> {
-        axhal::console::write_bytes(buf);
-        Ok(buf.len())
+    pub fn ax_delay_ns(nanos: u64) {
+        // monotonic time, as the wall clock may be adjusted while spinning
+        let deadline = axhal::time::monotonic_time() + core::time::Duration::from_nanos(nanos);
+        while axhal::time::monotonic_time() < deadline {
+            core::hint::spin_loop();
+        }
     }
 
-    pub fn ax_console_write_fmt(args: fmt::Arguments) -> fmt::Result {
-        axlog::print_fmt(args)
+    pub fn ax_delay_us(micros: u64) {
+        ax_delay_ns(micros.saturating_mul(1000))
     }
 }
 
-mod time {
-    pub use axhal::time::{
-        monotonic_time as ax_monotonic_time, wall_time as ax_wall_time, TimeValue as AxTimeValue,
-    };
-}
-
 mod misc {
     pub use axhal::misc::random as ax_random;
+
+    use crate::misc::AxSysInfo;
+
+    pub fn ax_sysinfo() -> AxSysInfo {
+        #[cfg(feature = "alloc")]
+        let (total_mem, free_mem) = {
+            let allocator = axalloc::global_allocator();
+            let free = allocator.available_pages() * axhal::mem::PAGE_SIZE_4K;
+            let used = allocator.used_pages() * axhal::mem::PAGE_SIZE_4K;
+            (used + free, free)
+        };
+        #[cfg(not(feature = "alloc"))]
+        let (total_mem, free_mem) = (0, 0);
+        #[cfg(feature = "multitask")]
+        let task_count = axtask::num_tasks();
+        #[cfg(not(feature = "multitask"))]
+        let task_count = 1;
+        AxSysInfo {
+            uptime: axhal::time::monotonic_time(),
+            total_mem,
+            free_mem,
+            task_count,
+            cpu_count: axconfig::SMP,
+        }
+    }
 }
 
+pub use self::log::*;
 pub use self::mem::*;
 pub use self::stdio::*;
 pub use self::task::*;
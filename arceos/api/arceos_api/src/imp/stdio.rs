@@ -0,0 +1,130 @@
+use core::fmt;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use kspin::SpinNoIrq;
+
+/// Maximum length of a line buffered in cooked mode.
+const LINE_BUF_SIZE: usize = 256;
+
+/// Whether the console is in raw mode (no echo, no line buffering).
+///
+/// Raw mode is the default, as callers such as the shell do their own echoing.
+static RAW_MODE: AtomicBool = AtomicBool::new(true);
+
+/// Line being edited in cooked mode.
+static LINE: SpinNoIrq<LineBuf> = SpinNoIrq::new(LineBuf::new());
+
+struct LineBuf {
+    buf: [u8; LINE_BUF_SIZE],
+    len: usize,
+    /// Read cursor, only meaningful once the line is complete.
+    pos: usize,
+    ready: bool,
+}
+
+impl LineBuf {
+    const fn new() -> Self {
+        Self {
+            buf: [0; LINE_BUF_SIZE],
+            len: 0,
+            pos: 0,
+            ready: false,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.len = 0;
+        self.pos = 0;
+        self.ready = false;
+    }
+
+    /// Pops the next byte of a completed line.
+    fn pop(&mut self) -> Option<u8> {
+        if !self.ready {
+            return None;
+        }
+        let c = self.buf[self.pos];
+        self.pos += 1;
+        if self.pos == self.len {
+            self.clear();
+        }
+        Some(c)
+    }
+
+    /// Feeds a byte typed by the user, echoing and handling line editing.
+    fn feed(&mut self, c: u8) {
+        match c {
+            // backspace or delete
+            0x08 | 0x7f => {
+                if self.len > 0 {
+                    self.len -= 1;
+                    axhal::console::write_bytes(b"\x08 \x08");
+                }
+            }
+            _ => {
+                self.buf[self.len] = c;
+                self.len += 1;
+                axhal::console::write_bytes(&[c]);
+                if c == b'\n' || self.len == LINE_BUF_SIZE {
+                    self.ready = true;
+                }
+            }
+        }
+    }
+}
+
+fn getchar() -> Option<u8> {
+    axhal::console::getchar().map(|c| if c == b'\r' { b'\n' } else { c })
+}
+
+pub fn ax_console_read_byte() -> Option<u8> {
+    if RAW_MODE.load(Ordering::Acquire) {
+        return getchar();
+    }
+    let mut line = LINE.lock();
+    loop {
+        if let Some(c) = line.pop() {
+            return Some(c);
+        }
+        let c = getchar()?;
+        line.feed(c);
+    }
+}
+
+pub fn ax_console_read_until(delim: u8, buf: &mut [u8]) -> crate::AxResult<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match ax_console_read_byte() {
+            Some(c) => {
+                buf[len] = c;
+                len += 1;
+                if c == delim {
+                    break;
+                }
+            }
+            None => super::ax_yield_now(),
+        }
+    }
+    Ok(len)
+}
+
+pub fn ax_console_write_bytes(buf: &[u8]) -> crate::AxResult<usize> {
+    axhal::console::write_bytes(buf);
+    Ok(buf.len())
+}
+
+pub fn ax_console_write_fmt(args: fmt::Arguments) -> fmt::Result {
+    axlog::print_fmt(args)
+}
+
+pub fn ax_console_set_raw(enabled: bool) {
+    let mut line = LINE.lock();
+    if RAW_MODE.swap(enabled, Ordering::AcqRel) != enabled {
+        // drop any partially edited line when switching modes
+        line.clear();
+    }
+}
+
+pub fn ax_console_is_raw() -> bool {
+    RAW_MODE.load(Ordering::Acquire)
+}
@@ -0,0 +1,59 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+use axerrno::{ax_err, AxResult};
+
+/// A handle to an event, a counter that tasks can wait on.
+pub struct AxEventHandle {
+    count: AtomicU64,
+    wq: axtask::WaitQueue,
+}
+
+impl AxEventHandle {
+    /// Takes one from the counter if it is nonzero.
+    fn try_take(&self) -> bool {
+        self.count
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| n.checked_sub(1))
+            .is_ok()
+    }
+}
+
+pub fn ax_event_create() -> AxEventHandle {
+    AxEventHandle {
+        count: AtomicU64::new(0),
+        wq: axtask::WaitQueue::new(),
+    }
+}
+
+pub fn ax_event_signal(event: &AxEventHandle) {
+    event.count.fetch_add(1, Ordering::AcqRel);
+    event.wq.notify_one(true);
+}
+
+pub fn ax_event_wait(event: &AxEventHandle, timeout: Option<Duration>) -> AxResult {
+    let signaled = || event.count.load(Ordering::Acquire) != 0;
+    #[cfg(feature = "irq")]
+    if let Some(dur) = timeout {
+        let deadline = axhal::time::wall_time()
+            .checked_add(dur)
+            .unwrap_or(Duration::MAX);
+        // another waiter may take the count between the wakeup and `try_take`
+        while !event.try_take() {
+            let now = axhal::time::wall_time();
+            if now >= deadline {
+                return ax_err!(TimedOut, "ax_event_wait: timed out");
+            }
+            let timeout = (deadline - now).min(super::timer::MAX_WAIT);
+            event.wq.wait_timeout_until(timeout, signaled);
+        }
+        return Ok(());
+    }
+
+    if timeout.is_some() {
+        axlog::warn!("ax_event_wait: the `timeout` argument is ignored without the `irq` feature");
+    }
+    while !event.try_take() {
+        event.wq.wait_until(signaled);
+    }
+    Ok(())
+}
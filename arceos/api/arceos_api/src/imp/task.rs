@@ -1,13 +1,28 @@
 pub fn ax_sleep_until(deadline: crate::time::AxTimeValue) {
     #[cfg(feature = "multitask")]
-    axtask::sleep_until(deadline);
+    {
+        axtask::sleep_until(deadline);
+        deliver_signals();
+    }
     #[cfg(not(feature = "multitask"))]
     axhal::time::busy_wait_until(deadline);
 }
 
+pub fn ax_sleep_until_nanos(abs_monotonic_nanos: u64) {
+    if axhal::time::monotonic_time_nanos() >= abs_monotonic_nanos {
+        return;
+    }
+    // sleeps take wall-clock deadlines, ahead by the epoch offset
+    let deadline = abs_monotonic_nanos.saturating_add(axhal::time::epochoffset_nanos());
+    ax_sleep_until(core::time::Duration::from_nanos(deadline));
+}
+
 pub fn ax_yield_now() {
     #[cfg(feature = "multitask")]
-    axtask::yield_now();
+    {
+        axtask::yield_now();
+        deliver_signals();
+    }
     #[cfg(not(feature = "multitask"))]
     if cfg!(feature = "irq") {
         axhal::arch::wait_for_irqs();
@@ -18,13 +33,83 @@ pub fn ax_yield_now() {
 
 pub fn ax_exit(_exit_code: i32) -> ! {
     #[cfg(feature = "multitask")]
-    axtask::exit(_exit_code);
+    {
+        task_exiting();
+        axtask::exit(_exit_code);
+    }
     #[cfg(not(feature = "multitask"))]
     axhal::misc::terminate();
 }
 
+/// Mask of all the CPUs in the system.
+fn all_cpus_mask() -> usize {
+    match axconfig::SMP {
+        n if n >= usize::BITS as usize => usize::MAX,
+        n => (1 << n) - 1,
+    }
+}
+
+pub fn ax_sched_setaffinity(cpu_mask: usize) -> crate::AxResult {
+    let all = all_cpus_mask();
+    if cpu_mask & all == 0 {
+        return axerrno::ax_err!(InvalidInput, "ax_sched_setaffinity: no CPU in the mask");
+    }
+    if cpu_mask & all != all {
+        return axerrno::ax_err!(
+            Unsupported,
+            "ax_sched_setaffinity: per-task CPU affinity is not supported"
+        );
+    }
+    Ok(())
+}
+
+pub fn ax_sched_getaffinity() -> usize {
+    all_cpus_mask()
+}
+
 cfg_task! {
+    use alloc::collections::BTreeMap;
+    use alloc::vec::Vec;
     use core::time::Duration;
+    use kspin::SpinNoIrq;
+
+    /// Signal state of a task that called [`ax_signal_register`].
+    struct TaskSignals {
+        handler: fn(sig: u32),
+        pending: Vec<u32>,
+    }
+
+    /// Signal handlers and pending signals, by task id. Entries are removed
+    /// when their task exits.
+    static SIGNALS: SpinNoIrq<BTreeMap<u64, TaskSignals>> = SpinNoIrq::new(BTreeMap::new());
+
+    /// Runs the handler of the current task for each of its pending signals,
+    /// in the order they were raised.
+    fn deliver_signals() {
+        let id = ax_current_task_id();
+        loop {
+            // the lock is not held by the handler, which may raise signals
+            let (handler, pending) = match SIGNALS.lock().get_mut(&id) {
+                Some(signals) if !signals.pending.is_empty() => {
+                    (signals.handler, core::mem::take(&mut signals.pending))
+                }
+                _ => return,
+            };
+            for sig in pending {
+                handler(sig);
+            }
+        }
+    }
+
+    /// Drops the state kept by this crate for the current task, which is
+    /// exiting, either through [`ax_exit`] or by returning from the entry
+    /// point given to [`ax_spawn`].
+    fn task_exiting() {
+        let id = ax_current_task_id();
+        SIGNALS.lock().remove(&id);
+        #[cfg(feature = "fs")]
+        super::fs::forget_task_cwd(id);
+    }
 
     /// A handle to a task.
     pub struct AxTaskHandle {
@@ -60,7 +145,11 @@ cfg_task! {
     where
         F: FnOnce() + Send + 'static,
     {
-        let inner = axtask::spawn_raw(f, name, stack_size);
+        let main = move || {
+            f();
+            task_exiting();
+        };
+        let inner = axtask::spawn_raw(main, name, stack_size);
         AxTaskHandle {
             id: inner.id().as_u64(),
             inner,
@@ -71,6 +160,23 @@ cfg_task! {
         task.inner.join()
     }
 
+    pub fn ax_join_all(handles: &[AxTaskHandle]) -> alloc::vec::Vec<crate::AxResult<i32>> {
+        let current = ax_current_task_id();
+        handles
+            .iter()
+            .map(|task| {
+                if task.id == current {
+                    // joining itself would never return
+                    return Err(axerrno::ax_err_type!(
+                        BadState,
+                        "ax_join_all: a task cannot wait for itself"
+                    ));
+                }
+                task.inner.join().ok_or(axerrno::AxError::BadState)
+            })
+            .collect()
+    }
+
     pub fn ax_set_current_priority(prio: isize) -> crate::AxResult {
         if axtask::set_priority(prio) {
             Ok(())
@@ -89,13 +195,16 @@ cfg_task! {
     ) -> bool {
         #[cfg(feature = "irq")]
         if let Some(dur) = timeout {
-            return wq.0.wait_timeout_until(dur, until_condition);
+            let timed_out = wq.0.wait_timeout_until(dur, until_condition);
+            deliver_signals();
+            return timed_out;
         }
 
         if timeout.is_some() {
             axlog::warn!("ax_wait_queue_wait: the `timeout` argument is ignored without the `irq` feature");
         }
         wq.0.wait_until(until_condition);
+        deliver_signals();
         false
     }
 
@@ -108,4 +217,29 @@ cfg_task! {
             }
         }
     }
+
+    pub fn ax_signal_register(handler: fn(sig: u32)) {
+        let id = ax_current_task_id();
+        SIGNALS
+            .lock()
+            .entry(id)
+            .and_modify(|signals| signals.handler = handler)
+            .or_insert(TaskSignals {
+                handler,
+                pending: Vec::new(),
+            });
+    }
+
+    pub fn ax_signal_raise(task: &AxTaskHandle, sig: u32) -> crate::AxResult {
+        match SIGNALS.lock().get_mut(&task.id) {
+            Some(signals) => {
+                signals.pending.push(sig);
+                Ok(())
+            }
+            None => axerrno::ax_err!(
+                BadState,
+                "ax_signal_raise: the task has no signal handler"
+            ),
+        }
+    }
 }
@@ -0,0 +1,104 @@
+use alloc::collections::{BTreeMap, BinaryHeap};
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+
+use axhal::time::{monotonic_time, TimeValue};
+use kspin::SpinNoIrq;
+
+/// A handle to a timer created by [`ax_timer_after`].
+#[derive(Debug)]
+pub struct AxTimerHandle(u64);
+
+struct Timers {
+    /// Deadlines with the id of their timer, earliest first. Entries of
+    /// cancelled timers are skipped when they come up.
+    deadlines: BinaryHeap<Reverse<(TimeValue, u64)>>,
+    /// Callbacks of the pending timers, by id.
+    callbacks: BTreeMap<u64, fn()>,
+    next_id: u64,
+    /// Bumped on each new timer, to wake up the timer task.
+    generation: u64,
+}
+
+static TIMERS: SpinNoIrq<Timers> = SpinNoIrq::new(Timers {
+    deadlines: BinaryHeap::new(),
+    callbacks: BTreeMap::new(),
+    next_id: 0,
+    generation: 0,
+});
+
+/// Longest single wait on a wait queue, after which the deadline is checked
+/// again. Waits take a duration that the wait queue adds to the current
+/// time, which would overflow for far deadlines such as [`Duration::MAX`].
+#[cfg(feature = "irq")]
+pub(crate) const MAX_WAIT: Duration = Duration::from_secs(24 * 60 * 60);
+
+static TIMER_WQ: axtask::WaitQueue = axtask::WaitQueue::new();
+static TIMER_TASK_STARTED: AtomicBool = AtomicBool::new(false);
+
+/// Removes the timers that are due, and returns their callbacks with the
+/// next deadline, if any.
+fn take_due(now: TimeValue) -> (Vec<fn()>, Option<TimeValue>) {
+    let mut timers = TIMERS.lock();
+    let mut due = Vec::new();
+    while let Some(&Reverse((deadline, id))) = timers.deadlines.peek() {
+        if !timers.callbacks.contains_key(&id) {
+            timers.deadlines.pop(); // cancelled
+        } else if deadline <= now {
+            timers.deadlines.pop();
+            due.extend(timers.callbacks.remove(&id));
+        } else {
+            return (due, Some(deadline));
+        }
+    }
+    (due, None)
+}
+
+/// Runs the callbacks of the timers as they become due.
+fn timer_task() {
+    loop {
+        let generation = TIMERS.lock().generation;
+        let (due, next) = take_due(monotonic_time());
+        // the lock is not held by the callbacks, which may add timers
+        for callback in due {
+            callback();
+        }
+        let changed = || TIMERS.lock().generation != generation;
+        match next {
+            #[cfg(feature = "irq")]
+            Some(deadline) => {
+                let timeout = deadline.saturating_sub(monotonic_time());
+                TIMER_WQ.wait_timeout_until(timeout.min(MAX_WAIT), changed);
+            }
+            // without timer interrupts, poll between other tasks
+            #[cfg(not(feature = "irq"))]
+            Some(_) => axtask::yield_now(),
+            None => TIMER_WQ.wait_until(changed),
+        }
+    }
+}
+
+pub fn ax_timer_after(dur: Duration, callback: fn()) -> AxTimerHandle {
+    // clamped rather than overflowing, e.g. for `Duration::MAX` meaning never
+    let deadline = monotonic_time().checked_add(dur).unwrap_or(Duration::MAX);
+    let id = {
+        let mut timers = TIMERS.lock();
+        let id = timers.next_id;
+        timers.next_id += 1;
+        timers.generation += 1;
+        timers.deadlines.push(Reverse((deadline, id)));
+        timers.callbacks.insert(id, callback);
+        id
+    };
+    if !TIMER_TASK_STARTED.swap(true, Ordering::AcqRel) {
+        axtask::spawn(timer_task);
+    }
+    TIMER_WQ.notify_one(true);
+    AxTimerHandle(id)
+}
+
+pub fn ax_timer_cancel(handle: AxTimerHandle) -> bool {
+    TIMERS.lock().callbacks.remove(&handle.0).is_some()
+}
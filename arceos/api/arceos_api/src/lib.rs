@@ -44,8 +44,41 @@ pub mod time {
     define_api! {
         /// Returns the time elapsed since system boot.
         pub fn ax_monotonic_time() -> AxTimeValue;
+        /// Returns the time elapsed since system boot, in nanoseconds.
+        pub fn ax_monotonic_time_nanos() -> u64;
         /// Returns the time elapsed since epoch, also known as realtime.
         pub fn ax_wall_time() -> AxTimeValue;
+        /// Returns the resolution of the monotonic clock, i.e. the duration
+        /// of one timer tick.
+        pub fn ax_clock_getres() -> core::time::Duration;
+        /// Busy-waits for at least `micros` microseconds without yielding the
+        /// CPU.
+        ///
+        /// Meant for short hardware timing requirements in drivers; use
+        /// [`ax_sleep_until`](crate::task::ax_sleep_until) to wait for longer.
+        pub fn ax_delay_us(micros: u64);
+        /// Busy-waits for at least `nanos` nanoseconds without yielding the
+        /// CPU, with the resolution given by [`ax_clock_getres`].
+        pub fn ax_delay_ns(nanos: u64);
+    }
+
+    define_api_type! {
+        @cfg "multitask";
+        pub type AxTimerHandle;
+    }
+
+    define_api! {
+        @cfg "multitask";
+
+        /// Calls `callback` once, when `dur` has elapsed.
+        ///
+        /// Callbacks run one after the other in a dedicated task, at or
+        /// after their deadline. Without the `irq` feature, that task polls
+        /// the deadlines whenever it is scheduled.
+        pub fn ax_timer_after(dur: core::time::Duration, callback: fn()) -> AxTimerHandle;
+        /// Cancels a timer, returns false if its callback already ran (or
+        /// is running).
+        pub fn ax_timer_cancel(handle: AxTimerHandle) -> bool;
     }
 }
 
@@ -75,6 +108,56 @@ pub mod mem {
         pub unsafe fn ax_dealloc(ptr: NonNull<u8>, layout: Layout);
     }
 
+    define_api_type! {
+        @cfg "paging";
+        pub type AxMapFlags;
+        pub type AxShmHandle;
+    }
+
+    define_api! {
+        @cfg "paging";
+        /// Maps a new memory region of at least `size` bytes with the given
+        /// `flags`, and returns its base virtual address.
+        ///
+        /// The region is backed by newly allocated, zeroed pages and placed in
+        /// the kernel address space, which all tasks share. Returns
+        /// [`InvalidInput`](crate::AxError::InvalidInput) if `size` is zero,
+        /// or too large to be rounded up to whole pages.
+        pub fn ax_mem_map(size: usize, flags: AxMapFlags) -> crate::AxResult<usize>;
+        /// Unmaps the memory region at `addr` previously mapped by
+        /// [`ax_mem_map`], freeing its pages.
+        ///
+        /// `size` is the one given to [`ax_mem_map`]. Returns
+        /// [`InvalidInput`](crate::AxError::InvalidInput) if `addr` and
+        /// `size` are not those of a region it mapped and not unmapped yet.
+        pub fn ax_mem_unmap(addr: usize, size: usize) -> crate::AxResult;
+        /// Creates a shared memory region of at least `size` bytes, backed by
+        /// zeroed, physically contiguous pages.
+        ///
+        /// The handle can be cloned and passed to other tasks. The pages are
+        /// freed, and any remaining mappings removed, when the last clone is
+        /// dropped.
+        ///
+        /// # Panics
+        ///
+        /// Panics if there is not enough memory for the region.
+        pub fn ax_shm_create(size: usize) -> AxShmHandle;
+        /// Maps the shared memory region of `handle` for the current task,
+        /// readable and writable, and returns its base virtual address.
+        ///
+        /// Each task mapping the region gets its own mapping of the same
+        /// physical pages, possibly at a different address from the other
+        /// tasks. Mapping it again from the same task returns the existing
+        /// address.
+        pub fn ax_shm_map(handle: &AxShmHandle) -> crate::AxResult<usize>;
+        /// Removes the mapping of the shared memory region of `handle` made
+        /// by [`ax_shm_map`] for the current task.
+        ///
+        /// Returns [`BadState`](crate::AxError::BadState) if the current task
+        /// has not mapped it.
+        pub fn ax_shm_unmap(handle: &AxShmHandle) -> crate::AxResult;
+    }
+
     define_api_type! {
         @cfg "dma";
         pub type DMAInfo;
@@ -108,17 +191,164 @@ pub mod stdio {
     define_api! {
         /// Reads a byte from the console, or returns [`None`] if no input is available.
         pub fn ax_console_read_byte() -> Option<u8>;
+        /// Reads bytes from the console into `buf` until `delim` is read or
+        /// `buf` is full, returns the number of bytes read.
+        ///
+        /// The delimiter is stored in `buf` too. Blocks (yielding the CPU)
+        /// while no input is available. As with [`ax_console_read_byte`],
+        /// `\r` is read as `\n`.
+        pub fn ax_console_read_until(delim: u8, buf: &mut [u8]) -> crate::AxResult<usize>;
         /// Writes a slice of bytes to the console, returns the number of bytes written.
         pub fn ax_console_write_bytes(buf: &[u8]) -> crate::AxResult<usize>;
         /// Writes a formatted string to the console.
         pub fn ax_console_write_fmt(args: fmt::Arguments) -> fmt::Result;
+        /// Switches the console between raw and cooked mode.
+        ///
+        /// In raw mode (the default), [`ax_console_read_byte`] returns each
+        /// keystroke as soon as it arrives, without echo. In cooked mode, input
+        /// is echoed and line-edited (backspace is honored), and bytes are only
+        /// delivered once a whole line has been entered.
+        pub fn ax_console_set_raw(enabled: bool);
+        /// Returns whether the console is in raw mode.
+        pub fn ax_console_is_raw() -> bool;
+    }
+
+    /// Size of the internal buffer of [`BufferedConsole`].
+    pub const CONSOLE_BUF_SIZE: usize = 256;
+
+    /// A console writer that batches output into fewer
+    /// [`ax_console_write_bytes`] calls.
+    ///
+    /// Buffered bytes are written out on a newline, when the buffer is full,
+    /// on [`flush`](Self::flush), or when the writer is dropped.
+    pub struct BufferedConsole {
+        buf: [u8; CONSOLE_BUF_SIZE],
+        len: usize,
+    }
+
+    impl BufferedConsole {
+        /// Creates an empty buffered writer.
+        pub const fn new() -> Self {
+            Self {
+                buf: [0; CONSOLE_BUF_SIZE],
+                len: 0,
+            }
+        }
+
+        /// Returns the number of bytes waiting to be written.
+        pub fn buffered(&self) -> usize {
+            self.len
+        }
+
+        /// Writes all buffered bytes to the console.
+        pub fn flush(&mut self) -> crate::AxResult {
+            let mut written = 0;
+            while written < self.len {
+                written += ax_console_write_bytes(&self.buf[written..self.len])?;
+            }
+            self.len = 0;
+            Ok(())
+        }
+    }
+
+    impl Default for BufferedConsole {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl fmt::Write for BufferedConsole {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            for &c in s.as_bytes() {
+                self.buf[self.len] = c;
+                self.len += 1;
+                if c == b'\n' || self.len == CONSOLE_BUF_SIZE {
+                    self.flush().map_err(|_| fmt::Error)?;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    impl Drop for BufferedConsole {
+        fn drop(&mut self) {
+            let _ = self.flush();
+        }
     }
 }
+
+/// Leveled logging for applications.
+pub mod log {
+    use core::fmt;
+
+    /// Severity of a log message, from the most to the least severe.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+    #[repr(u8)]
+    pub enum AxLogLevel {
+        /// Logging disabled, only meaningful as a maximum level.
+        Off = 0,
+        /// Errors.
+        Error,
+        /// Warnings.
+        Warn,
+        /// Informational messages.
+        Info,
+        /// Debugging messages.
+        Debug,
+        /// Very verbose tracing messages.
+        Trace,
+    }
+
+    impl AxLogLevel {
+        /// Returns the name of the level, e.g. `"WARN"`.
+        pub const fn as_str(self) -> &'static str {
+            match self {
+                Self::Off => "OFF",
+                Self::Error => "ERROR",
+                Self::Warn => "WARN",
+                Self::Info => "INFO",
+                Self::Debug => "DEBUG",
+                Self::Trace => "TRACE",
+            }
+        }
+    }
+
+    define_api! {
+        /// Prints a message prefixed with its level to the console, unless
+        /// `level` is more verbose than the one set by [`ax_log_set_level`].
+        pub fn ax_log(level: AxLogLevel, args: fmt::Arguments);
+        /// Sets the most verbose level printed by [`ax_log`] (`Info` by
+        /// default). This is independent of the kernel's own log level.
+        pub fn ax_log_set_level(level: AxLogLevel);
+        /// Returns the most verbose level printed by [`ax_log`].
+        pub fn ax_log_get_level() -> AxLogLevel;
+    }
+}
+
 /// MISC
 pub mod misc {
+    /// A snapshot of the system state, see [`ax_sysinfo`].
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct AxSysInfo {
+        /// Time elapsed since boot.
+        pub uptime: core::time::Duration,
+        /// Bytes managed by the global allocator, 0 without the `alloc`
+        /// feature.
+        pub total_mem: usize,
+        /// Bytes of free pages in the global allocator, at most `total_mem`.
+        pub free_mem: usize,
+        /// Number of tasks not exited yet, including the idle tasks; 1
+        /// without the `multitask` feature.
+        pub task_count: usize,
+        /// Number of CPUs.
+        pub cpu_count: usize,
+    }
+
     define_api! {
         /// Get a random u128
         pub fn ax_random() -> u128;
+        /// Gathers uptime, memory, task and CPU counts in one snapshot.
+        pub fn ax_sysinfo() -> AxSysInfo;
     }
 }
 
@@ -136,6 +366,16 @@ pub mod task {
         /// If the feature `multitask` is not enabled, it uses busy-wait instead
         pub fn ax_sleep_until(deadline: crate::time::AxTimeValue);
 
+        /// Current task is going to sleep until
+        /// [`ax_monotonic_time_nanos`](crate::time::ax_monotonic_time_nanos)
+        /// reaches `abs_monotonic_nanos`, and returns at once if it already
+        /// has.
+        ///
+        /// Unlike sleeping for a duration computed from the current time, a
+        /// periodic loop advancing an absolute deadline by its period does
+        /// not drift by the time spent between wakeups.
+        pub fn ax_sleep_until_nanos(abs_monotonic_nanos: u64);
+
         /// Current task gives up the CPU time voluntarily, and switches to another
         /// ready task.
         ///
@@ -144,6 +384,15 @@ pub mod task {
 
         /// Exits the current task with the given exit code.
         pub fn ax_exit(exit_code: i32) -> !;
+
+        /// Restricts the current task to the CPUs set in `cpu_mask`.
+        ///
+        /// The scheduler has no per-task affinity yet, so only a mask
+        /// allowing every CPU is accepted. Other non-empty masks return
+        /// [`AxError::Unsupported`](crate::AxError::Unsupported).
+        pub fn ax_sched_setaffinity(cpu_mask: usize) -> crate::AxResult;
+        /// Returns the bitset of CPUs the current task may run on.
+        pub fn ax_sched_getaffinity() -> usize;
     }
 
     define_api! {
@@ -160,6 +409,12 @@ pub mod task {
         /// Waits for the given task to exit, and returns its exit code (the
         /// argument of [`ax_exit`]).
         pub fn ax_wait_for_exit(task: AxTaskHandle) -> Option<i32>;
+        /// Waits for all the given tasks to exit, and returns their exit
+        /// codes in the order of `handles`, whatever order they exit in.
+        ///
+        /// The entry of the current task, which cannot wait for itself, is
+        /// [`AxError::BadState`](crate::AxError::BadState).
+        pub fn ax_join_all(handles: &[AxTaskHandle]) -> alloc::vec::Vec<crate::AxResult<i32>>;
         /// Sets the priority of the current task.
         pub fn ax_set_current_priority(prio: isize) -> crate::AxResult;
 
@@ -176,6 +431,47 @@ pub mod task {
         /// The maximum number of tasks to wake up is specified by `count`. If
         /// `count` is `u32::MAX`, it will wake up all tasks in the wait queue.
         pub fn ax_wait_queue_wake(wq: &AxWaitQueueHandle, count: u32);
+
+        /// Sets the signal handler of the current task, replacing any
+        /// previous one.
+        ///
+        /// Signals are cooperative: the handler runs in the task itself, at
+        /// its next scheduling point through this API ([`ax_yield_now`],
+        /// [`ax_sleep_until`] or [`ax_wait_queue_wait`]).
+        pub fn ax_signal_register(handler: fn(sig: u32));
+        /// Queues the signal `sig` for the given task, to be handled at its
+        /// next scheduling point.
+        ///
+        /// Returns [`AxError::BadState`](crate::AxError::BadState) if the task
+        /// has not registered a handler.
+        pub fn ax_signal_raise(task: &AxTaskHandle, sig: u32) -> crate::AxResult;
+    }
+}
+
+/// Synchronization primitives between tasks.
+pub mod sync {
+    define_api_type! {
+        @cfg "multitask";
+        pub type AxEventHandle;
+    }
+
+    define_api! {
+        @cfg "multitask";
+
+        /// Creates an event with a zero counter, like Linux's `eventfd`.
+        pub fn ax_event_create() -> AxEventHandle;
+        /// Increments the counter of the event, waking up one waiting task.
+        pub fn ax_event_signal(event: &AxEventHandle);
+        /// Blocks the current task until the counter of the event is nonzero,
+        /// then decrements it.
+        ///
+        /// Returns [`AxError::TimedOut`](crate::AxError::TimedOut) if
+        /// `timeout` elapsed first, leaving the counter unchanged. The timeout
+        /// is ignored without the `irq` feature.
+        pub fn ax_event_wait(
+            event: &AxEventHandle,
+            timeout: Option<core::time::Duration>,
+        ) -> crate::AxResult;
     }
 }
 
@@ -261,6 +557,18 @@ pub mod fs {
         pub fn ax_current_dir() -> AxResult<alloc::string::String>;
         /// Changes the current working directory to the specified path.
         pub fn ax_set_current_dir(path: &str) -> AxResult;
+
+        /// Returns the working directory of the current task.
+        ///
+        /// It is the global current directory until the task calls
+        /// [`ax_chdir`].
+        pub fn ax_getcwd() -> AxResult<alloc::string::String>;
+        /// Changes the working directory of the current task, which the other
+        /// functions of this module resolve relative paths against.
+        ///
+        /// Fails with [`NotADirectory`](crate::AxError::NotADirectory) if
+        /// `path` is not a directory, and leaves the directory unchanged.
+        pub fn ax_chdir(path: &str) -> AxResult;
     }
 }
 
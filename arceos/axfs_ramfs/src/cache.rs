@@ -0,0 +1,85 @@
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::sync::{Arc, Weak};
+use axfs_vfs::{VfsNodeOps, VfsNodeRef};
+
+/// A bounded LRU cache mapping resolved paths to nodes.
+///
+/// Entries hold weak references, so a node dropped from the tree never
+/// outlives its last strong owner because of the cache.
+pub(crate) struct LookupCache {
+    capacity: usize,
+    /// Least recently used entry first.
+    entries: VecDeque<(String, Weak<dyn VfsNodeOps>)>,
+    /// Bumped by each invalidation, so that a lookup that started before
+    /// one does not cache what it found.
+    generation: u64,
+}
+
+impl LookupCache {
+    pub const fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: VecDeque::new(),
+            generation: 0,
+        }
+    }
+
+    /// Returns the current generation, to be given to [`insert`](Self::insert)
+    /// once the lookup is done.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Returns the cached node for `path`, marking it as most recently used.
+    /// Entries whose node is gone are dropped.
+    pub fn get(&mut self, path: &str) -> Option<VfsNodeRef> {
+        let idx = self.entries.iter().position(|(p, _)| p == path)?;
+        let entry = self.entries.remove(idx)?;
+        let node = entry.1.upgrade()?;
+        self.entries.push_back(entry);
+        Some(node)
+    }
+
+    /// Caches `node` for `path`, found by a lookup that started at
+    /// `generation`, unless the cache was invalidated since.
+    pub fn insert(&mut self, path: String, node: &VfsNodeRef, generation: u64) {
+        if self.capacity == 0 || generation != self.generation {
+            return;
+        }
+        self.entries.retain(|(p, _)| *p != path);
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((path, Arc::downgrade(node)));
+    }
+
+    /// Drops `prefix` and every cached path below it.
+    pub fn invalidate(&mut self, prefix: &str) {
+        self.generation += 1;
+        self.entries.retain(|(p, _)| {
+            !(prefix.is_empty()
+                || p.strip_prefix(prefix)
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/')))
+        });
+    }
+}
+
+/// Normalizes `path` into a cache key, or returns `None` if the path cannot
+/// be cached because it contains `..` components.
+pub(crate) fn cache_key(path: &str) -> Option<String> {
+    let mut key = String::new();
+    for comp in path.split('/') {
+        match comp {
+            "" | "." => {}
+            ".." => return None,
+            _ => {
+                if !key.is_empty() {
+                    key.push('/');
+                }
+                key.push_str(comp);
+            }
+        }
+    }
+    Some(key)
+}
@@ -0,0 +1,208 @@
+use alloc::collections::BTreeMap;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use axfs_vfs::{impl_vfs_non_dir_default, VfsNodeAttr, VfsNodeOps, VfsResult};
+use spin::{Mutex, RwLock};
+
+use crate::file::{fnv1a, FNV_OFFSET};
+
+/// Size of the chunks a [`ChunkedFileNode`] is split into.
+pub const CHUNK_SIZE: usize = 4096;
+
+/// A content-addressed store of file chunks, shared by the files of a
+/// [`RamFileSystem`](crate::RamFileSystem) created with
+/// [`with_dedup`](crate::RamFileSystem::with_dedup).
+///
+/// Identical chunks are stored once and referenced by every file holding
+/// them. The store only keeps weak references: a chunk is freed when no
+/// file refers to it anymore.
+pub struct ChunkStore {
+    chunks: Mutex<BTreeMap<u64, Vec<Weak<[u8]>>>>,
+}
+
+impl ChunkStore {
+    /// Creates an empty store.
+    pub const fn new() -> Self {
+        Self {
+            chunks: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Returns the shared chunk holding `data`, storing it if needed.
+    fn intern(&self, data: &[u8]) -> Arc<[u8]> {
+        let mut chunks = self.chunks.lock();
+        let same = chunks.entry(fnv1a(FNV_OFFSET, data)).or_default();
+        same.retain(|chunk| chunk.strong_count() > 0);
+        if let Some(chunk) = same
+            .iter()
+            .filter_map(Weak::upgrade)
+            .find(|chunk| **chunk == *data)
+        {
+            return chunk;
+        }
+        let chunk: Arc<[u8]> = data.into();
+        same.push(Arc::downgrade(&chunk));
+        chunk
+    }
+
+    /// Returns the number of distinct chunks in use, forgetting the freed
+    /// ones.
+    pub fn len(&self) -> usize {
+        let mut chunks = self.chunks.lock();
+        chunks.retain(|_, same| {
+            same.retain(|chunk| chunk.strong_count() > 0);
+            !same.is_empty()
+        });
+        chunks.values().map(Vec::len).sum()
+    }
+
+    /// Returns whether no chunk is in use.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of references to the chunk holding `data`, 0 if
+    /// it is not stored. A chunk repeated in a file counts once per copy.
+    pub fn share_count(&self, data: &[u8]) -> usize {
+        let chunks = self.chunks.lock();
+        let Some(same) = chunks.get(&fnv1a(FNV_OFFSET, data)) else {
+            return 0;
+        };
+        same.iter()
+            .filter_map(Weak::upgrade)
+            .find(|chunk| **chunk == *data)
+            .map_or(0, |chunk| Arc::strong_count(&chunk) - 1)
+    }
+}
+
+impl Default for ChunkStore {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A file node whose contents are split into [`CHUNK_SIZE`] chunks shared
+/// through a [`ChunkStore`].
+///
+/// Writes replace the chunks they touch with the stored copy of their new
+/// contents, so files with identical data share memory. The last chunk is
+/// zero-padded past the end of the file.
+///
+/// It implements [`axfs_vfs::VfsNodeOps`].
+pub struct ChunkedFileNode {
+    store: Arc<ChunkStore>,
+    content: RwLock<Chunks>,
+}
+
+struct Chunks {
+    chunks: Vec<Arc<[u8]>>,
+    size: usize,
+}
+
+impl ChunkedFileNode {
+    pub(super) fn new(store: Arc<ChunkStore>) -> Self {
+        Self {
+            store,
+            content: RwLock::new(Chunks {
+                chunks: Vec::new(),
+                size: 0,
+            }),
+        }
+    }
+
+    /// Returns the number of chunks of the file.
+    pub fn chunk_count(&self) -> usize {
+        self.content.read().chunks.len()
+    }
+
+    /// Returns a 64-bit FNV-1a hash of the contents, the same as
+    /// [`FileNode::content_hash`](crate::FileNode::content_hash) for the
+    /// same bytes.
+    pub fn content_hash(&self) -> u64 {
+        let content = self.content.read();
+        let mut left = content.size;
+        content.chunks.iter().fold(FNV_OFFSET, |hash, chunk| {
+            let len = left.min(chunk.len());
+            left -= len;
+            fnv1a(hash, &chunk[..len])
+        })
+    }
+
+    /// Resizes the file to `size` bytes, zero-filling any new bytes.
+    fn resize(&self, content: &mut Chunks, size: usize) {
+        let tail = size % CHUNK_SIZE;
+        if size < content.size && tail > 0 {
+            // clear the tail of the new last chunk
+            let last = size / CHUNK_SIZE;
+            let mut chunk = content.chunks[last].to_vec();
+            chunk[tail..].fill(0);
+            content.chunks[last] = self.store.intern(&chunk);
+        }
+        let count = size.div_ceil(CHUNK_SIZE);
+        if count > content.chunks.len() {
+            let zero = self.store.intern(&[0; CHUNK_SIZE]);
+            content.chunks.resize(count, zero);
+        } else {
+            content.chunks.truncate(count);
+        }
+        content.size = size;
+    }
+}
+
+impl VfsNodeOps for ChunkedFileNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let content = self.content.read();
+        Ok(VfsNodeAttr::new_file(
+            content.size as _,
+            content.chunks.len() as _,
+        ))
+    }
+
+    fn truncate(&self, size: u64) -> VfsResult {
+        self.resize(&mut self.content.write(), size as _);
+        Ok(())
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let content = self.content.read();
+        let start = content.size.min(offset as usize);
+        let end = content.size.min(offset as usize + buf.len());
+        let mut pos = start;
+        while pos < end {
+            let chunk = &content.chunks[pos / CHUNK_SIZE];
+            let from = pos % CHUNK_SIZE;
+            let len = (CHUNK_SIZE - from).min(end - pos);
+            buf[pos - start..pos - start + len].copy_from_slice(&chunk[from..from + len]);
+            pos += len;
+        }
+        Ok(end - start)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let mut content = self.content.write();
+        let offset = offset as usize;
+        let end = offset + buf.len();
+        if end > content.size {
+            self.resize(&mut content, end);
+        }
+        let mut pos = offset;
+        while pos < end {
+            let idx = pos / CHUNK_SIZE;
+            let from = pos % CHUNK_SIZE;
+            let len = (CHUNK_SIZE - from).min(end - pos);
+            let src = &buf[pos - offset..pos - offset + len];
+            let chunk = if len == CHUNK_SIZE {
+                self.store.intern(src)
+            } else {
+                let mut chunk = content.chunks[idx].to_vec();
+                chunk[from..from + len].copy_from_slice(src);
+                self.store.intern(&chunk)
+            };
+            content.chunks[idx] = chunk;
+            pos += len;
+        }
+        Ok(buf.len())
+    }
+
+    impl_vfs_non_dir_default! {}
+}
@@ -0,0 +1,90 @@
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use axfs_vfs::{impl_vfs_non_dir_default, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeType};
+use axfs_vfs::{VfsError, VfsResult};
+use spin::RwLock;
+
+/// Backend of a device node, performing the actual I/O.
+pub trait DeviceHandler: Send + Sync {
+    /// Reads data from the device at the given offset.
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize>;
+    /// Writes data to the device at the given offset.
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize>;
+}
+
+/// Handlers registered for each `(major, minor)` device number.
+static DEVICE_HANDLERS: RwLock<BTreeMap<(u32, u32), Arc<dyn DeviceHandler>>> =
+    RwLock::new(BTreeMap::new());
+
+/// Registers the handler serving I/O on device nodes with the given numbers.
+///
+/// Returns the previously registered handler, if any.
+pub fn register_device(
+    major: u32,
+    minor: u32,
+    handler: Arc<dyn DeviceHandler>,
+) -> Option<Arc<dyn DeviceHandler>> {
+    DEVICE_HANDLERS.write().insert((major, minor), handler)
+}
+
+/// Unregisters the handler of the given device numbers.
+pub fn unregister_device(major: u32, minor: u32) -> Option<Arc<dyn DeviceHandler>> {
+    DEVICE_HANDLERS.write().remove(&(major, minor))
+}
+
+/// The character or block device node in the RAM filesystem.
+///
+/// Reads and writes are routed to the [`DeviceHandler`] registered for its
+/// `(major, minor)` numbers, or fail with [`VfsError::Unsupported`] if there
+/// is none.
+///
+/// It implements [`axfs_vfs::VfsNodeOps`].
+pub struct DeviceNode {
+    ty: VfsNodeType,
+    major: u32,
+    minor: u32,
+}
+
+impl DeviceNode {
+    pub(super) const fn new(ty: VfsNodeType, major: u32, minor: u32) -> Self {
+        Self { ty, major, minor }
+    }
+
+    /// Returns the major device number.
+    pub fn major(&self) -> u32 {
+        self.major
+    }
+
+    /// Returns the minor device number.
+    pub fn minor(&self) -> u32 {
+        self.minor
+    }
+
+    fn handler(&self) -> VfsResult<Arc<dyn DeviceHandler>> {
+        DEVICE_HANDLERS
+            .read()
+            .get(&(self.major, self.minor))
+            .cloned()
+            .ok_or(VfsError::Unsupported)
+    }
+}
+
+impl VfsNodeOps for DeviceNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new(VfsNodePerm::default_file(), self.ty, 0, 0))
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        self.handler()?.read_at(offset, buf)
+    }
+
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        self.handler()?.write_at(offset, buf)
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Ok(())
+    }
+
+    impl_vfs_non_dir_default! {}
+}
@@ -1,14 +1,39 @@
 use crate::alloc::string::ToString;
+use crate::chunk::{ChunkStore, ChunkedFileNode};
+use crate::device::DeviceNode;
 use crate::dir;
-use crate::file::FileNode;
+use crate::fifo::FifoNode;
+use crate::file::{fnv1a, FileNode, ReadOnlyFile, FNV_OFFSET};
+use crate::path::PathComponents;
+use crate::socket::SocketNode;
+use crate::symlink::SymlinkNode;
+use crate::time::{NodeTimes, Times};
+use crate::watch::{self, Watch, WatchEventKind};
 use alloc::collections::BTreeMap;
 use alloc::format;
 use alloc::sync::{Arc, Weak};
 use alloc::{string::String, vec::Vec};
 use axfs_vfs::{VfsDirEntry, VfsNodeAttr, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps};
-use axfs_vfs::{VfsError, VfsResult};
+use axfs_vfs::{VfsError, VfsNodePerm, VfsResult};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use core::time::Duration;
 use log::debug;
-use spin::RwLock;
+use spin::{RwLock, RwLockWriteGuard};
+
+/// Maximum number of symbolic links followed while resolving one path, like
+/// Linux's limit before `ELOOP`.
+pub const MAX_SYMLINK_DEPTH: usize = 40;
+
+/// Maximum length in bytes of an entry name, like Linux's `NAME_MAX`.
+pub const MAX_NAME_LEN: usize = 255;
+
+/// Order in which a directory lists its entries, see [`DirNode::sort_mode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum SortMode {
+    /// Ascending byte order of the names.
+    Name,
+}
 
 /// The directory node in the RAM filesystem.
 ///
@@ -18,6 +43,15 @@ pub struct DirNode {
     this: Weak<DirNode>,
     parent: RwLock<Weak<dyn VfsNodeOps>>,
     children: RwLock<BTreeMap<String, VfsNodeRef>>,
+    temp_seq: AtomicU64,
+    /// Maximum number of bytes stored in files of this subtree.
+    quota: RwLock<Option<u64>>,
+    watches: RwLock<Vec<Weak<Watch>>>,
+    times: Times,
+    /// Permission bits, `0o755` by default.
+    mode: RwLock<u16>,
+    /// Whether names are matched ignoring ASCII case.
+    case_insensitive: AtomicBool,
 }
 impl DirNode {
     pub(super) fn new(parent: Option<Weak<dyn VfsNodeOps>>) -> Arc<Self> {
@@ -25,6 +59,12 @@ impl DirNode {
             this: this.clone(),
             parent: RwLock::new(parent.unwrap_or_else(|| Weak::<Self>::new())),
             children: RwLock::new(BTreeMap::new()),
+            temp_seq: AtomicU64::new(0),
+            quota: RwLock::new(None),
+            watches: RwLock::new(Vec::new()),
+            times: Times::new(),
+            mode: RwLock::new(0o755),
+            case_insensitive: AtomicBool::new(false),
         })
     }
 
@@ -32,6 +72,43 @@ impl DirNode {
         *self.parent.write() = parent.map_or(Weak::<Self>::new() as _, Arc::downgrade);
     }
 
+    /// Returns the access, modification and change times of this directory.
+    ///
+    /// Its modification time is updated whenever an entry is created,
+    /// removed or renamed in it, and its access time by `read_dir` as set by
+    /// [`set_atime_mode`](crate::set_atime_mode).
+    pub fn times(&self) -> NodeTimes {
+        self.times.get()
+    }
+
+    /// Sets the access and modification times of this directory, leaving
+    /// out the `None` ones. The change time is set to the current time.
+    pub fn set_times(&self, atime: Option<Duration>, mtime: Option<Duration>) {
+        self.times.set(atime, mtime);
+    }
+
+    /// Returns the permission bits of this directory.
+    pub fn mode(&self) -> u16 {
+        *self.mode.read()
+    }
+
+    /// Sets the permission bits of this directory, like `chmod`. Bits above
+    /// `0o777` are ignored.
+    pub fn set_mode(&self, mode: u16) {
+        *self.mode.write() = mode & 0o777;
+        self.times.changed();
+    }
+
+    /// Returns the order of the entries listed by `read_dir`,
+    /// [`read_dir_full`](Self::read_dir_full) and
+    /// [`get_entries`](Self::get_entries), after `.` and `..`.
+    ///
+    /// It depends neither on the insertion order nor on any hash secret, so
+    /// identical trees always list identically.
+    pub fn sort_mode(&self) -> SortMode {
+        SortMode::Name
+    }
+
     /// Returns a string list of all entries in this directory.
     pub fn get_entries(&self) -> Vec<String> {
         self.children.read().keys().cloned().collect()
@@ -39,39 +116,812 @@ impl DirNode {
 
     /// Checks whether a node with the given name exists in this directory.
     pub fn exist(&self, name: &str) -> bool {
-        self.children.read().contains_key(name)
+        self.entry_name(&self.children.read(), name).is_some()
+    }
+
+    /// Makes names in this directory match ignoring ASCII case, like on FAT,
+    /// or match exactly again, which is the default.
+    ///
+    /// Entries keep the case they were created with, as listed by
+    /// `read_dir`, but are found, replaced, removed and renamed through any
+    /// spelling, and creating a name that only differs by case from an
+    /// existing one fails with [`VfsError::AlreadyExists`]. Subdirectories
+    /// created afterwards inherit the setting. Entries already only
+    /// differing by case stay, and the first one in name order wins.
+    pub fn set_case_insensitive(&self, case_insensitive: bool) {
+        self.case_insensitive
+            .store(case_insensitive, Ordering::Relaxed);
+    }
+
+    /// Returns whether names in this directory match ignoring ASCII case.
+    pub fn is_case_insensitive(&self) -> bool {
+        self.case_insensitive.load(Ordering::Relaxed)
+    }
+
+    /// Returns the stored name of the entry `name` refers to in `children`,
+    /// the entries of this directory.
+    fn entry_name<'a>(
+        &self,
+        children: &'a BTreeMap<String, VfsNodeRef>,
+        name: &str,
+    ) -> Option<&'a String> {
+        if self.is_case_insensitive() {
+            children.keys().find(|key| key.eq_ignore_ascii_case(name))
+        } else {
+            children.get_key_value(name).map(|(key, _)| key)
+        }
+    }
+
+    /// Returns the entry `name` refers to in this directory.
+    fn child(&self, name: &str) -> Option<VfsNodeRef> {
+        let children = self.children.read();
+        let name = self.entry_name(&children, name)?;
+        children.get(name).cloned()
+    }
+
+    /// Adds `node` under `name` to `children`, the locked entries of this
+    /// directory, and counts the new link.
+    ///
+    /// All new entries are added through here, so that their names are
+    /// checked as in [`create_node`](Self::create_node).
+    fn insert_child(
+        &self,
+        children: &mut BTreeMap<String, VfsNodeRef>,
+        name: &str,
+        node: VfsNodeRef,
+    ) -> VfsResult {
+        if !valid_name(name) {
+            return Err(VfsError::InvalidInput);
+        }
+        if self.entry_name(children, name).is_some() {
+            return Err(VfsError::AlreadyExists);
+        }
+        link_added(&node);
+        children.insert(name.into(), node);
+        Ok(())
     }
 
     /// Creates a new node with the given name and type in this directory.
+    ///
+    /// Fails with [`VfsError::InvalidInput`] if `name` is empty, `.` or `..`,
+    /// longer than [`MAX_NAME_LEN`] bytes, or contains `/` or NUL.
     pub fn create_node(&self, name: &str, ty: VfsNodeType) -> VfsResult {
-        if self.exist(name) {
-            log::error!("AlreadyExists {}", name);
-            return Err(VfsError::AlreadyExists);
+        self.check_quota(1)?;
+        if matches!(ty, VfsNodeType::CharDevice | VfsNodeType::BlockDevice) {
+            return self.create_device_node(name, ty, 0, 0);
         }
-        let node: VfsNodeRef = match ty {
-            VfsNodeType::File => Arc::new(FileNode::new()),
-            VfsNodeType::Dir => Self::new(Some(self.this.clone())),
-            _ => return Err(VfsError::Unsupported),
-        };
+        let node = self.new_node(ty)?;
         debug!("create_node: name = '{}', type = {:?}", name, ty);
         debug!("create_node: created node ptr = {:p}", Arc::as_ptr(&node));
-        self.children.write().insert(name.into(), node);
+        self.insert_child(&mut self.children.write(), name, node)?;
+        self.notify(WatchEventKind::Create, name);
+
+        Ok(())
+    }
+
+    /// Returns the node named `name` in this directory, creating it with
+    /// type `ty` first if there is none, as one atomic step.
+    ///
+    /// Fails with [`VfsError::AlreadyExists`] if the existing node is not of
+    /// type `ty`. Names are checked as in [`create_node`](Self::create_node).
+    pub fn get_or_create(&self, name: &str, ty: VfsNodeType) -> VfsResult<VfsNodeRef> {
+        // only matters if the node is created, but cannot be checked under
+        // the write lock, as it reads the children
+        let quota = self.check_quota(1);
+        let mut children = self.children.write();
+        if let Some(name) = self.entry_name(&children, name) {
+            let node = children[name].clone();
+            if node.get_attr()?.file_type() != ty {
+                return Err(VfsError::AlreadyExists);
+            }
+            return Ok(node);
+        }
+        quota?;
+        let node = self.new_node(ty)?;
+        self.insert_child(&mut children, name, node.clone())?;
+        drop(children);
+        self.notify(WatchEventKind::Create, name);
+        Ok(node)
+    }
+
+    /// Makes a new, empty node of type `ty` to be added to this directory.
+    fn new_node(&self, ty: VfsNodeType) -> VfsResult<VfsNodeRef> {
+        Ok(match ty {
+            VfsNodeType::File => self.new_file(FileNode::new()),
+            VfsNodeType::Dir => {
+                let dir = Self::new(Some(self.this.clone()));
+                dir.set_case_insensitive(self.is_case_insensitive());
+                dir
+            }
+            VfsNodeType::Fifo => Arc::new(FifoNode::new()),
+            // an empty target, to be set with `SymlinkNode::set_link`
+            VfsNodeType::SymLink => Arc::new(SymlinkNode::new("")),
+            VfsNodeType::CharDevice | VfsNodeType::BlockDevice => {
+                Arc::new(DeviceNode::new(ty, 0, 0))
+            }
+            // unbound, see `SocketNode::set_endpoint`
+            VfsNodeType::Socket => Arc::new(SocketNode::new()),
+        })
+    }
+
+    /// Creates a new character or block device node with the given device
+    /// numbers in this directory.
+    pub fn create_device_node(
+        &self,
+        name: &str,
+        ty: VfsNodeType,
+        major: u32,
+        minor: u32,
+    ) -> VfsResult {
+        if !matches!(ty, VfsNodeType::CharDevice | VfsNodeType::BlockDevice) {
+            return Err(VfsError::InvalidInput);
+        }
+        self.check_quota(1)?;
+        debug!(
+            "create_device_node: name = '{}', type = {:?}, dev = {}:{}",
+            name, ty, major, minor
+        );
+        let node = Arc::new(DeviceNode::new(ty, major, minor));
+        self.insert_child(&mut self.children.write(), name, node)?;
+        self.notify(WatchEventKind::Create, name);
+        Ok(())
+    }
+
+    /// Creates a symbolic link named `name` to `target` in this directory.
+    pub fn create_symlink(&self, name: &str, target: &str) -> VfsResult {
+        self.check_quota(1)?;
+        let node = Arc::new(SymlinkNode::new(target));
+        self.insert_child(&mut self.children.write(), name, node)?;
+        self.notify(WatchEventKind::Create, name);
+        Ok(())
+    }
+
+    /// Creates an empty file named `name` in this directory, whose contents
+    /// are deduplicated through `store`.
+    ///
+    /// Its contents are not accounted for by quotas.
+    pub fn create_chunked_file(&self, name: &str, store: &Arc<ChunkStore>) -> VfsResult {
+        self.check_quota(1)?;
+        let node = Arc::new(ChunkedFileNode::new(store.clone()));
+        self.insert_child(&mut self.children.write(), name, node)?;
+        self.notify(WatchEventKind::Create, name);
+        Ok(())
+    }
+
+    /// Adds `node`, which may live anywhere in the filesystem, under `name`
+    /// in this directory, like a hard link.
+    ///
+    /// Both names then refer to the same node, so writes through one are
+    /// seen through the other. Directories cannot be linked, as that could
+    /// make cycles, and fail with [`VfsError::IsADirectory`]. Names are
+    /// checked as in [`create_node`](Self::create_node).
+    pub fn link(&self, name: &str, node: VfsNodeRef) -> VfsResult {
+        if node.as_any().is::<DirNode>() {
+            return Err(VfsError::IsADirectory);
+        }
+        self.check_quota(1)?;
+        self.insert_child(&mut self.children.write(), name, node)?;
+        self.notify(WatchEventKind::Create, name);
+        Ok(())
+    }
+
+    /// Makes `target` visible under `name` in this directory, read-only.
+    ///
+    /// Reads through the new name see the live contents of `target`, while
+    /// writes and truncations through it fail with
+    /// [`VfsError::PermissionDenied`].
+    pub fn bind_file_ro(&self, name: &str, target: Arc<FileNode>) -> VfsResult {
+        let node = Arc::new(ReadOnlyFile::new(target));
+        self.insert_child(&mut self.children.write(), name, node)?;
+        self.notify(WatchEventKind::Create, name);
+        Ok(())
+    }
+
+    /// Looks up `path` relative to this directory, following symbolic links
+    /// in every component including the last one.
+    ///
+    /// Absolute link targets are resolved from the root of this filesystem,
+    /// relative ones from the directory holding the link. Fails with
+    /// [`VfsError::InvalidInput`] after following [`MAX_SYMLINK_DEPTH`]
+    /// links, which is how link cycles are reported.
+    pub fn lookup_follow(&self, path: &str) -> VfsResult<VfsNodeRef> {
+        let this = self.this.upgrade().ok_or(VfsError::NotFound)?;
+        let mut depth = 0;
+        resolve(this, path, &mut depth)
+    }
+
+    /// Like [`lookup_follow`](Self::lookup_follow), but also returns the
+    /// number of symbolic links followed to reach the node, including those
+    /// met while resolving link targets.
+    pub fn resolve(&self, path: &str) -> VfsResult<(VfsNodeRef, usize)> {
+        let this = self.this.upgrade().ok_or(VfsError::NotFound)?;
+        let mut depth = 0;
+        let node = resolve(this, path, &mut depth)?;
+        Ok((node, depth))
+    }
+
+    /// Returns the target of the symbolic link at `path`, without following
+    /// it.
+    ///
+    /// Like `lookup`, no link is followed on the way either. Fails with
+    /// [`VfsError::InvalidInput`] if the node is not a symbolic link.
+    pub fn readlink(&self, path: &str) -> VfsResult<String> {
+        let this = self.this.upgrade().ok_or(VfsError::NotFound)?;
+        let node = this.lookup(path)?;
+        let link = node
+            .as_any()
+            .downcast_ref::<SymlinkNode>()
+            .ok_or(VfsError::InvalidInput)?;
+        Ok(link.read_link())
+    }
+
+    /// Walks to the directory holding the last component of `path`, and
+    /// returns it with the name of that component.
+    ///
+    /// Like `lookup`, every `..` on the way is resolved in turn, so `a/..`
+    /// requires `a` to be a directory.
+    pub(crate) fn split_last<'a>(&self, path: &'a str) -> VfsResult<Split<'a>> {
+        let this = self.this.upgrade().ok_or(VfsError::NotFound)?;
+        // a trailing `.` or `..` names a directory rather than an entry in it
+        let names_dir = matches!(
+            path.trim_end_matches('/').rsplit('/').next(),
+            Some("" | "." | "..")
+        );
+        let mut comps = PathComponents::new(path);
+        let node = match walk(this, &mut comps, !names_dir)? {
+            Walked::Here(node) => node,
+            Walked::Above(node, rest) if PathComponents::new(rest).next().is_some() => {
+                return Ok(Split::Above(node, rest))
+            }
+            Walked::Above(..) => return Ok(Split::Dir),
+        };
+        let dir = node
+            .as_any()
+            .downcast_ref::<DirNode>()
+            .and_then(|dir| dir.this.upgrade())
+            .ok_or(VfsError::NotADirectory)?;
+        match comps.next() {
+            Some(name) if !names_dir => Ok(Split::Entry(dir, name)),
+            _ => Ok(Split::Dir),
+        }
+    }
+
+    /// Returns the root directory of the tree holding this directory.
+    fn root(self: Arc<Self>) -> Arc<DirNode> {
+        let mut dir = self;
+        while let Some(parent) = dir
+            .parent()
+            .and_then(|parent| parent.as_any().downcast_ref::<DirNode>()?.this.upgrade())
+        {
+            dir = parent;
+        }
+        dir
+    }
+
+    /// Replaces the file at `path` (relative to this directory) with one
+    /// holding `data`, creating it if it does not exist.
+    ///
+    /// The new contents are fully written into a temporary file next to the
+    /// target, which is then renamed over it, so readers either see the old
+    /// complete contents or the new complete contents, never a mix of both.
+    pub fn atomic_write(&self, path: &str, data: &[u8]) -> VfsResult {
+        let (dir, name) = match self.split_last(path)? {
+            Split::Entry(dir, name) => (dir, name),
+            Split::Dir => return Err(VfsError::IsADirectory),
+            Split::Above(..) => return Err(VfsError::InvalidInput),
+        };
+        let old_size = match dir.child(name) {
+            Some(old) => old.get_attr()?.size(),
+            None => 0,
+        };
+        dir.check_quota((data.len() as u64).saturating_sub(old_size))?;
+        let temp = dir.insert_temp(
+            ".atomic_write.",
+            dir.new_file(FileNode::from_vec(data.to_vec())),
+        )?;
+        dir.replace_file(&temp, name).inspect_err(|_| {
+            let _ = dir.remove_node(&temp);
+        })
+    }
+
+    /// Creates a copy of this directory subtree that shares its files.
+    ///
+    /// Only the directory structure is duplicated: files and other
+    /// non-directory nodes are shared by `Arc` with the original. Creating or
+    /// removing entries in either tree leaves the other untouched, while data
+    /// written to a shared file is seen through both.
+    ///
+    /// Directories are copied upfront rather than on their first mutation:
+    /// each one has its own `..` and its entries refer to the subdirectories
+    /// of its own tree, so no directory can be shared by both trees. This
+    /// costs one map of references per directory, the files themselves are
+    /// never copied.
+    ///
+    /// The copy has the same parent as this directory but is not inserted
+    /// into it.
+    pub fn cow_clone(&self) -> Arc<DirNode> {
+        self.clone_under(self.parent.read().clone())
+    }
+
+    fn clone_under(&self, parent: Weak<dyn VfsNodeOps>) -> Arc<DirNode> {
+        let dir = Self::new(Some(parent));
+        *dir.mode.write() = self.mode();
+        dir.set_case_insensitive(self.is_case_insensitive());
+        let this: Weak<dyn VfsNodeOps> = dir.this.clone();
+        let mut children = dir.children.write();
+        for (name, node) in self.children.read().iter() {
+            let node = match node.as_any().downcast_ref::<DirNode>() {
+                Some(subdir) => subdir.clone_under(this.clone()),
+                None => node.clone(),
+            };
+            link_added(&node);
+            children.insert(name.clone(), node);
+        }
+        drop(children);
+        dir
+    }
+
+    /// Creates a new empty file with a unique name starting with `prefix` in
+    /// this directory, and returns its name and node.
+    ///
+    /// Names are derived from a per-directory counter, so the sequence is
+    /// deterministic; names already taken are skipped.
+    ///
+    /// Fails with [`VfsError::InvalidInput`] if the names would not be valid
+    /// entry names, e.g. if `prefix` contains `/` or is too long to fit the
+    /// 16 characters added to it within [`MAX_NAME_LEN`].
+    pub fn make_temp(&self, prefix: &str) -> VfsResult<(String, Arc<FileNode>)> {
+        self.check_quota(1)?;
+        let node = self.new_file(FileNode::new());
+        let name = self.insert_temp(prefix, node.clone())?;
+        Ok((name, node))
+    }
+
+    /// Inserts `node` under a fresh name starting with `prefix`, and returns
+    /// that name.
+    fn insert_temp(&self, prefix: &str, node: VfsNodeRef) -> VfsResult<String> {
+        let mut children = self.children.write();
+        loop {
+            let seq = self.temp_seq.fetch_add(1, Ordering::Relaxed);
+            let name = format!("{}{:016x}", prefix, mix64(seq));
+            match self.insert_child(&mut children, &name, node.clone()) {
+                Ok(()) => {}
+                Err(VfsError::AlreadyExists) => continue,
+                Err(e) => return Err(e),
+            }
+            drop(children);
+            self.notify(WatchEventKind::Create, &name);
+            return Ok(name);
+        }
+    }
+
+    /// Starts watching this directory for created, removed and replaced
+    /// entries, also in all its subdirectories if `recursive` is set.
+    ///
+    /// Event paths are relative to this directory, so that a file created in
+    /// `b/c` below it is reported as `b/c/file` by a recursive watch.
+    /// Subdirectories created later are covered too. Only changes to the
+    /// namespace are reported, not writes to file contents.
+    pub fn watch(&self, recursive: bool) -> Arc<Watch> {
+        let watch = Arc::new(Watch::new(recursive));
+        self.watches.write().push(Arc::downgrade(&watch));
+        watch
+    }
 
+    /// Reports a change of the entry `name` to the watches of this directory
+    /// and the recursive watches of its ancestors, after updating the
+    /// modification time of this directory.
+    fn notify(&self, kind: WatchEventKind, name: &str) {
+        self.times.modified();
+        if !watch::any_watch() {
+            return;
+        }
+        let Some(mut dir) = self.this.upgrade() else {
+            return;
+        };
+        let mut path = String::from(name);
+        let mut direct = true;
+        loop {
+            watch::deliver(&mut dir.watches.write(), kind, &path, direct);
+            let Some(parent) = dir
+                .parent()
+                .and_then(|parent| parent.as_any().downcast_ref::<DirNode>()?.this.upgrade())
+            else {
+                break;
+            };
+            // the name of `dir` in its parent, unless it was just detached
+            let Some(dir_name) = parent.children.read().iter().find_map(|(name, node)| {
+                (Arc::as_ptr(node) as *const () == Arc::as_ptr(&dir) as *const ())
+                    .then(|| name.clone())
+            }) else {
+                break;
+            };
+            path = format!("{}/{}", dir_name, path);
+            dir = parent;
+            direct = false;
+        }
+    }
+
+    /// Limits the total size of the files in this subtree to `bytes`.
+    ///
+    /// Writes and creates anywhere below this directory that would exceed
+    /// the limit fail with [`VfsError::StorageFull`]. Data already stored is
+    /// kept even if it is over the new limit.
+    pub fn set_quota(&self, bytes: u64) {
+        *self.quota.write() = Some(bytes);
+    }
+
+    /// Removes the quota of this subtree, if any.
+    pub fn clear_quota(&self) {
+        *self.quota.write() = None;
+    }
+
+    /// Returns the number of bytes used by files in this subtree, and its
+    /// quota if one is set.
+    pub fn quota_usage(&self) -> (u64, Option<u64>) {
+        (self.subtree_bytes(), *self.quota.read())
+    }
+
+    /// Returns the number of bytes used by this subtree, like `du -sb`: the
+    /// sizes of its files, and 4096 for this directory and each directory
+    /// below, as reported by `get_attr`.
+    ///
+    /// A file linked several times in the subtree counts once. Each
+    /// directory is only locked while its entries are collected, so the
+    /// walk never holds two directory locks at once.
+    pub fn total_used_bytes(&self) -> usize {
+        self.used_bytes(&mut Vec::new())
+    }
+
+    fn used_bytes(&self, seen: &mut Vec<u64>) -> usize {
+        let children: Vec<_> = self.children.read().values().cloned().collect();
+        let mut total = 4096;
+        for node in children {
+            if let Some(dir) = node.as_any().downcast_ref::<DirNode>() {
+                total += dir.used_bytes(seen);
+                continue;
+            }
+            if nlink(&node) > 1 {
+                if seen.contains(&ino_of(&*node)) {
+                    continue;
+                }
+                seen.push(ino_of(&*node));
+            }
+            total += node.get_attr().map_or(0, |attr| attr.size() as usize);
+        }
+        total
+    }
+
+    /// Checks that `bytes` more bytes fit in the quota of this directory and
+    /// of each of its ancestors.
+    ///
+    /// Creating a node is checked as needing one byte, so that nothing can be
+    /// created in a subtree whose quota is used up.
+    pub(crate) fn check_quota(&self, bytes: u64) -> VfsResult {
+        let mut dir = self.this.upgrade();
+        while let Some(cur) = dir {
+            if let Some(limit) = *cur.quota.read() {
+                if cur.subtree_bytes() + bytes > limit {
+                    return Err(VfsError::StorageFull);
+                }
+            }
+            dir = cur
+                .parent()
+                .and_then(|parent| parent.as_any().downcast_ref::<DirNode>()?.this.upgrade());
+        }
         Ok(())
     }
 
+    /// Total size of the non-directory nodes in this subtree.
+    fn subtree_bytes(&self) -> u64 {
+        self.children
+            .read()
+            .values()
+            .map(|node| match node.as_any().downcast_ref::<DirNode>() {
+                Some(dir) => dir.subtree_bytes(),
+                None => node.get_attr().map_or(0, |attr| attr.size()),
+            })
+            .sum()
+    }
+
+    /// Returns a hash of this subtree, combining the names, types and
+    /// contents of all entries in name order.
+    ///
+    /// Two trees with the same layout and file contents hash equal. Only
+    /// file contents and symlink targets are hashed for non-directories:
+    /// FIFOs and devices contribute their name and type.
+    pub fn tree_hash(&self) -> u64 {
+        let mut hash = FNV_OFFSET;
+        for (name, node) in self.children.read().iter() {
+            let any = node.as_any();
+            let node_hash = if let Some(dir) = any.downcast_ref::<DirNode>() {
+                dir.tree_hash()
+            } else if let Some(file) = any.downcast_ref::<FileNode>() {
+                file.content_hash()
+            } else if let Some(view) = any.downcast_ref::<ReadOnlyFile>() {
+                view.file().content_hash()
+            } else if let Some(file) = any.downcast_ref::<ChunkedFileNode>() {
+                file.content_hash()
+            } else if let Some(link) = any.downcast_ref::<SymlinkNode>() {
+                fnv1a(FNV_OFFSET, link.read_link().as_bytes())
+            } else {
+                FNV_OFFSET
+            };
+            let ty = node
+                .get_attr()
+                .map_or('?', |attr| attr.file_type().as_char());
+            hash = fnv1a(hash, name.as_bytes());
+            hash = fnv1a(hash, &[0, ty as u8]);
+            hash = fnv1a(hash, &node_hash.to_le_bytes());
+        }
+        hash
+    }
+
+    /// Number of entries of this directory referring to the node at `ptr`.
+    pub(crate) fn count_refs(&self, ptr: *const ()) -> usize {
+        self.children
+            .read()
+            .values()
+            .filter(|node| Arc::as_ptr(node) as *const () == ptr)
+            .count()
+    }
+
+    /// Checks the subtree at `path` for [`check_consistency`], appending a
+    /// message for each violation to `errors`. `seen` holds the directories
+    /// visited so far, and `links` maps each file met so far to its first
+    /// path, its link count and the number of entries found referring to it.
+    ///
+    /// [`check_consistency`]: crate::RamFileSystem::check_consistency
+    pub(crate) fn check_tree(
+        &self,
+        path: &str,
+        seen: &mut Vec<u64>,
+        links: &mut BTreeMap<u64, (String, usize, usize)>,
+        errors: &mut Vec<String>,
+    ) {
+        let entries: Vec<_> = self
+            .children
+            .read()
+            .iter()
+            .map(|(name, node)| (name.clone(), node.clone()))
+            .collect();
+        for (name, node) in entries {
+            let child_path = format!("{}/{}", path.trim_end_matches('/'), name);
+            if !valid_name(&name) {
+                errors.push(format!("{}: invalid entry name", child_path));
+            }
+            if let Some(file) = node.as_any().downcast_ref::<FileNode>() {
+                let (_, _, found) = links
+                    .entry(ino_of(file))
+                    .or_insert_with(|| (child_path.clone(), file.link_count(), 0));
+                *found += 1;
+            }
+            let Some(dir) = node.as_any().downcast_ref::<DirNode>() else {
+                continue;
+            };
+            let parent_ok = dir
+                .parent()
+                .is_some_and(|parent| ino_of(&*parent) == ino_of(self));
+            if !parent_ok {
+                errors.push(format!(
+                    "{}: parent link does not point to {}",
+                    child_path, path
+                ));
+            }
+            let ino = ino_of(dir);
+            if seen.contains(&ino) {
+                // met again: either a cycle or a directory with two names
+                errors.push(format!(
+                    "{}: directory reachable more than once",
+                    child_path
+                ));
+                continue;
+            }
+            seen.push(ino);
+            dir.check_tree(&child_path, seen, links, errors);
+        }
+    }
+
+    /// Creates a file accounted to the quotas of this directory.
+    fn new_file(&self, file: FileNode) -> Arc<FileNode> {
+        file.set_dir(self.this.clone());
+        Arc::new(file)
+    }
+
+    /// Moves the entry `temp` of this directory to `name`, replacing any
+    /// existing non-directory node.
+    fn replace_file(&self, temp: &str, name: &str) -> VfsResult {
+        if !valid_name(name) {
+            return Err(VfsError::InvalidInput);
+        }
+        let mut children = self.children.write();
+        // a replaced entry keeps its name
+        let name = match self.entry_name(&children, name) {
+            Some(name) => name.clone(),
+            None => name.into(),
+        };
+        if let Some(old) = children.get(&name) {
+            if old.get_attr()?.is_dir() {
+                return Err(VfsError::IsADirectory);
+            }
+        }
+        let node = children.remove(temp).ok_or(VfsError::NotFound)?;
+        let kind = match children.insert(name.clone(), node) {
+            Some(old) => {
+                link_removed(&old);
+                WatchEventKind::Replace
+            }
+            None => WatchEventKind::Create,
+        };
+        drop(children);
+        self.notify(WatchEventKind::Remove, temp);
+        self.notify(kind, &name);
+        Ok(())
+    }
+
+    /// Swaps all the entries of this directory for `new_children` at once,
+    /// and returns the old entries sorted by name.
+    ///
+    /// Lookups see either the old or the new entries, never a mix. New
+    /// subdirectories are reparented here, and new files are accounted to
+    /// the quotas of this directory. Fails with [`VfsError::InvalidInput`],
+    /// changing nothing, if a name is invalid or repeated, or if a new entry
+    /// is this directory or one of its ancestors.
+    pub fn replace_contents(
+        &self,
+        new_children: Vec<(String, VfsNodeRef)>,
+    ) -> VfsResult<Vec<(String, VfsNodeRef)>> {
+        let this: VfsNodeRef = self.this.upgrade().ok_or(VfsError::NotFound)?;
+        let mut children = BTreeMap::new();
+        for (name, node) in new_children {
+            if !valid_name(&name) {
+                return Err(VfsError::InvalidInput);
+            }
+            if is_ancestor_or_self(&node, this.clone()) {
+                return Err(VfsError::InvalidInput);
+            }
+            if children.insert(name, node).is_some() {
+                return Err(VfsError::InvalidInput);
+            }
+        }
+        for node in children.values() {
+            if let Some(dir) = node.as_any().downcast_ref::<DirNode>() {
+                dir.set_parent(Some(&this));
+            } else if let Some(file) = node.as_any().downcast_ref::<FileNode>() {
+                file.set_dir(self.this.clone());
+            }
+        }
+        let names: Vec<_> = children.keys().cloned().collect();
+        children.values().for_each(link_added);
+        let old = core::mem::replace(&mut *self.children.write(), children);
+        old.values().for_each(link_removed);
+        for name in old.keys() {
+            self.notify(WatchEventKind::Remove, name);
+        }
+        for name in &names {
+            self.notify(WatchEventKind::Create, name);
+        }
+        Ok(old.into_iter().collect())
+    }
+
+    /// Formats an `ls -l` style listing of the directory at `path` (relative
+    /// to this directory), one line per entry sorted by name.
+    ///
+    /// Each line holds the mode string, the link count, the size in bytes,
+    /// the modification time as `YYYY-MM-DD HH:MM` in UTC, taking the time
+    /// source as counting from the Unix epoch, and the name.
+    pub fn format_listing(&self, path: &str) -> VfsResult<String> {
+        let this = self.this.upgrade().ok_or(VfsError::NotFound)?;
+        let dir = this.lookup(path)?;
+        let dir = dir
+            .as_any()
+            .downcast_ref::<DirNode>()
+            .ok_or(VfsError::NotADirectory)?;
+
+        let mut listing = String::new();
+        for (name, node) in dir.children.read().iter() {
+            let attr = node.get_attr()?;
+            let perm = attr.perm().rwx_buf();
+            listing += &format!(
+                "{}{} {:>2} {:>8} {} {}\n",
+                attr.file_type().as_char(),
+                core::str::from_utf8(&perm).unwrap(),
+                nlink(node),
+                attr.size(),
+                format_time(mtime(node)),
+                name
+            );
+        }
+        Ok(listing)
+    }
+
+    /// Reads directory entries together with their inode numbers, starting
+    /// at `cookie` (0 for the first entry).
+    ///
+    /// Returns the number of entries filled and the cookie to resume from.
+    pub fn read_dir_full(&self, cookie: u64, out: &mut [FullDirEntry]) -> VfsResult<(usize, u64)> {
+        let start = usize::try_from(cookie).map_err(|_| VfsError::InvalidInput)?;
+        let children = self.children.read();
+        let mut children = children.iter().skip(start.max(2) - 2);
+        let mut filled = 0;
+        for ent in out.iter_mut() {
+            let (name, ty, ino) = match start + filled {
+                0 => (".", VfsNodeType::Dir, ino_of(self)),
+                1 => {
+                    let ino = self.parent().map_or(ino_of(self), |p| ino_of(&*p));
+                    ("..", VfsNodeType::Dir, ino)
+                }
+                _ => match children.next() {
+                    Some((name, node)) => {
+                        (name.as_str(), node.get_attr()?.file_type(), ino_of(&**node))
+                    }
+                    None => break,
+                },
+            };
+            *ent = FullDirEntry {
+                name: name.to_string(),
+                ty,
+                ino,
+            };
+            filled += 1;
+        }
+        Ok((filled, cookie + filled as u64))
+    }
+
     /// Removes a node by the given name in this directory.
     pub fn remove_node(&self, name: &str) -> VfsResult {
         let mut children = self.children.write();
-        let node = children.get(name).ok_or(VfsError::NotFound)?;
-        if let Some(dir) = node.as_any().downcast_ref::<DirNode>() {
+        let name = self
+            .entry_name(&children, name)
+            .ok_or(VfsError::NotFound)?
+            .clone();
+        if let Some(dir) = children[&name].as_any().downcast_ref::<DirNode>() {
             if !dir.children.read().is_empty() {
                 return Err(VfsError::DirectoryNotEmpty);
             }
         }
-        children.remove(name);
+        if let Some(node) = children.remove(&name) {
+            link_removed(&node);
+        }
+        drop(children);
+        self.notify(WatchEventKind::Remove, &name);
         Ok(())
     }
+
+    /// Removes a node by the given name in this directory, along with all
+    /// its descendants if it is a directory.
+    pub fn remove_node_recursive(&self, name: &str) -> VfsResult {
+        if name == "." || name == ".." {
+            return Err(VfsError::InvalidInput);
+        }
+        let (name, node) = {
+            let mut children = self.children.write();
+            let name = self
+                .entry_name(&children, name)
+                .ok_or(VfsError::NotFound)?
+                .clone();
+            let node = children.remove(&name).unwrap();
+            (name, node)
+        };
+        link_removed(&node);
+        if let Some(dir) = node.as_any().downcast_ref::<DirNode>() {
+            dir.clear();
+        }
+        self.notify(WatchEventKind::Remove, &name);
+        Ok(())
+    }
+
+    /// Drops all entries of this subtree, so that nodes still referenced
+    /// from elsewhere do not keep the whole subtree alive.
+    fn clear(&self) {
+        let children = core::mem::take(&mut *self.children.write());
+        for node in children.into_values() {
+            link_removed(&node);
+            if let Some(dir) = node.as_any().downcast_ref::<DirNode>() {
+                dir.clear();
+            }
+        }
+    }
     // find root
     // pub fn find_root(self: &Arc<DirNode>) -> Arc<DirNode> {
     //     let mut current: Arc<DirNode> = self.clone();
@@ -109,9 +959,19 @@ impl DirNode {
     // }
 }
 
+impl Drop for DirNode {
+    fn drop(&mut self) {
+        // entries of a directory dropped without being removed, such as an
+        // unused `cow_clone`
+        self.children.read().values().for_each(link_removed);
+    }
+}
+
 impl VfsNodeOps for DirNode {
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
-        Ok(VfsNodeAttr::new_dir(4096, 0))
+        let mut attr = VfsNodeAttr::new_dir(4096, 0);
+        attr.set_perm(VfsNodePerm::from_bits_truncate(self.mode()));
+        Ok(attr)
     }
 
     fn parent(&self) -> Option<VfsNodeRef> {
@@ -119,216 +979,393 @@ impl VfsNodeOps for DirNode {
     }
 
     fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
-        let (name, rest) = split_path(path);
         debug!(
-            "lookup: path = '{}', current node = {:p}, name = '{}', rest = {:?}",
+            "lookup: path = '{}', current node = {:p}",
             path,
-            Arc::as_ptr(&self),
-            name,
-            rest
+            Arc::as_ptr(&self)
         );
-
-        let node = match name {
-            "" | "." => {
-                debug!("-> current directory");
-                Ok(self.clone() as VfsNodeRef)
-            }
-            ".." => {
-                debug!("-> parent directory");
-                self.parent().ok_or(VfsError::NotFound)
-            }
-            _ => {
-                let children = self.children.read();
-                if let Some(child) = children.get(name) {
-                    debug!(
-                        "-> found child '{}': {:p}",
-                        name,
-                        Arc::as_ptr(&child.clone())
-                    );
-                    Ok(child.clone())
-                } else {
-                    debug!("-> child '{}' not found in current node", name);
-                    Err(VfsError::NotFound)
-                }
-            }
-        }?;
-
-        if let Some(rest) = rest {
-            debug!("-> descending into '{}'", rest);
-            node.lookup(rest)
-        } else {
-            debug!("-> final node reached: {:p}", Arc::as_ptr(&node));
-            Ok(node)
+        let node = match walk(self, &mut PathComponents::new(path), false)? {
+            Walked::Here(node) => node,
+            Walked::Above(node, rest) => node.lookup(rest)?,
+        };
+        if path.ends_with('/') && !node.get_attr()?.is_dir() {
+            return Err(VfsError::NotADirectory);
         }
+        Ok(node)
     }
 
     fn read_dir(&self, start_idx: usize, dirents: &mut [VfsDirEntry]) -> VfsResult<usize> {
+        self.times.accessed();
         let children = self.children.read();
-        let mut children = children.iter().skip(start_idx.max(2) - 2);
-        for (i, ent) in dirents.iter_mut().enumerate() {
-            match i + start_idx {
-                0 => *ent = VfsDirEntry::new(".", VfsNodeType::Dir),
-                1 => *ent = VfsDirEntry::new("..", VfsNodeType::Dir),
-                _ => {
-                    if let Some((name, node)) = children.next() {
-                        *ent = VfsDirEntry::new(name, node.get_attr().unwrap().file_type());
-                    } else {
-                        return Ok(i);
-                    }
-                }
-            }
+        let children = children
+            .iter()
+            .map(|(name, node)| (name.as_str(), Some(node)));
+        // `.` and `..` are entries 0 and 1, followed by the children
+        let entries = [(".", None), ("..", None)]
+            .into_iter()
+            .chain(children)
+            .skip(start_idx);
+        let mut filled = 0;
+        for (ent, (name, node)) in dirents.iter_mut().zip(entries) {
+            let ty = node.map_or(VfsNodeType::Dir, |node| {
+                node.get_attr().unwrap().file_type()
+            });
+            *ent = VfsDirEntry::new(name, ty);
+            filled += 1;
         }
-        Ok(dirents.len())
+        Ok(filled)
     }
 
     fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
         log::debug!("create {:?} at ramfs: {}", ty, path);
-        let (name, rest) = split_path(path);
-        if let Some(rest) = rest {
-            match name {
-                "" | "." => self.create(rest, ty),
-                ".." => self.parent().ok_or(VfsError::NotFound)?.create(rest, ty),
-                _ => {
-                    let subdir = self
-                        .children
-                        .read()
-                        .get(name)
-                        .ok_or(VfsError::NotFound)?
-                        .clone();
-                    subdir.create(rest, ty)
-                }
-            }
-        } else if name.is_empty() || name == "." || name == ".." {
-            Ok(()) // already exists
-        } else {
-            self.create_node(name, ty)
+        match self.split_last(path)? {
+            Split::Entry(dir, name) => dir.create_node(name, ty),
+            Split::Dir => Ok(()), // already exists
+            Split::Above(node, rest) => node.create(rest, ty),
         }
     }
 
     fn remove(&self, path: &str) -> VfsResult {
         log::debug!("remove at ramfs: {}", path);
-        let (name, rest) = split_path(path);
-        if let Some(rest) = rest {
-            match name {
-                "" | "." => self.remove(rest),
-                ".." => self.parent().ok_or(VfsError::NotFound)?.remove(rest),
-                _ => {
-                    let subdir = self
-                        .children
-                        .read()
-                        .get(name)
-                        .ok_or(VfsError::NotFound)?
-                        .clone();
-                    subdir.remove(rest)
-                }
-            }
-        } else if name.is_empty() || name == "." || name == ".." {
-            Err(VfsError::InvalidInput) // remove '.' or '..
-        } else {
-            self.remove_node(name)
+        match self.split_last(path)? {
+            Split::Entry(dir, name) => dir.remove_node(name),
+            Split::Dir => Err(VfsError::InvalidInput), // remove '.' or '..'
+            Split::Above(node, rest) => node.remove(rest),
         }
     }
 
     fn rename(&self, old_path: &str, new_path: &str) -> VfsResult<()> {
         debug!("rename: {} -> {}", old_path, new_path);
 
-        // 解析 old_path，获得 old_dir_path 和 old_name
-        let (_, old_name) = split_parent(old_path)?;
-        let (_, new_name) = split_parent(new_path)?;
-        // 从 root 开始查找 old_dir
-        let old_dir = self.this.upgrade().ok_or(VfsError::NotFound)?;
+        // 分别找到源和目标所在的目录，两者可以不同
+        let (old_dir, old_name, new_dir, new_name) =
+            match (self.split_last(old_path)?, self.split_last(new_path)?) {
+                (Split::Entry(old_dir, old_name), Split::Entry(new_dir, new_name)) => {
+                    (old_dir, old_name, new_dir, new_name)
+                }
+                // rename '.' or '..', or across filesystems
+                _ => return Err(VfsError::InvalidInput),
+            };
+        if !valid_name(new_name) {
+            return Err(VfsError::InvalidInput);
+        }
 
-        // 移除 old_node
-        let old_node = {
-            let mut old_children = old_dir.children.write();
-            old_children.remove(old_name).ok_or(VfsError::NotFound)?
+        // 跨目录的 rename 一次只进行一个，见 lock_children
+        let _rename_guard = (!Arc::ptr_eq(&old_dir, &new_dir)).then(|| RENAME_LOCK.lock());
+        // 在同一把锁下移除并插入，避免中途被其他 rename 插入
+        let (node, src_name, replaced) = {
+            let (mut old_children, new_children) = lock_children(&old_dir, &new_dir);
+            let src_name = old_dir
+                .entry_name(&old_children, old_name)
+                .ok_or(VfsError::NotFound)?
+                .clone();
+            let src = old_children[&src_name].clone();
+            let new_dir_ref: VfsNodeRef = new_dir.clone();
+            if is_ancestor_or_self(&src, new_dir_ref) {
+                // 不允许把目录移动到它自己或其子目录中
+                return Err(VfsError::InvalidInput);
+            }
+            let dst_children = new_children.as_ref().unwrap_or(&old_children);
+            let dst_name = new_dir.entry_name(dst_children, new_name).cloned();
+            let replaced = match &dst_name {
+                // 不区分大小写时，只改变同一项名字的大小写
+                Some(dst_name)
+                    if new_children.is_none() && *dst_name == src_name && dst_name != new_name =>
+                {
+                    false
+                }
+                Some(dst_name) if ino_of(&*dst_children[dst_name]) == ino_of(&*src) => {
+                    return Ok(())
+                }
+                // 目标已存在时按 POSIX 的规则覆盖
+                Some(dst_name) => {
+                    check_replace(&src, &dst_children[dst_name], &old_dir)?;
+                    true
+                }
+                None => false,
+            };
+            old_children.remove(&src_name);
+            let mut dst_children = new_children.unwrap_or(old_children);
+            if replaced {
+                let dst = dst_children.remove(&dst_name.unwrap()).unwrap();
+                link_removed(&dst);
+            }
+            dst_children.insert(new_name.into(), src.clone());
+            (src, src_name, replaced)
         };
-        old_dir.children.write().insert(new_name.to_string(), old_node);
+        if let Some(dir) = node.as_any().downcast_ref::<DirNode>() {
+            let new_dir: VfsNodeRef = new_dir.clone();
+            dir.set_parent(Some(&new_dir));
+            dir.times.changed();
+        } else if let Some(file) = node.as_any().downcast_ref::<FileNode>() {
+            file.set_dir(Arc::downgrade(&new_dir));
+            file.times.changed();
+        }
+        old_dir.notify(WatchEventKind::Remove, &src_name);
+        let kind = if replaced {
+            WatchEventKind::Replace
+        } else {
+            WatchEventKind::Create
+        };
+        new_dir.notify(kind, new_name);
         Ok(())
     }
-    // fn rename(&self, old_path: &str, new_path: &str) -> VfsResult<()> {
-    //     debug!("rename: {} -> {}", old_path, new_path);
 
-    //     // 先获得 root 节点
-    //     let current_dir = self.this.upgrade().ok_or(VfsError::NotFound)?;
-    //     let root = current_dir.find_root();
-    //     let parent_node = self.parent();
-    //     debug!("Root children:");
-    //     for (k, v) in root.children.read().iter() {
-    //         debug!("  {} => {:p}", k, Arc::as_ptr(v));
-    //     }
-    //     debug!(
-    //         "DEBUG Node Info:
-    //         self ptr: {:p}
-    //         root ptr: {:p}
-    //         parent ptr: {}
-    //         ",
-    //         Arc::as_ptr(&current_dir),
-    //         Arc::as_ptr(&root),
-    //         parent_node
-    //             .as_ref()
-    //             .map(|p| format!("{:p}", Arc::as_ptr(p)))
-    //             .unwrap_or_else(|| "None".into())
-    //     );
-    //     // 解析 old_path，获得 old_dir_path 和 old_name
-    //     let (old_dir_path, old_name) = split_parent(old_path)?;
-    //     // 从 root 开始查找 old_dir
-    //     let old_dir_node = current_dir.clone().lookup(old_dir_path)?;
-    //     let old_dir_ref = old_dir_node
-    //         .as_any()
-    //         .downcast_ref::<DirNode>()
-    //         .ok_or(VfsError::NotADirectory)?;
-    //     let old_dir = old_dir_ref.this.upgrade().ok_or(VfsError::NotFound)?;
-    //     // let self_arc = self.this.upgrade().unwrap();
-    //     // debug!("old_dir ptr: {:p}", Arc::as_ptr(&old_dir));
-    //     // debug!("self ptr: {:p}", Arc::as_ptr(&self_arc));
-    //     for name in old_dir.children.read().keys() {
-    //         debug!("child in old_dir: {}", name);
-    //     }
+    axfs_vfs::impl_vfs_dir_default! {}
+}
 
-    //     // 移除 old_node
-    //     let old_node = {
-    //         let mut old_children = old_dir.children.write();
-    //         old_children.remove(old_name).ok_or(VfsError::NotFound)?
-    //     };
-    //     // 解析 new_path，获得 new_dir_path 和 new_name
-    //     let (new_dir_path, new_name) = split_parent(new_path)?;
-    //     // 从 root 开始查找 new_dir
-    //     debug!("new_dir_path is {}, new_name is {}", new_dir_path, new_name);
-    //     let new_dir_node = root.clone().lookup(new_dir_path)?;
-    //     let new_dir_ref = new_dir_node
-    //         .as_any()
-    //         .downcast_ref::<DirNode>()
-    //         .ok_or(VfsError::NotADirectory)?;
-    //     let new_dir = new_dir_ref.this.upgrade().ok_or(VfsError::NotFound)?;
-    //     // 插入新节点
-
-    //     let mut new_children = new_dir.children.write();
-    //     if new_children.contains_key(new_name) {
-    //         return Err(VfsError::AlreadyExists);
-    //     }
-    //     new_children.insert(new_name.to_string(), old_node);
+/// The directory holding the last component of a path, as found by
+/// [`DirNode::split_last`].
+pub(crate) enum Split<'a> {
+    /// The entry `name` of a directory of this filesystem.
+    Entry(Arc<DirNode>, &'a str),
+    /// The path names a directory itself, being empty or ending with `.` or
+    /// `..`.
+    Dir,
+    /// A node of the filesystem this one is mounted in, reached by `..` above
+    /// the root, and the rest of the path to resolve from it.
+    Above(VfsNodeRef, &'a str),
+}
 
-    //     Ok(())
-    // }
+/// Where a [`walk`] stopped.
+enum Walked<'a> {
+    /// All the components were walked through to this node.
+    Here(VfsNodeRef),
+    /// A `..` above the root led to this node of another filesystem, which is
+    /// left to resolve the rest of the path.
+    Above(VfsNodeRef, &'a str),
+}
 
-    axfs_vfs::impl_vfs_dir_default! {}
+/// Walks down from `dir` through `comps`, without following symbolic links.
+/// If `keep_last` is set, the last component is left in `comps`.
+fn walk<'a>(
+    dir: Arc<DirNode>,
+    comps: &mut PathComponents<'a>,
+    keep_last: bool,
+) -> VfsResult<Walked<'a>> {
+    let mut cur: VfsNodeRef = dir;
+    while comps.clone().nth(keep_last as usize).is_some() {
+        let name = comps.next().unwrap();
+        let dir = cur
+            .as_any()
+            .downcast_ref::<DirNode>()
+            .ok_or(VfsError::NotADirectory)?;
+        let next = match name {
+            // nothing above a root, or above a directory whose parent
+            // is gone: never fall back to creating in `dir` itself
+            ".." => match dir.parent().ok_or(VfsError::NotFound)? {
+                parent if parent.as_any().is::<DirNode>() => parent,
+                parent => return Ok(Walked::Above(parent, comps.as_str())),
+            },
+            _ => dir.child(name).ok_or(VfsError::NotFound)?,
+        };
+        cur = next;
+    }
+    Ok(Walked::Here(cur))
+}
+
+/// Resolves `path` from `dir`, following symbolic links. `depth` counts the
+/// links followed so far, across nested resolutions of link targets.
+fn resolve(dir: Arc<DirNode>, path: &str, depth: &mut usize) -> VfsResult<VfsNodeRef> {
+    let mut cur: VfsNodeRef = dir;
+    for name in path.split('/').filter(|name| !name.is_empty()) {
+        let parent = cur
+            .as_any()
+            .downcast_ref::<DirNode>()
+            .and_then(|dir| dir.this.upgrade())
+            .ok_or(VfsError::NotADirectory)?;
+        cur = parent.clone().lookup(name)?;
+        if let Some(link) = cur.as_any().downcast_ref::<SymlinkNode>() {
+            *depth += 1;
+            if *depth > MAX_SYMLINK_DEPTH {
+                return Err(VfsError::InvalidInput);
+            }
+            let target = link.read_link();
+            let base = if target.starts_with('/') {
+                parent.root()
+            } else {
+                parent
+            };
+            cur = resolve(base, &target, depth)?;
+        }
+    }
+    Ok(cur)
 }
 
-fn split_path(path: &str) -> (&str, Option<&str>) {
-    let trimmed_path = path.trim_start_matches('/');
-    trimmed_path.find('/').map_or((trimmed_path, None), |n| {
-        (&trimmed_path[..n], Some(&trimmed_path[n + 1..]))
-    })
+/// A directory entry returned by [`DirNode::read_dir_full`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FullDirEntry {
+    /// Entry name.
+    pub name: String,
+    /// Type of the node.
+    pub ty: VfsNodeType,
+    /// Inode number of the node.
+    pub ino: u64,
+}
+
+impl Default for FullDirEntry {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            ty: VfsNodeType::File,
+            ino: 0,
+        }
+    }
+}
+
+/// Inode number of a node: the address of the node itself, which is unique
+/// among live nodes and stable for as long as the node exists.
+fn ino_of<T: VfsNodeOps + ?Sized>(node: &T) -> u64 {
+    node as *const T as *const () as usize as u64
+}
+
+type ChildrenGuard<'a> = RwLockWriteGuard<'a, BTreeMap<String, VfsNodeRef>>;
+
+/// Serializes the renames across directories, which are the only operations
+/// holding the children of two directories that are not parent and child.
+static RENAME_LOCK: spin::Mutex<()> = spin::Mutex::new(());
+
+/// Write-locks the children of two directories. Takes a single lock, returned
+/// first, if `a` and `b` are the same directory.
+///
+/// Like every other operation, an ancestor is locked before its descendants.
+/// Unrelated directories are locked in the order of their inode numbers; the
+/// caller must hold [`RENAME_LOCK`] then.
+fn lock_children<'a>(
+    a: &'a Arc<DirNode>,
+    b: &'a Arc<DirNode>,
+) -> (ChildrenGuard<'a>, Option<ChildrenGuard<'a>>) {
+    if Arc::ptr_eq(a, b) {
+        return (a.children.write(), None);
+    }
+    let (a_ref, b_ref): (VfsNodeRef, VfsNodeRef) = (a.clone(), b.clone());
+    let a_first = if is_ancestor_or_self(&a_ref, b_ref.clone()) {
+        true
+    } else if is_ancestor_or_self(&b_ref, a_ref) {
+        false
+    } else {
+        ino_of(&**a) < ino_of(&**b)
+    };
+    if a_first {
+        let first = a.children.write();
+        (first, Some(b.children.write()))
+    } else {
+        let second = b.children.write();
+        (a.children.write(), Some(second))
+    }
+}
+
+/// Checks that `src` may replace the existing `dst` in a rename, like POSIX
+/// `rename`: a directory only replaces an empty directory, and anything else
+/// only replaces a non-directory. `locked` is the source directory, whose
+/// children are already locked.
+fn check_replace(src: &VfsNodeRef, dst: &VfsNodeRef, locked: &Arc<DirNode>) -> VfsResult {
+    let src_is_dir = src.as_any().is::<DirNode>();
+    match dst.as_any().downcast_ref::<DirNode>() {
+        Some(_) if !src_is_dir => Err(VfsError::IsADirectory),
+        // it holds `src`, maybe through subdirectories: not empty, and its
+        // children must not be locked after those of `locked`
+        Some(_) if is_ancestor_or_self(dst, locked.clone()) => Err(VfsError::DirectoryNotEmpty),
+        Some(dst) if !dst.children.read().is_empty() => Err(VfsError::DirectoryNotEmpty),
+        None if src_is_dir => Err(VfsError::NotADirectory),
+        _ => Ok(()),
+    }
+}
+
+/// Whether `ancestor` is `node` itself or one of its ancestors.
+fn is_ancestor_or_self(ancestor: &VfsNodeRef, node: VfsNodeRef) -> bool {
+    let target = Arc::as_ptr(ancestor) as *const ();
+    let mut cur = Some(node);
+    while let Some(node) = cur {
+        if Arc::as_ptr(&node) as *const () == target {
+            return true;
+        }
+        cur = node.parent();
+    }
+    false
+}
+
+/// The `splitmix64` finalizer, spreads consecutive counters into unrelated
+/// looking values.
+fn mix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+/// Number of names referring to `node`: a directory is referred to by its
+/// entry in the parent, its own `.` and the `..` of each subdirectory.
+fn nlink(node: &VfsNodeRef) -> usize {
+    match node.as_any().downcast_ref::<DirNode>() {
+        Some(dir) => {
+            let children = dir.children.read();
+            2 + children
+                .values()
+                .filter(|child| child.as_any().is::<DirNode>())
+                .count()
+        }
+        None => node
+            .as_any()
+            .downcast_ref::<FileNode>()
+            .map_or(1, FileNode::link_count),
+    }
+}
+
+/// Returns the modification time of `node`, zero for the kinds of nodes
+/// without timestamps.
+fn mtime(node: &VfsNodeRef) -> Duration {
+    if let Some(dir) = node.as_any().downcast_ref::<DirNode>() {
+        dir.times().mtime
+    } else if let Some(file) = node.as_any().downcast_ref::<FileNode>() {
+        file.times().mtime
+    } else {
+        Duration::ZERO
+    }
+}
+
+/// Formats `time` since the Unix epoch as `YYYY-MM-DD HH:MM` in UTC, like
+/// `ls -l --time-style=long-iso`.
+fn format_time(time: Duration) -> String {
+    let (days, secs) = (time.as_secs() / 86400, time.as_secs() % 86400);
+    // Howard Hinnant's `civil_from_days`, with years starting in March
+    let z = days + 719_468;
+    let (era, doe) = (z / 146_097, z % 146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = era * 400 + yoe + (month <= 2) as u64;
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60
+    )
+}
+
+/// Returns whether `name` can name a directory entry.
+fn valid_name(name: &str) -> bool {
+    !(name.is_empty()
+        || name == "."
+        || name == ".."
+        || name.len() > MAX_NAME_LEN
+        || name.contains(['/', '\0']))
+}
+
+/// Counts a new directory entry referring to `node`.
+fn link_added(node: &VfsNodeRef) {
+    if let Some(file) = node.as_any().downcast_ref::<FileNode>() {
+        file.add_link();
+    }
 }
 
-/// 拆分出父目录路径 + 文件名，例如 "/tmp/f1" => ("/tmp", "f1")
-fn split_parent(path: &str) -> VfsResult<(&str, &str)> {
-    let trimmed = path.trim_end_matches('/');
-    match trimmed.rfind('/') {
-        Some(pos) if pos > 0 => Ok((&trimmed[..pos], &trimmed[pos + 1..])),
-        Some(0) => Ok(("/", &trimmed[1..])),
-        None => Err(VfsError::InvalidInput),
-        _ => Err(VfsError::InvalidInput),
+/// Counts a removed directory entry referring to `node`.
+fn link_removed(node: &VfsNodeRef) {
+    if let Some(file) = node.as_any().downcast_ref::<FileNode>() {
+        file.remove_link();
     }
 }
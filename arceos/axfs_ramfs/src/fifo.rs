@@ -0,0 +1,123 @@
+use alloc::collections::VecDeque;
+use axfs_vfs::{impl_vfs_non_dir_default, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeType};
+use axfs_vfs::{VfsError, VfsResult};
+use spin::{Mutex, RwLock};
+
+/// Capacity of the ring buffer of a FIFO, in bytes.
+pub const FIFO_CAPACITY: usize = 4096;
+
+/// How FIFOs block the calling task, e.g. on a wait queue of the scheduler,
+/// which this crate cannot depend on. See [`set_fifo_waiter`].
+#[derive(Clone, Copy)]
+pub struct FifoWaiter {
+    /// Blocks the calling task until the condition returns `true`, checking
+    /// it again after each call to `wake`.
+    pub wait_until: fn(&dyn Fn() -> bool),
+    /// Wakes the tasks blocked in `wait_until`.
+    pub wake: fn(),
+}
+
+/// Waiter given to new FIFOs, see [`set_fifo_waiter`].
+static DEFAULT_WAITER: RwLock<Option<FifoWaiter>> = RwLock::new(None);
+
+/// Makes FIFOs created from now on block with `waiter`, e.g. one built on an
+/// `axtask::WaitQueue`.
+///
+/// Until it is set, FIFOs do not block, see [`FifoNode`].
+pub fn set_fifo_waiter(waiter: FifoWaiter) {
+    *DEFAULT_WAITER.write() = Some(waiter);
+}
+
+/// The named pipe (FIFO) node in the RAM filesystem.
+///
+/// Bytes written are read back in order through a bounded ring buffer. With
+/// a [`FifoWaiter`], reading an empty FIFO blocks until some bytes are
+/// written, and writing blocks whenever the FIFO is full until all the bytes
+/// are written. Without one, the node never blocks: reading an empty FIFO or
+/// writing a full one fails with [`VfsError::WouldBlock`], and the caller is
+/// expected to yield and retry.
+///
+/// It implements [`axfs_vfs::VfsNodeOps`].
+pub struct FifoNode {
+    buf: Mutex<VecDeque<u8>>,
+    waiter: RwLock<Option<FifoWaiter>>,
+}
+
+impl FifoNode {
+    pub(super) fn new() -> Self {
+        Self {
+            buf: Mutex::new(VecDeque::with_capacity(FIFO_CAPACITY)),
+            waiter: RwLock::new(*DEFAULT_WAITER.read()),
+        }
+    }
+
+    /// Sets how this FIFO blocks, overriding the one given by
+    /// [`set_fifo_waiter`] when it was created. `None` makes it never block.
+    pub fn set_waiter(&self, waiter: Option<FifoWaiter>) {
+        *self.waiter.write() = waiter;
+    }
+}
+
+impl VfsNodeOps for FifoNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let len = self.buf.lock().len();
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::default_file(),
+            VfsNodeType::Fifo,
+            len as _,
+            0,
+        ))
+    }
+
+    fn read_at(&self, _offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let waiter = *self.waiter.read();
+        loop {
+            let len = {
+                let mut fifo = self.buf.lock();
+                let len = buf.len().min(fifo.len());
+                for (dst, src) in buf.iter_mut().zip(fifo.drain(..len)) {
+                    *dst = src;
+                }
+                len
+            };
+            if len > 0 || buf.is_empty() {
+                // there is room for blocked writers now
+                if let Some(waiter) = waiter {
+                    (waiter.wake)();
+                }
+                return Ok(len);
+            }
+            let waiter = waiter.ok_or(VfsError::WouldBlock)?;
+            (waiter.wait_until)(&|| !self.buf.lock().is_empty());
+        }
+    }
+
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let waiter = *self.waiter.read();
+        let mut written = 0;
+        loop {
+            let len = {
+                let mut fifo = self.buf.lock();
+                let len = (buf.len() - written).min(FIFO_CAPACITY - fifo.len());
+                fifo.extend(&buf[written..written + len]);
+                len
+            };
+            written += len;
+            if let (Some(waiter), true) = (waiter, len > 0) {
+                (waiter.wake)();
+            }
+            match waiter {
+                _ if written == buf.len() => return Ok(written),
+                None if written > 0 => return Ok(written),
+                None => return Err(VfsError::WouldBlock),
+                Some(waiter) => (waiter.wait_until)(&|| self.buf.lock().len() < FIFO_CAPACITY),
+            }
+        }
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Ok(())
+    }
+
+    impl_vfs_non_dir_default! {}
+}
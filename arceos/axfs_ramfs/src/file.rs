@@ -1,38 +1,463 @@
+use alloc::collections::TryReserveError;
+use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
-use axfs_vfs::{impl_vfs_non_dir_default, VfsNodeAttr, VfsNodeOps, VfsResult};
-use spin::RwLock;
+use axfs_vfs::{impl_vfs_non_dir_default, VfsError, VfsNodeAttr, VfsNodeOps};
+use axfs_vfs::{VfsNodePerm, VfsResult};
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::time::Duration;
+use spin::{Mutex, RwLock, RwLockReadGuard};
+
+use crate::time::{NodeTimes, Times};
+use crate::DirNode;
+
+/// Largest contents kept inline in a [`FileNode`], without a heap buffer.
+pub const INLINE_CAPACITY: usize = 32;
 
 /// The file node in the RAM filesystem.
 ///
 /// It implements [`axfs_vfs::VfsNodeOps`].
 pub struct FileNode {
-    content: RwLock<Vec<u8>>,
+    content: RwLock<Content>,
+    /// Writes not yet visible to readers, `None` unless staging is enabled.
+    staging: Mutex<Option<Staging>>,
+    /// Directory whose quotas account for this file.
+    dir: RwLock<Weak<DirNode>>,
+    pub(crate) times: Times,
+    /// Permission bits, `0o644` by default.
+    mode: RwLock<u16>,
+    /// Number of directory entries referring to the file.
+    links: AtomicUsize,
+}
+
+/// Read access to the contents of a [`FileNode`], see
+/// [`FileNode::as_slice`].
+pub struct FileReadGuard<'a>(RwLockReadGuard<'a, Content>);
+
+impl Deref for FileReadGuard<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Contents of a file, stored inline while they fit in [`INLINE_CAPACITY`]
+/// bytes and moved to the heap once they grow past it.
+enum Content {
+    /// The bytes past `len` are always zero.
+    Inline {
+        len: u8,
+        buf: [u8; INLINE_CAPACITY],
+    },
+    Heap(Vec<u8>),
+}
+
+impl Content {
+    const fn new() -> Self {
+        Self::Inline {
+            len: 0,
+            buf: [0; INLINE_CAPACITY],
+        }
+    }
+
+    fn from_vec(data: Vec<u8>) -> Self {
+        if data.len() > INLINE_CAPACITY {
+            return Self::Heap(data);
+        }
+        let mut content = Self::new();
+        content.resize(data.len());
+        content.copy_from_slice(&data);
+        content
+    }
+
+    fn is_inline(&self) -> bool {
+        matches!(self, Self::Inline { .. })
+    }
+
+    fn capacity(&self) -> usize {
+        match self {
+            Self::Inline { .. } => INLINE_CAPACITY,
+            Self::Heap(data) => data.capacity(),
+        }
+    }
+
+    fn try_reserve_exact(&mut self, additional: usize) -> Result<(), TryReserveError> {
+        match self {
+            Self::Heap(data) => data.try_reserve_exact(additional),
+            Self::Inline { len, .. } if *len as usize + additional <= INLINE_CAPACITY => Ok(()),
+            Self::Inline { len, buf } => {
+                let mut data = Vec::new();
+                data.try_reserve_exact(*len as usize + additional)?;
+                data.extend_from_slice(&buf[..*len as usize]);
+                *self = Self::Heap(data);
+                Ok(())
+            }
+        }
+    }
+
+    /// Resizes the contents to `size` bytes, zero-filling any new bytes.
+    fn resize(&mut self, size: usize) {
+        match self {
+            Self::Heap(data) => data.resize(size, 0),
+            Self::Inline { len, buf } if size <= INLINE_CAPACITY => {
+                if size < *len as usize {
+                    buf[size..*len as usize].fill(0);
+                }
+                *len = size as u8;
+            }
+            Self::Inline { len, buf } => {
+                let mut data = Vec::with_capacity(size);
+                data.extend_from_slice(&buf[..*len as usize]);
+                data.resize(size, 0);
+                *self = Self::Heap(data);
+            }
+        }
+    }
+}
+
+impl Deref for Content {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Self::Inline { len, buf } => &buf[..*len as usize],
+            Self::Heap(data) => data,
+        }
+    }
+}
+
+impl DerefMut for Content {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        match self {
+            Self::Inline { len, buf } => &mut buf[..*len as usize],
+            Self::Heap(data) => data,
+        }
+    }
+}
+
+/// Bounded buffer of staged modifications, applied in order on commit.
+struct Staging {
+    limit: usize,
+    bytes: usize,
+    ops: Vec<StagedOp>,
+}
+
+enum StagedOp {
+    Write(u64, Vec<u8>),
+    Truncate(u64),
 }
 
 impl FileNode {
-    pub(super) const fn new() -> Self {
+    pub(super) fn new() -> Self {
+        Self::from_content(Content::new())
+    }
+
+    pub(super) fn from_vec(content: Vec<u8>) -> Self {
+        Self::from_content(Content::from_vec(content))
+    }
+
+    fn from_content(content: Content) -> Self {
         Self {
-            content: RwLock::new(Vec::new()),
+            content: RwLock::new(content),
+            staging: Mutex::new(None),
+            dir: RwLock::new(Weak::new()),
+            times: Times::new(),
+            mode: RwLock::new(0o644),
+            links: AtomicUsize::new(0),
+        }
+    }
+
+    pub(super) fn set_dir(&self, dir: Weak<DirNode>) {
+        *self.dir.write() = dir;
+    }
+
+    /// Makes room in the backing buffer for at least `size` bytes of
+    /// content, so that writes within that size do not reallocate.
+    ///
+    /// This is only a hint: the file size is unchanged.
+    pub fn reserve(&self, size: u64) -> VfsResult {
+        let mut content = self.content.write();
+        let additional = (size as usize).saturating_sub(content.len());
+        content
+            .try_reserve_exact(additional)
+            .map_err(|_| VfsError::NoMemory)
+    }
+
+    /// Grows the file to at least `offset + len` bytes, zero-filling the
+    /// newly covered region, like `fallocate` with mode 0.
+    ///
+    /// Unlike [`truncate`](VfsNodeOps::truncate), it never shrinks the file.
+    /// It acts on the committed contents even while staging is enabled.
+    pub fn fallocate(&self, offset: u64, len: u64) -> VfsResult {
+        let size = offset.checked_add(len).ok_or(VfsError::InvalidInput)?;
+        self.check_growth(size)?;
+        let mut content = self.content.write();
+        if size > content.len() as u64 {
+            let additional = size as usize - content.len();
+            content
+                .try_reserve_exact(additional)
+                .map_err(|_| VfsError::NoMemory)?;
+            content.resize(size as usize);
+            self.times.modified();
+        }
+        Ok(())
+    }
+
+    /// Returns the number of bytes the file can hold without reallocating.
+    ///
+    /// This is [`INLINE_CAPACITY`] while the contents are stored inline.
+    pub fn capacity(&self) -> usize {
+        self.content.read().capacity()
+    }
+
+    /// Returns whether the contents are stored inline in the node.
+    ///
+    /// Files start inline and move to a heap buffer once they grow past
+    /// [`INLINE_CAPACITY`] bytes, staying there even if they shrink back.
+    pub fn is_inline(&self) -> bool {
+        self.content.read().is_inline()
+    }
+
+    /// Borrows the committed contents in place, without copying them.
+    ///
+    /// The guard holds the read lock of the contents: writes and truncations
+    /// of the file wait until it is dropped, so do not write the file while
+    /// holding it. Staged writes are only buffered and do not wait, but their
+    /// commit does.
+    pub fn as_slice(&self) -> FileReadGuard<'_> {
+        self.times.accessed();
+        FileReadGuard(self.content.read())
+    }
+
+    /// Returns the access, modification and change times of the file.
+    ///
+    /// Its modification time is updated by writes and truncations, when
+    /// they are committed if staging is enabled, and its access time by
+    /// reads as set by [`set_atime_mode`](crate::set_atime_mode).
+    pub fn times(&self) -> NodeTimes {
+        self.times.get()
+    }
+
+    /// Sets the access and modification times of the file, leaving out the
+    /// `None` ones. The change time is set to the current time.
+    pub fn set_times(&self, atime: Option<Duration>, mtime: Option<Duration>) {
+        self.times.set(atime, mtime);
+    }
+
+    /// Returns the permission bits of the file.
+    pub fn mode(&self) -> u16 {
+        *self.mode.read()
+    }
+
+    /// Sets the permission bits of the file, like `chmod`. Bits above
+    /// `0o777` are ignored.
+    pub fn set_mode(&self, mode: u16) {
+        *self.mode.write() = mode & 0o777;
+        self.times.changed();
+    }
+
+    /// Returns the number of directory entries referring to the file, more
+    /// than one once it is hard-linked with [`DirNode::link`].
+    pub fn link_count(&self) -> usize {
+        self.links.load(Ordering::Relaxed)
+    }
+
+    pub(super) fn add_link(&self) {
+        self.links.fetch_add(1, Ordering::Relaxed);
+        self.times.changed();
+    }
+
+    pub(super) fn remove_link(&self) {
+        self.links.fetch_sub(1, Ordering::Relaxed);
+        self.times.changed();
+    }
+
+    /// Returns a 64-bit FNV-1a hash of the committed contents.
+    ///
+    /// Meant for integrity checks and for spotting identical files; it is
+    /// not collision resistant against crafted contents.
+    pub fn content_hash(&self) -> u64 {
+        fnv1a(FNV_OFFSET, &self.content.read())
+    }
+
+    /// Writes the buffers one after the other starting at `offset`, like
+    /// `writev`, and returns the total number of bytes written.
+    ///
+    /// The contents are locked once for all the buffers, so that readers
+    /// never see only some of them.
+    pub fn write_vectored(&self, offset: u64, bufs: &[&[u8]]) -> VfsResult<usize> {
+        let len: usize = bufs.iter().map(|buf| buf.len()).sum();
+        if let Some(staged) = self.staging.lock().as_mut() {
+            if staged.bytes + len > staged.limit {
+                return Err(VfsError::StorageFull);
+            }
+            staged.bytes += len;
+            staged.ops.push(StagedOp::Write(offset, bufs.concat()));
+            return Ok(len);
+        }
+        self.check_growth(offset + len as u64)?;
+        let mut content = self.content.write();
+        let mut pos = offset;
+        for buf in bufs {
+            write_content(&mut content, pos, buf);
+            pos += buf.len() as u64;
         }
+        self.times.modified();
+        Ok(len)
+    }
+
+    /// Returns the approximate number of open handles to this file.
+    ///
+    /// This is the number of strong references minus the entries of the
+    /// directory the file was created in that refer to it, `self` included.
+    /// References held elsewhere in the filesystem, such as read-only binds
+    /// or the entries of a [`cow_clone`](DirNode::cow_clone) of the
+    /// directory, are counted as handles too.
+    pub fn handle_count(self: &Arc<Self>) -> usize {
+        let dir_refs = self
+            .dir
+            .read()
+            .upgrade()
+            .map_or(0, |dir| dir.count_refs(Arc::as_ptr(self) as *const ()));
+        Arc::strong_count(self).saturating_sub(dir_refs)
     }
+
+    /// Checks the quotas of the owning directory before growing to `size`.
+    fn check_growth(&self, size: u64) -> VfsResult {
+        let grow = size.saturating_sub(self.content.read().len() as u64);
+        match self.dir.read().upgrade() {
+            Some(dir) if grow > 0 => dir.check_quota(grow),
+            _ => Ok(()),
+        }
+    }
+
+    /// Starts buffering writes and truncations, holding at most `limit`
+    /// bytes of written data.
+    ///
+    /// Readers keep seeing the committed content until [`commit`](Self::commit).
+    /// Staged writes beyond the limit fail with [`VfsError::StorageFull`].
+    /// Quotas are only checked when the modifications are committed.
+    pub fn enable_staging(&self, limit: usize) {
+        let mut staging = self.staging.lock();
+        if staging.is_none() {
+            *staging = Some(Staging {
+                limit,
+                bytes: 0,
+                ops: Vec::new(),
+            });
+        }
+    }
+
+    /// Commits pending modifications and goes back to writing through.
+    ///
+    /// Fails like [`commit`](Self::commit), staying in staging mode.
+    pub fn disable_staging(&self) -> VfsResult {
+        let mut staging = self.staging.lock();
+        if let Some(staged) = staging.as_ref() {
+            self.apply(&staged.ops)?;
+            *staging = None;
+        }
+        Ok(())
+    }
+
+    /// Returns the number of written bytes waiting to be committed.
+    pub fn staged_bytes(&self) -> usize {
+        self.staging
+            .lock()
+            .as_ref()
+            .map_or(0, |staged| staged.bytes)
+    }
+
+    /// Makes all staged modifications visible to readers.
+    ///
+    /// Fails with [`VfsError::StorageFull`] if the file would end up growing
+    /// past the quotas of its directory. Nothing is applied then, and the
+    /// modifications stay staged.
+    pub fn commit(&self) -> VfsResult {
+        let mut staging = self.staging.lock();
+        if let Some(staged) = staging.as_mut() {
+            self.apply(&staged.ops)?;
+            staged.bytes = 0;
+            staged.ops.clear();
+        }
+        Ok(())
+    }
+
+    /// Discards all staged modifications.
+    pub fn rollback(&self) {
+        if let Some(staged) = self.staging.lock().as_mut() {
+            staged.bytes = 0;
+            staged.ops.clear();
+        }
+    }
+
+    fn apply(&self, ops: &[StagedOp]) -> VfsResult {
+        if ops.is_empty() {
+            return Ok(());
+        }
+        let size = ops
+            .iter()
+            .fold(self.content.read().len() as u64, |size, op| match op {
+                StagedOp::Write(offset, data) => size.max(offset + data.len() as u64),
+                StagedOp::Truncate(new_size) => *new_size,
+            });
+        self.check_growth(size)?;
+        let mut content = self.content.write();
+        for op in ops {
+            match op {
+                StagedOp::Write(offset, data) => write_content(&mut content, *offset, data),
+                StagedOp::Truncate(size) => truncate_content(&mut content, *size),
+            }
+        }
+        self.times.modified();
+        Ok(())
+    }
+}
+
+/// Initial state of an FNV-1a hash.
+pub(crate) const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// Feeds `bytes` into the FNV-1a hash `state`.
+pub(crate) fn fnv1a(state: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(state, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+fn truncate_content(content: &mut Content, size: u64) {
+    content.resize(size as _);
+}
+
+fn write_content(content: &mut Content, offset: u64, buf: &[u8]) {
+    let offset = offset as usize;
+    if offset + buf.len() > content.len() {
+        content.resize(offset + buf.len());
+    }
+    let dst = &mut content[offset..offset + buf.len()];
+    dst.copy_from_slice(&buf[..dst.len()]);
 }
 
 impl VfsNodeOps for FileNode {
     fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
-        Ok(VfsNodeAttr::new_file(self.content.read().len() as _, 0))
+        let mut attr = VfsNodeAttr::new_file(self.content.read().len() as _, 0);
+        attr.set_perm(VfsNodePerm::from_bits_truncate(self.mode()));
+        Ok(attr)
     }
 
     fn truncate(&self, size: u64) -> VfsResult {
-        let mut content = self.content.write();
-        if size < content.len() as u64 {
-            content.truncate(size as _);
-        } else {
-            content.resize(size as _, 0);
+        if let Some(staged) = self.staging.lock().as_mut() {
+            staged.ops.push(StagedOp::Truncate(size));
+            return Ok(());
         }
+        self.check_growth(size)?;
+        truncate_content(&mut self.content.write(), size);
+        self.times.modified();
         Ok(())
     }
 
     fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        self.times.accessed();
         let content = self.content.read();
         let start = content.len().min(offset as usize);
         let end = content.len().min(offset as usize + buf.len());
@@ -42,15 +467,56 @@ impl VfsNodeOps for FileNode {
     }
 
     fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
-        let offset = offset as usize;
-        let mut content = self.content.write();
-        if offset + buf.len() > content.len() {
-            content.resize(offset + buf.len(), 0);
+        if let Some(staged) = self.staging.lock().as_mut() {
+            if staged.bytes + buf.len() > staged.limit {
+                return Err(VfsError::StorageFull);
+            }
+            staged.bytes += buf.len();
+            staged.ops.push(StagedOp::Write(offset, buf.to_vec()));
+            return Ok(buf.len());
         }
-        let dst = &mut content[offset..offset + buf.len()];
-        dst.copy_from_slice(&buf[..dst.len()]);
+        self.check_growth(offset + buf.len() as u64)?;
+        write_content(&mut self.content.write(), offset, buf);
+        self.times.modified();
         Ok(buf.len())
     }
 
     impl_vfs_non_dir_default! {}
 }
+
+/// A read-only view of a [`FileNode`], sharing its live contents.
+pub(crate) struct ReadOnlyFile {
+    file: Arc<FileNode>,
+}
+
+impl ReadOnlyFile {
+    pub(super) fn new(file: Arc<FileNode>) -> Self {
+        Self { file }
+    }
+
+    pub(super) fn file(&self) -> &FileNode {
+        &self.file
+    }
+}
+
+impl VfsNodeOps for ReadOnlyFile {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        let mut attr = self.file.get_attr()?;
+        attr.set_perm(VfsNodePerm::from_bits_truncate(attr.perm().bits() & 0o555));
+        Ok(attr)
+    }
+
+    fn truncate(&self, _size: u64) -> VfsResult {
+        Err(VfsError::PermissionDenied)
+    }
+
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        self.file.read_at(offset, buf)
+    }
+
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::PermissionDenied)
+    }
+
+    impl_vfs_non_dir_default! {}
+}
@@ -0,0 +1,24 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use axfs_vfs::VfsNodeType;
+
+/// A mutating operation recorded in the journal of a
+/// [`RamFileSystem`](crate::RamFileSystem) created with
+/// [`with_journal`](crate::RamFileSystem::with_journal).
+///
+/// Paths are the ones given to the filesystem, relative to its root.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Op {
+    /// A node of type `ty` was created at `path`.
+    Create { path: String, ty: VfsNodeType },
+    /// The node at `path` was removed.
+    Remove { path: String },
+    /// The node at `old_path` was moved to `new_path`.
+    Rename { old_path: String, new_path: String },
+    /// `data` was written at `offset` in the file at `path`.
+    Write {
+        path: String,
+        offset: u64,
+        data: Vec<u8>,
+    },
+}
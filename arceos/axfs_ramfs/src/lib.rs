@@ -6,23 +6,55 @@
 
 extern crate alloc;
 
+mod cache;
+mod chunk;
+mod device;
 mod dir;
+mod fifo;
 mod file;
+mod journal;
+mod path;
+mod socket;
+mod symlink;
+mod time;
+mod watch;
 
 #[cfg(test)]
 mod tests;
 
-pub use self::dir::DirNode;
-pub use self::file::FileNode;
+pub use self::chunk::{ChunkStore, ChunkedFileNode, CHUNK_SIZE};
+pub use self::device::{register_device, unregister_device, DeviceHandler, DeviceNode};
+pub use self::dir::{DirNode, FullDirEntry, SortMode, MAX_NAME_LEN, MAX_SYMLINK_DEPTH};
+pub use self::fifo::{set_fifo_waiter, FifoNode, FifoWaiter, FIFO_CAPACITY};
+pub use self::file::{FileNode, FileReadGuard, INLINE_CAPACITY};
+pub use self::journal::Op;
+pub use self::path::PathComponents;
+pub use self::socket::SocketNode;
+pub use self::symlink::SymlinkNode;
+pub use self::time::{
+    set_atime_mode, set_relatime_interval, set_time_source, AtimeMode, NodeTimes,
+};
+pub use self::watch::{Watch, WatchEvent, WatchEventKind};
 
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
 use alloc::sync::Arc;
-use axfs_vfs::{VfsNodeRef, VfsOps, VfsResult};
+use alloc::vec::Vec;
+use axfs_vfs::{VfsError, VfsNodeOps, VfsNodeRef, VfsNodeType, VfsOps, VfsResult};
 use spin::once::Once;
+use spin::Mutex;
+
+use self::cache::{cache_key, LookupCache};
+use self::dir::Split;
 
 /// A RAM filesystem that implements [`axfs_vfs::VfsOps`].
 pub struct RamFileSystem {
     parent: Once<VfsNodeRef>,
     root: Arc<DirNode>,
+    cache: Option<Mutex<LookupCache>>,
+    chunks: Option<Arc<ChunkStore>>,
+    journal: Option<Mutex<Vec<Op>>>,
 }
 
 impl RamFileSystem {
@@ -31,6 +63,226 @@ impl RamFileSystem {
         Self {
             parent: Once::new(),
             root: DirNode::new(None),
+            cache: None,
+            chunks: None,
+            journal: None,
+        }
+    }
+
+    /// Create a new instance with a lookup cache holding up to `capacity`
+    /// resolved paths.
+    ///
+    /// The cache is only kept coherent for mutations made through
+    /// [`create`](Self::create), [`remove`](Self::remove) and
+    /// [`rename`](Self::rename) on the filesystem itself. A mutation below a
+    /// [case-insensitive](DirNode::set_case_insensitive) directory clears the
+    /// whole cache, as cached paths may name the same nodes with different
+    /// cases.
+    pub fn with_lookup_cache(capacity: usize) -> Self {
+        Self {
+            cache: Some(Mutex::new(LookupCache::new(capacity))),
+            ..Self::new()
+        }
+    }
+
+    /// Create a new instance whose files share identical [`CHUNK_SIZE`]
+    /// chunks of contents, see [`ChunkedFileNode`].
+    ///
+    /// Only files created through [`create`](Self::create) on the
+    /// filesystem itself are deduplicated.
+    pub fn with_dedup() -> Self {
+        Self {
+            chunks: Some(Arc::new(ChunkStore::new())),
+            ..Self::new()
+        }
+    }
+
+    /// Returns the chunk store of the files, if deduplication is enabled.
+    pub fn chunk_store(&self) -> Option<&Arc<ChunkStore>> {
+        self.chunks.as_ref()
+    }
+
+    /// Create a new instance recording every successful
+    /// [`create`](Self::create), [`remove`](Self::remove),
+    /// [`rename`](Self::rename) and [`write`](Self::write) in a journal,
+    /// from which [`replay`](Self::replay) rebuilds the same tree.
+    ///
+    /// Like the lookup cache, the journal only sees mutations made through
+    /// the filesystem itself.
+    pub fn with_journal() -> Self {
+        Self {
+            journal: Some(Mutex::new(Vec::new())),
+            ..Self::new()
+        }
+    }
+
+    /// Returns a copy of the journal, if journaling is enabled.
+    pub fn journal(&self) -> Option<Vec<Op>> {
+        self.journal.as_ref().map(|journal| journal.lock().clone())
+    }
+
+    /// Applies the operations of `journal` in order, stopping at the first
+    /// one that fails.
+    ///
+    /// Replaying the journal of a filesystem onto a new one gives the same
+    /// tree, as long as only journaled operations mutated it.
+    pub fn replay(&self, journal: &[Op]) -> VfsResult {
+        for op in journal {
+            match op {
+                Op::Create { path, ty } => self.create(path, *ty)?,
+                Op::Remove { path } => self.remove(path)?,
+                Op::Rename { old_path, new_path } => self.rename(old_path, new_path)?,
+                Op::Write { path, offset, data } => {
+                    self.write(path, *offset, data)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn record(&self, op: impl FnOnce() -> Op) {
+        if let Some(journal) = &self.journal {
+            journal.lock().push(op());
+        }
+    }
+
+    /// Looks up `path` from the root, consulting the lookup cache if enabled.
+    pub fn lookup(&self, path: &str) -> VfsResult<VfsNodeRef> {
+        let key = self.cache.as_ref().and_then(|_| cache_key(path));
+        let mut generation = 0;
+        if let (Some(cache), Some(key)) = (&self.cache, &key) {
+            let mut cache = cache.lock();
+            generation = cache.generation();
+            if let Some(node) = cache.get(key) {
+                // the key is the same with or without a trailing `/`, which
+                // only resolves to directories
+                if path.ends_with('/') && !node.get_attr()?.is_dir() {
+                    return Err(VfsError::NotADirectory);
+                }
+                return Ok(node);
+            }
+        }
+        let node = self.root.clone().lookup(path)?;
+        if let (Some(cache), Some(key)) = (&self.cache, key) {
+            cache.lock().insert(key, &node, generation);
+        }
+        Ok(node)
+    }
+
+    /// Creates a node at `path` from the root.
+    pub fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        match &self.chunks {
+            Some(store) if ty == VfsNodeType::File => match self.root.split_last(path)? {
+                Split::Entry(dir, name) => dir.create_chunked_file(name, store),
+                _ => self.root.create(path, ty),
+            },
+            _ => self.root.create(path, ty),
+        }?;
+        self.invalidate(path);
+        self.record(|| Op::Create {
+            path: path.into(),
+            ty,
+        });
+        Ok(())
+    }
+
+    /// Removes the node at `path` from the root.
+    pub fn remove(&self, path: &str) -> VfsResult {
+        self.root.remove(path)?;
+        self.invalidate(path);
+        self.record(|| Op::Remove { path: path.into() });
+        Ok(())
+    }
+
+    /// Renames `old_path` to `new_path` from the root.
+    pub fn rename(&self, old_path: &str, new_path: &str) -> VfsResult {
+        self.root.rename(old_path, new_path)?;
+        self.invalidate(old_path);
+        self.invalidate(new_path);
+        self.record(|| Op::Rename {
+            old_path: old_path.into(),
+            new_path: new_path.into(),
+        });
+        Ok(())
+    }
+
+    /// Writes `buf` at `offset` in the file at `path` from the root, and
+    /// returns the number of bytes written.
+    pub fn write(&self, path: &str, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let written = self.lookup(path)?.write_at(offset, buf)?;
+        self.record(|| Op::Write {
+            path: path.into(),
+            offset,
+            data: buf[..written].into(),
+        });
+        Ok(written)
+    }
+
+    /// Drops the cached paths that `path`, just mutated, may have changed.
+    ///
+    /// Called once the mutation is done: a lookup running concurrently may
+    /// still find the old node, but it is then either dropped here or not
+    /// cached at all, as the cache generation has moved on.
+    fn invalidate(&self, path: &str) {
+        if let Some(cache) = &self.cache {
+            // Paths with `..` are not cached, but may still alias cached ones,
+            // and so may any path differing only by case in a
+            // case-insensitive directory.
+            match cache_key(path) {
+                Some(key) if !self.case_insensitive_on(&key) => cache.lock().invalidate(&key),
+                _ => cache.lock().invalidate(""),
+            }
+        }
+    }
+
+    /// Whether any existing directory on `key`, from the root, is
+    /// case-insensitive.
+    fn case_insensitive_on(&self, key: &str) -> bool {
+        let mut node: VfsNodeRef = self.root.clone();
+        for name in key.split('/') {
+            let Some(dir) = node.as_any().downcast_ref::<DirNode>() else {
+                return false;
+            };
+            if dir.is_case_insensitive() {
+                return true;
+            }
+            let Ok(next) = node.clone().lookup(name) else {
+                return false;
+            };
+            node = next;
+        }
+        false
+    }
+
+    /// Walks the whole tree and checks its invariants, returning a message
+    /// for each violation found.
+    ///
+    /// Every directory must be reachable through exactly one entry, whose
+    /// directory is the one its `..` points to, so that the tree has no
+    /// cycle, and entry names must be valid. Non-directory nodes may be
+    /// referenced from several directories, e.g. after
+    /// [`cow_clone`](DirNode::cow_clone), but the link count of a file must
+    /// match the number of entries referring to it, so directories taken out
+    /// of the tree must not be kept alive. Meant as an oracle for tests that
+    /// mutate the tree heavily.
+    pub fn check_consistency(&self) -> Result<(), Vec<String>> {
+        let mut errors = Vec::new();
+        let mut seen = Vec::new();
+        let mut links = BTreeMap::new();
+        self.root
+            .check_tree("/", &mut seen, &mut links, &mut errors);
+        for (path, link_count, found) in links.into_values() {
+            if link_count != found {
+                errors.push(format!(
+                    "{}: link count is {}, but {} entries refer to it",
+                    path, link_count, found
+                ));
+            }
+        }
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
         }
     }
 
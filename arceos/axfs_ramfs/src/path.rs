@@ -0,0 +1,39 @@
+/// An iterator over the components of a path.
+///
+/// Empty components (from leading, trailing or repeated slashes) and `.` are
+/// skipped, so that `//a/./b/../c/` yields `a`, `b`, `..` then `c`.
+///
+/// A `..` is yielded as is rather than cancelling the component before it:
+/// whether `a/..` names anything depends on `a` being an existing directory,
+/// which only the filesystem can tell.
+#[derive(Debug, Clone)]
+pub struct PathComponents<'a> {
+    rest: &'a str,
+}
+
+impl<'a> PathComponents<'a> {
+    /// Creates an iterator over the components of `path`.
+    pub fn new(path: &'a str) -> Self {
+        Self { rest: path }
+    }
+
+    /// Returns the part of the path not yet iterated over.
+    pub fn as_str(&self) -> &'a str {
+        self.rest
+    }
+}
+
+impl<'a> Iterator for PathComponents<'a> {
+    type Item = &'a str;
+
+    fn next(&mut self) -> Option<&'a str> {
+        while !self.rest.is_empty() {
+            let (name, tail) = self.rest.split_once('/').unwrap_or((self.rest, ""));
+            self.rest = tail;
+            if !name.is_empty() && name != "." {
+                return Some(name);
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,49 @@
+use alloc::sync::{Arc, Weak};
+use axfs_vfs::VfsResult;
+use axfs_vfs::{impl_vfs_non_dir_default, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeType};
+use core::any::Any;
+use spin::RwLock;
+
+/// The Unix domain socket node in the RAM filesystem.
+///
+/// It only marks the path a socket is bound to: the socket itself lives
+/// elsewhere, e.g. in the network stack, and is attached to the node with
+/// [`set_endpoint`](Self::set_endpoint) so that a connect finds it by
+/// looking the path up. The node does not keep the endpoint alive, and
+/// cannot be read or written.
+///
+/// It implements [`axfs_vfs::VfsNodeOps`].
+pub struct SocketNode {
+    endpoint: RwLock<Option<Weak<dyn Any + Send + Sync>>>,
+}
+
+impl SocketNode {
+    pub(super) const fn new() -> Self {
+        Self {
+            endpoint: RwLock::new(None),
+        }
+    }
+
+    /// Attaches the endpoint bound to this node, or detaches it with `None`.
+    pub fn set_endpoint(&self, endpoint: Option<&Arc<dyn Any + Send + Sync>>) {
+        *self.endpoint.write() = endpoint.map(Arc::downgrade);
+    }
+
+    /// Returns the endpoint bound to this node, if it is still alive.
+    pub fn endpoint(&self) -> Option<Arc<dyn Any + Send + Sync>> {
+        self.endpoint.read().as_ref()?.upgrade()
+    }
+}
+
+impl VfsNodeOps for SocketNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::default_file(),
+            VfsNodeType::Socket,
+            0,
+            0,
+        ))
+    }
+
+    impl_vfs_non_dir_default! {}
+}
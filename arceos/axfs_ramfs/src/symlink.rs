@@ -0,0 +1,48 @@
+use alloc::string::String;
+use axfs_vfs::{impl_vfs_non_dir_default, VfsNodeAttr, VfsNodeOps, VfsNodePerm};
+use axfs_vfs::{VfsNodeType, VfsResult};
+use spin::RwLock;
+
+/// The symbolic link node in the RAM filesystem.
+///
+/// It only stores the target path; following it is up to the path
+/// resolution in [`DirNode`](crate::DirNode).
+///
+/// It implements [`axfs_vfs::VfsNodeOps`].
+pub struct SymlinkNode {
+    target: RwLock<String>,
+}
+
+impl SymlinkNode {
+    pub(super) fn new(target: &str) -> Self {
+        Self {
+            target: RwLock::new(target.into()),
+        }
+    }
+
+    /// Returns the target path of the link.
+    pub fn read_link(&self) -> String {
+        self.target.read().clone()
+    }
+
+    /// Points the link to `target`.
+    ///
+    /// The target is not checked: it may not exist, in which case following
+    /// the link fails with [`VfsError::NotFound`](axfs_vfs::VfsError::NotFound).
+    pub fn set_link(&self, target: &str) {
+        *self.target.write() = target.into();
+    }
+}
+
+impl VfsNodeOps for SymlinkNode {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        Ok(VfsNodeAttr::new(
+            VfsNodePerm::from_bits_truncate(0o777),
+            VfsNodeType::SymLink,
+            self.target.read().len() as _,
+            0,
+        ))
+    }
+
+    impl_vfs_non_dir_default! {}
+}
@@ -1,6 +1,8 @@
 use std::sync::Arc;
 
-use axfs_vfs::{VfsError, VfsNodeType, VfsResult};
+use axfs_vfs::{
+    VfsDirEntry, VfsError, VfsNodeAttr, VfsNodeOps, VfsNodePerm, VfsNodeRef, VfsNodeType, VfsResult,
+};
 
 use crate::*;
 
@@ -134,3 +136,1920 @@ fn test_ramfs() {
     assert_eq!(root.remove("./foo"), Ok(()));
     assert!(ramfs.root_dir_node().get_entries().is_empty());
 }
+
+#[test]
+fn test_device_node() {
+    struct Zero;
+
+    impl DeviceHandler for Zero {
+        fn read_at(&self, _offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+            buf.fill(0);
+            Ok(buf.len())
+        }
+
+        fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+            Ok(buf.len())
+        }
+    }
+
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create_device_node("null", VfsNodeType::CharDevice, 1, 3)
+        .unwrap();
+    root.create_device_node("zero", VfsNodeType::CharDevice, 1, 5)
+        .unwrap();
+    assert_eq!(
+        root.create_device_node("f", VfsNodeType::File, 1, 3).err(),
+        Some(VfsError::InvalidInput)
+    );
+
+    let node = root.clone().lookup("null").unwrap();
+    assert_eq!(
+        node.get_attr().unwrap().file_type(),
+        VfsNodeType::CharDevice
+    );
+    let dev = node.as_any().downcast_ref::<DeviceNode>().unwrap();
+    assert_eq!((dev.major(), dev.minor()), (1, 3));
+
+    let mut buf = [1; 4];
+    assert_eq!(node.read_at(0, &mut buf).err(), Some(VfsError::Unsupported));
+
+    register_device(1, 5, Arc::new(Zero));
+    let zero = root.clone().lookup("zero").unwrap();
+    assert_eq!(zero.read_at(0, &mut buf), Ok(4));
+    assert_eq!(buf, [0; 4]);
+    assert_eq!(zero.write_at(0, &buf), Ok(4));
+    unregister_device(1, 5);
+    assert_eq!(zero.read_at(0, &mut buf).err(), Some(VfsError::Unsupported));
+
+    root.create("sda", VfsNodeType::BlockDevice).unwrap();
+    assert_eq!(
+        root.lookup("sda").unwrap().get_attr().unwrap().file_type(),
+        VfsNodeType::BlockDevice
+    );
+}
+
+#[test]
+fn test_atomic_write() {
+    const OLD: [u8; 64] = [b'o'; 64];
+    const NEW: [u8; 128] = [b'n'; 128];
+
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("foo", VfsNodeType::Dir).unwrap();
+    root.atomic_write("foo/f1", &OLD).unwrap();
+
+    let old = root.clone().lookup("foo/f1").unwrap();
+    let reader = {
+        let root = root.clone();
+        std::thread::spawn(move || {
+            let mut buf = [0; 256];
+            for _ in 0..1000 {
+                let node = root.clone().lookup("foo/f1").unwrap();
+                let len = node.read_at(0, &mut buf).unwrap();
+                assert!(buf[..len] == OLD || buf[..len] == NEW);
+            }
+        })
+    };
+    for i in 0..100 {
+        let data: &[u8] = if i % 2 == 0 { &NEW } else { &OLD };
+        root.atomic_write("foo/f1", data).unwrap();
+    }
+    root.atomic_write("/foo/f1", &NEW).unwrap();
+    reader.join().unwrap();
+
+    // a reader holding the old node still sees the old complete contents
+    let mut buf = [0; 256];
+    assert_eq!(old.read_at(0, &mut buf), Ok(OLD.len()));
+    let node = root.clone().lookup("foo/f1").unwrap();
+    assert_eq!(node.read_at(0, &mut buf), Ok(NEW.len()));
+    assert_eq!(buf[..NEW.len()], NEW);
+
+    assert_eq!(
+        root.atomic_write("foo", &NEW).err(),
+        Some(VfsError::IsADirectory)
+    );
+    assert_eq!(
+        root.atomic_write("bar/f1", &NEW).err(),
+        Some(VfsError::NotFound)
+    );
+    assert_eq!(root.atomic_write("foo/", &NEW), Err(VfsError::IsADirectory));
+    assert_eq!(
+        root.atomic_write("foo/.", &NEW),
+        Err(VfsError::IsADirectory)
+    );
+    assert_eq!(
+        root.atomic_write("foo/a\0b", &NEW),
+        Err(VfsError::InvalidInput)
+    );
+    let long = format!("foo/{}", "x".repeat(MAX_NAME_LEN + 1));
+    assert_eq!(root.atomic_write(&long, &NEW), Err(VfsError::InvalidInput));
+    // the temporary files are gone, whether the rename succeeded or not
+    let foo = root.clone().lookup("foo").unwrap();
+    let foo = foo.as_any().downcast_ref::<DirNode>().unwrap();
+    assert_eq!(foo.get_entries(), ["f1"]);
+    assert_eq!(root.get_entries(), ["foo"]);
+    assert_eq!(ramfs.check_consistency(), Ok(()));
+}
+
+#[test]
+fn test_format_listing() {
+    use core::time::Duration;
+
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("foo", VfsNodeType::Dir).unwrap();
+    root.create("foo/bar", VfsNodeType::Dir).unwrap();
+    root.create("f1", VfsNodeType::File).unwrap();
+    let f1 = root.clone().lookup("f1").unwrap();
+    f1.write_at(0, &[0; 32]).unwrap();
+    // other tests may have installed a clock
+    let mtime = |path, secs| {
+        let node = root.clone().lookup(path).unwrap();
+        let mtime = Some(Duration::from_secs(secs));
+        match node.as_any().downcast_ref::<DirNode>() {
+            Some(dir) => dir.set_times(None, mtime),
+            None => node
+                .as_any()
+                .downcast_ref::<FileNode>()
+                .unwrap()
+                .set_times(None, mtime),
+        }
+    };
+    mtime("f1", 1_700_000_000);
+    mtime("foo", 951_782_400);
+    mtime("foo/bar", 0);
+
+    assert_eq!(
+        root.format_listing("/").unwrap(),
+        "-rw-r--r--  1       32 2023-11-14 22:13 f1\n\
+         drwxr-xr-x  3     4096 2000-02-29 00:00 foo\n"
+    );
+    assert_eq!(
+        root.format_listing("foo").unwrap(),
+        "drwxr-xr-x  2     4096 1970-01-01 00:00 bar\n"
+    );
+    assert_eq!(
+        root.format_listing("f1").err(),
+        Some(VfsError::NotADirectory)
+    );
+}
+
+#[test]
+fn test_fifo() {
+    const N: usize = FIFO_CAPACITY * 4;
+
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    root.create("pipe", VfsNodeType::Fifo).unwrap();
+    let fifo = root.clone().lookup("pipe").unwrap();
+    assert_eq!(fifo.get_attr().unwrap().file_type(), VfsNodeType::Fifo);
+
+    // reading an empty FIFO would block
+    let mut buf = [0; 16];
+    assert_eq!(fifo.read_at(0, &mut buf).err(), Some(VfsError::WouldBlock));
+
+    let writer = {
+        let fifo = root.clone().lookup("pipe").unwrap();
+        std::thread::spawn(move || {
+            let data: Vec<u8> = (0..N).map(|i| i as u8).collect();
+            let mut written = 0;
+            while written < N {
+                match fifo.write_at(0, &data[written..]) {
+                    Ok(n) => written += n,
+                    Err(VfsError::WouldBlock) => std::thread::yield_now(),
+                    Err(e) => panic!("unexpected error: {:?}", e),
+                }
+            }
+        })
+    };
+    let reader = std::thread::spawn(move || {
+        let mut received = Vec::new();
+        let mut buf = [0; 100];
+        while received.len() < N {
+            match fifo.read_at(0, &mut buf) {
+                Ok(n) => received.extend_from_slice(&buf[..n]),
+                Err(VfsError::WouldBlock) => std::thread::yield_now(),
+                Err(e) => panic!("unexpected error: {:?}", e),
+            }
+        }
+        received
+    });
+    writer.join().unwrap();
+    let received = reader.join().unwrap();
+    assert!(received.iter().enumerate().all(|(i, &b)| b == i as u8));
+
+    // writing a full FIFO would block
+    let fifo = root.lookup("pipe").unwrap();
+    assert_eq!(fifo.write_at(0, &[0; N]), Ok(FIFO_CAPACITY));
+    assert_eq!(fifo.write_at(0, &[0; 1]).err(), Some(VfsError::WouldBlock));
+    assert_eq!(fifo.get_attr().unwrap().size(), FIFO_CAPACITY as u64);
+}
+
+#[test]
+fn test_fifo_blocking() {
+    use std::sync::{Condvar, Mutex};
+    use std::time::Duration;
+
+    const N: usize = FIFO_CAPACITY * 4;
+
+    // stands for a wait queue of the scheduler
+    static LOCK: Mutex<()> = Mutex::new(());
+    static WAKE: Condvar = Condvar::new();
+    let waiter = FifoWaiter {
+        wait_until: |ready| {
+            let mut guard = LOCK.lock().unwrap();
+            while !ready() {
+                guard = WAKE.wait(guard).unwrap();
+            }
+        },
+        wake: || {
+            let _guard = LOCK.lock().unwrap();
+            WAKE.notify_all();
+        },
+    };
+
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir();
+    root.create("pipe", VfsNodeType::Fifo).unwrap();
+    let fifo = root.lookup("pipe").unwrap();
+    let node = fifo.as_any().downcast_ref::<FifoNode>().unwrap();
+    node.set_waiter(Some(waiter));
+
+    // the reader blocks on the empty FIFO until the writer comes
+    let reader = {
+        let fifo = fifo.clone();
+        std::thread::spawn(move || {
+            let mut received = Vec::new();
+            let mut buf = [0; 100];
+            while received.len() < N {
+                let n = fifo.read_at(0, &mut buf).unwrap();
+                assert_ne!(n, 0);
+                received.extend_from_slice(&buf[..n]);
+            }
+            received
+        })
+    };
+    std::thread::sleep(Duration::from_millis(20));
+    assert!(!reader.is_finished());
+
+    // a single write, blocking whenever the FIFO is full
+    let data: Vec<u8> = (0..N).map(|i| i as u8).collect();
+    assert_eq!(fifo.write_at(0, &data), Ok(N));
+    assert_eq!(reader.join().unwrap(), data);
+
+    node.set_waiter(None);
+    let mut buf = [0; 1];
+    assert_eq!(fifo.read_at(0, &mut buf), Err(VfsError::WouldBlock));
+}
+
+#[test]
+fn test_make_temp() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    let mut names = Vec::new();
+    for _ in 0..100 {
+        let (name, node) = root.make_temp(".tmp").unwrap();
+        assert!(name.starts_with(".tmp"));
+        let found = root.clone().lookup(&name).unwrap();
+        assert!(Arc::ptr_eq(&(node as VfsNodeRef), &found));
+        names.push(name);
+    }
+    names.sort();
+    names.dedup();
+    assert_eq!(names.len(), 100);
+    assert_eq!(root.get_entries().len(), 100);
+
+    // the sequence is deterministic across filesystem instances
+    let (name, _) = RamFileSystem::new()
+        .root_dir_node()
+        .make_temp(".tmp")
+        .unwrap();
+    assert!(names.contains(&name));
+
+    // prefixes that cannot start a valid name add nothing
+    let long = "a".repeat(MAX_NAME_LEN - 15);
+    for prefix in ["a/", "a\0", &long] {
+        assert_eq!(root.make_temp(prefix).err(), Some(VfsError::InvalidInput));
+    }
+    assert!(root.make_temp(&long[1..]).is_ok());
+    assert_eq!(root.get_entries().len(), 101);
+}
+
+#[test]
+fn test_cow_clone() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("foo", VfsNodeType::Dir).unwrap();
+    root.create("foo/bar", VfsNodeType::Dir).unwrap();
+    root.create("foo/bar/f1", VfsNodeType::File).unwrap();
+    root.create("foo/f2", VfsNodeType::File).unwrap();
+
+    let foo = root.clone().lookup("foo").unwrap();
+    let foo = foo.as_any().downcast_ref::<DirNode>().unwrap();
+    let copy = foo.cow_clone();
+    copy.create("bar/f3", VfsNodeType::File).unwrap();
+    copy.remove("f2").unwrap();
+
+    let mut entries = foo.get_entries();
+    entries.sort();
+    assert_eq!(entries, ["bar", "f2"]);
+    assert_eq!(
+        root.clone().lookup("foo/bar/f3").err(),
+        Some(VfsError::NotFound)
+    );
+    assert!(copy.clone().lookup("bar/f3").is_ok());
+
+    // unmodified files are shared by both trees
+    let f1 = root.clone().lookup("foo/bar/f1").unwrap();
+    assert!(Arc::ptr_eq(&f1, &copy.clone().lookup("bar/f1").unwrap()));
+    assert_eq!(Arc::strong_count(&f1), 3);
+
+    // `..` of the copied subdirectory leads to the copy
+    let parent = copy.clone().lookup("bar/..").unwrap();
+    assert!(Arc::ptr_eq(&parent, &(copy.clone() as VfsNodeRef)));
+    assert!(Arc::ptr_eq(
+        &copy.lookup("..").unwrap(),
+        &(root as VfsNodeRef)
+    ));
+}
+
+#[test]
+fn test_lookup_cache() {
+    let ramfs = RamFileSystem::with_lookup_cache(4);
+    ramfs.create("a", VfsNodeType::Dir).unwrap();
+    ramfs.create("a/b", VfsNodeType::Dir).unwrap();
+    ramfs.create("a/b/f", VfsNodeType::File).unwrap();
+
+    let node = ramfs.lookup("/a/b/f").unwrap();
+    let cached = ramfs.lookup("a/./b/f").unwrap();
+    assert!(Arc::ptr_eq(&node, &cached));
+
+    // `node` keeps the file alive, so only invalidation hides it.
+    ramfs.remove("a/b/f").unwrap();
+    assert_eq!(ramfs.lookup("a/b/f").err(), Some(VfsError::NotFound));
+
+    // a trailing `/` still requires a directory once the file is cached
+    ramfs.create("a/g", VfsNodeType::File).unwrap();
+    ramfs.lookup("a/g").unwrap();
+    assert_eq!(ramfs.lookup("a/g/").err(), Some(VfsError::NotADirectory));
+    assert!(ramfs.lookup("a/b/").is_ok());
+
+    ramfs.lookup("a/b").unwrap();
+    ramfs.rename("/a", "/c").unwrap();
+    assert_eq!(ramfs.lookup("a/b").err(), Some(VfsError::NotFound));
+    assert!(ramfs.lookup("c/b").is_ok());
+
+    // paths differing by case in a case-insensitive subdirectory
+    ramfs.create("d", VfsNodeType::Dir).unwrap();
+    let d = ramfs.lookup("d").unwrap();
+    let d = d.as_any().downcast_ref::<DirNode>().unwrap();
+    d.set_case_insensitive(true);
+    ramfs.create("d/foo", VfsNodeType::File).unwrap();
+    ramfs.lookup("d/Foo").unwrap();
+    ramfs.remove("d/foo").unwrap();
+    assert_eq!(ramfs.lookup("d/Foo").err(), Some(VfsError::NotFound));
+}
+
+#[test]
+fn test_lookup_cache_concurrent() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    let ramfs = Arc::new(RamFileSystem::with_lookup_cache(4));
+    let stop = Arc::new(AtomicBool::new(false));
+
+    // keeps the nodes it finds alive, so that a stale entry would resolve
+    let lookups = {
+        let ramfs = ramfs.clone();
+        let stop = stop.clone();
+        std::thread::spawn(move || {
+            let mut found = Vec::new();
+            while !stop.load(Ordering::Relaxed) {
+                found.extend(ramfs.lookup("f").ok());
+            }
+            found
+        })
+    };
+    for _ in 0..2000 {
+        ramfs.create("f", VfsNodeType::File).unwrap();
+        ramfs.remove("f").unwrap();
+        assert_eq!(ramfs.lookup("f").err(), Some(VfsError::NotFound));
+    }
+    stop.store(true, Ordering::Relaxed);
+    let found = lookups.join().unwrap();
+    assert_eq!(ramfs.lookup("f").err(), Some(VfsError::NotFound));
+    drop(found);
+}
+
+#[test]
+fn test_read_dir_full() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    let names = ["a", "b", "c", "d", "e"];
+    for name in names {
+        root.create(name, VfsNodeType::File).unwrap();
+    }
+    root.create("sub", VfsNodeType::Dir).unwrap();
+
+    let mut entries = Vec::new();
+    let mut cookie = 0;
+    let mut page: [FullDirEntry; 3] = Default::default();
+    loop {
+        let (n, next) = root.read_dir_full(cookie, &mut page).unwrap();
+        if n == 0 {
+            break;
+        }
+        assert_eq!(next, cookie + n as u64);
+        entries.extend_from_slice(&page[..n]);
+        cookie = next;
+    }
+
+    let got: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+    assert_eq!(got, [".", "..", "a", "b", "c", "d", "e", "sub"]);
+    let root_ino = entries[0].ino;
+    assert_eq!(entries[1].ino, root_ino); // the root is its own parent
+
+    for ent in &entries[2..] {
+        let node = root.clone().lookup(&ent.name).unwrap();
+        assert_eq!(ent.ty, node.get_attr().unwrap().file_type());
+        assert_eq!(ent.ino, Arc::as_ptr(&node) as *const () as u64);
+        assert_ne!(ent.ino, root_ino);
+    }
+
+    let sub = root.clone().lookup("sub").unwrap();
+    let sub = sub.as_any().downcast_ref::<DirNode>().unwrap();
+    let (n, _) = sub.read_dir_full(0, &mut page).unwrap();
+    assert_eq!(n, 2);
+    assert_eq!(page[1].ino, root_ino);
+}
+
+#[test]
+fn test_staged_writes() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("f", VfsNodeType::File).unwrap();
+    let node = root.clone().lookup("f").unwrap();
+    let file = node.as_any().downcast_ref::<FileNode>().unwrap();
+    file.write_at(0, b"old data").unwrap();
+
+    let mut buf = [0; 16];
+    file.enable_staging(16);
+    file.write_at(0, b"new").unwrap();
+    file.truncate(5).unwrap();
+    assert_eq!(file.staged_bytes(), 3);
+    let n = file.read_at(0, &mut buf).unwrap();
+    assert_eq!(&buf[..n], b"old data");
+
+    file.commit().unwrap();
+    let n = file.read_at(0, &mut buf).unwrap();
+    assert_eq!(&buf[..n], b"new d");
+
+    file.write_at(0, b"discarded").unwrap();
+    assert_eq!(
+        file.write_at(0, b"too much data").err(),
+        Some(VfsError::StorageFull)
+    );
+    file.rollback();
+    assert_eq!(file.staged_bytes(), 0);
+    let n = file.read_at(0, &mut buf).unwrap();
+    assert_eq!(&buf[..n], b"new d");
+
+    file.write_at(5, b"!").unwrap();
+    file.disable_staging().unwrap();
+    let n = file.read_at(0, &mut buf).unwrap();
+    assert_eq!(&buf[..n], b"new d!");
+}
+
+#[test]
+fn test_quota() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("a", VfsNodeType::Dir).unwrap();
+    root.create("a/b", VfsNodeType::Dir).unwrap();
+    root.create("a/b/f", VfsNodeType::File).unwrap();
+    root.create("g", VfsNodeType::File).unwrap();
+
+    let a = root.clone().lookup("a").unwrap();
+    let a = a.as_any().downcast_ref::<DirNode>().unwrap();
+    a.set_quota(100);
+    assert_eq!(a.quota_usage(), (0, Some(100)));
+
+    let f = root.clone().lookup("a/b/f").unwrap();
+    assert_eq!(f.write_at(0, &[1; 100]), Ok(100));
+    assert_eq!(a.quota_usage(), (100, Some(100)));
+    assert_eq!(f.write_at(100, &[1]), Err(VfsError::StorageFull));
+    assert_eq!(f.truncate(101), Err(VfsError::StorageFull));
+    // overwriting in place does not grow the file
+    assert_eq!(f.write_at(0, &[2; 10]), Ok(10));
+    assert_eq!(
+        root.create("a/b/h", VfsNodeType::File),
+        Err(VfsError::StorageFull)
+    );
+    assert_eq!(root.atomic_write("a/b/h", b"x"), Err(VfsError::StorageFull));
+
+    let g = root.clone().lookup("g").unwrap();
+    assert_eq!(g.write_at(0, &[1; 200]), Ok(200));
+
+    f.truncate(50).unwrap();
+    assert_eq!(f.write_at(50, &[3; 50]), Ok(50));
+
+    // staged modifications are checked as a whole when committed
+    let file = f.as_any().downcast_ref::<FileNode>().unwrap();
+    file.enable_staging(256);
+    file.truncate(10).unwrap();
+    file.write_at(200, &[4]).unwrap();
+    assert_eq!(file.commit(), Err(VfsError::StorageFull));
+    assert_eq!(file.disable_staging(), Err(VfsError::StorageFull));
+    assert_eq!(f.get_attr().unwrap().size(), 100);
+    assert_eq!(file.staged_bytes(), 1);
+    file.rollback();
+    file.truncate(10).unwrap();
+    file.write_at(99, &[4]).unwrap();
+    file.disable_staging().unwrap();
+    assert_eq!(a.quota_usage(), (100, Some(100)));
+    a.clear_quota();
+    assert_eq!(f.write_at(100, &[1]), Ok(1));
+}
+
+#[test]
+fn test_symlink_loop() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("d", VfsNodeType::Dir).unwrap();
+    root.create("d/f", VfsNodeType::File).unwrap();
+    root.create_symlink("to_d", "d").unwrap();
+    root.create_symlink("abs", "/to_d/f").unwrap();
+    root.create_symlink("a", "b").unwrap();
+    root.create_symlink("b", "a").unwrap();
+    root.create_symlink("self", "self/x").unwrap();
+
+    let f = root.clone().lookup("d/f").unwrap();
+    let sub = root.clone().lookup("d").unwrap();
+    let sub = sub.as_any().downcast_ref::<DirNode>().unwrap();
+    for (dir, path) in [(&*root, "to_d/f"), (&*root, "abs"), (sub, "../abs")] {
+        let node = dir.lookup_follow(path).unwrap();
+        assert!(Arc::ptr_eq(&node, &f));
+    }
+
+    assert_eq!(root.lookup_follow("a").err(), Some(VfsError::InvalidInput));
+    assert_eq!(
+        root.lookup_follow("self").err(),
+        Some(VfsError::InvalidInput)
+    );
+    assert_eq!(
+        root.lookup_follow("to_d/missing").err(),
+        Some(VfsError::NotFound)
+    );
+}
+
+#[test]
+fn test_create_symlink_node() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("f", VfsNodeType::File).unwrap();
+    root.create("l", VfsNodeType::SymLink).unwrap();
+    let node = root.clone().lookup("l").unwrap();
+    assert_eq!(node.get_attr().unwrap().file_type(), VfsNodeType::SymLink);
+    let link = node.as_any().downcast_ref::<SymlinkNode>().unwrap();
+    assert_eq!(link.read_link(), "");
+
+    link.set_link("f");
+    assert_eq!(root.readlink("l").unwrap(), "f");
+    assert_eq!(node.get_attr().unwrap().size(), 1);
+    let f = root.clone().lookup("f").unwrap();
+    assert!(Arc::ptr_eq(&root.lookup_follow("l").unwrap(), &f));
+
+    // dangling
+    link.set_link("missing");
+    assert_eq!(root.lookup_follow("l").err(), Some(VfsError::NotFound));
+    root.create_symlink("l2", "/gone/f").unwrap();
+    assert_eq!(root.lookup_follow("l2").err(), Some(VfsError::NotFound));
+    assert!(root.clone().lookup("l2").is_ok());
+}
+
+#[test]
+fn test_readlink_resolve() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("d", VfsNodeType::Dir).unwrap();
+    root.create("d/f", VfsNodeType::File).unwrap();
+    root.create_symlink("l1", "d/f").unwrap();
+    root.create_symlink("l2", "/l1").unwrap();
+
+    assert_eq!(root.readlink("l2").unwrap(), "/l1");
+    assert_eq!(root.readlink("l1").unwrap(), "d/f");
+    assert_eq!(root.readlink("d/f").err(), Some(VfsError::InvalidInput));
+    assert_eq!(root.readlink("missing").err(), Some(VfsError::NotFound));
+
+    let f = root.clone().lookup("d/f").unwrap();
+    for (path, hops) in [("d/f", 0), ("l1", 1), ("l2", 2)] {
+        let (node, depth) = root.resolve(path).unwrap();
+        assert!(Arc::ptr_eq(&node, &f));
+        assert_eq!(depth, hops);
+    }
+}
+
+#[test]
+fn test_bind_file_ro() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("etc", VfsNodeType::Dir).unwrap();
+    root.create("x", VfsNodeType::Dir).unwrap();
+    root.create("y", VfsNodeType::Dir).unwrap();
+    let (_, conf) = root.make_temp("conf").unwrap();
+    conf.write_at(0, b"v1").unwrap();
+
+    for dir in ["x", "y"] {
+        let dir = root.clone().lookup(dir).unwrap();
+        let dir = dir.as_any().downcast_ref::<DirNode>().unwrap();
+        dir.bind_file_ro("conf", conf.clone()).unwrap();
+        assert_eq!(
+            dir.bind_file_ro("conf", conf.clone()),
+            Err(VfsError::AlreadyExists)
+        );
+    }
+
+    conf.write_at(0, b"v2!").unwrap();
+    let mut buf = [0; 8];
+    for path in ["x/conf", "y/conf"] {
+        let view = root.clone().lookup(path).unwrap();
+        let n = view.read_at(0, &mut buf).unwrap();
+        assert_eq!(&buf[..n], b"v2!");
+        let attr = view.get_attr().unwrap();
+        assert_eq!(attr.size(), 3);
+        assert!(!attr.perm().owner_writable());
+        assert_eq!(view.write_at(0, b"x"), Err(VfsError::PermissionDenied));
+        assert_eq!(view.truncate(0), Err(VfsError::PermissionDenied));
+    }
+    let n = conf.read_at(0, &mut buf).unwrap();
+    assert_eq!(&buf[..n], b"v2!");
+}
+
+#[test]
+fn test_handle_count() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    let (name, file) = root.make_temp("tmp").unwrap();
+    assert_eq!(file.handle_count(), 1);
+
+    let h1 = root.clone().lookup(&name).unwrap();
+    let h2 = root.clone().lookup(&name).unwrap();
+    assert_eq!(file.handle_count(), 3);
+    drop(h1);
+    assert_eq!(file.handle_count(), 2);
+    drop(h2);
+    assert_eq!(file.handle_count(), 1);
+
+    // once unlinked, the directory no longer holds a reference
+    root.remove(&name).unwrap();
+    assert_eq!(file.handle_count(), 1);
+}
+
+#[test]
+fn test_reserve() {
+    const SIZE: usize = 1 << 20;
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    let (_, file) = root.make_temp("big").unwrap();
+
+    file.reserve(SIZE as u64).unwrap();
+    let capacity = file.capacity();
+    assert!(capacity >= SIZE);
+    assert_eq!(file.get_attr().unwrap().size(), 0);
+
+    let chunk = [0x5a; 4096];
+    for offset in (0..SIZE).step_by(chunk.len()) {
+        file.write_at(offset as u64, &chunk).unwrap();
+        assert_eq!(file.capacity(), capacity);
+    }
+    assert_eq!(file.get_attr().unwrap().size(), SIZE as u64);
+
+    // reserving less than the current size is a no-op
+    file.reserve(16).unwrap();
+    assert_eq!(file.capacity(), capacity);
+}
+
+#[test]
+fn test_content_hash() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    for dir in ["x", "y"] {
+        root.create(dir, VfsNodeType::Dir).unwrap();
+        root.atomic_write(&format!("{}/a", dir), b"same").unwrap();
+        root.create(&format!("{}/sub", dir), VfsNodeType::Dir)
+            .unwrap();
+    }
+    root.atomic_write("x/b", b"different").unwrap();
+    root.atomic_write("y/b", b"differenT").unwrap();
+
+    let hash = |path: &str| {
+        let node = root.clone().lookup(path).unwrap();
+        node.as_any()
+            .downcast_ref::<FileNode>()
+            .unwrap()
+            .content_hash()
+    };
+    assert_eq!(hash("x/a"), hash("y/a"));
+    assert_ne!(hash("x/b"), hash("y/b"));
+    assert_ne!(hash("x/a"), hash("x/b"));
+
+    let tree_hash = |path: &str| {
+        let node = root.clone().lookup(path).unwrap();
+        node.as_any().downcast_ref::<DirNode>().unwrap().tree_hash()
+    };
+    assert_ne!(tree_hash("x"), tree_hash("y"));
+    root.atomic_write("y/b", b"different").unwrap();
+    assert_eq!(tree_hash("x"), tree_hash("y"));
+    root.create("y/sub/c", VfsNodeType::File).unwrap();
+    assert_ne!(tree_hash("x"), tree_hash("y"));
+
+    // files split into shared chunks hash like plain ones
+    let ramfs = RamFileSystem::with_dedup();
+    let root = ramfs.root_dir_node();
+    let data = vec![7; CHUNK_SIZE + 10];
+    for dir in ["x", "y"] {
+        ramfs.create(dir, VfsNodeType::Dir).unwrap();
+        ramfs
+            .create(&format!("{}/a", dir), VfsNodeType::File)
+            .unwrap();
+        ramfs.write(&format!("{}/a", dir), 0, &data).unwrap();
+    }
+    let chunked = root.clone().lookup("x/a").unwrap();
+    let chunked = chunked.as_any().downcast_ref::<ChunkedFileNode>().unwrap();
+    assert_eq!(
+        chunked.content_hash(),
+        FileNode::from_vec(data).content_hash()
+    );
+    let tree_hash = |path: &str| {
+        let node = root.clone().lookup(path).unwrap();
+        node.as_any().downcast_ref::<DirNode>().unwrap().tree_hash()
+    };
+    assert_eq!(tree_hash("x"), tree_hash("y"));
+    ramfs.write("y/a", CHUNK_SIZE as u64 + 9, b"8").unwrap();
+    assert_ne!(tree_hash("x"), tree_hash("y"));
+}
+
+#[test]
+fn test_rename_into_itself() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("a", VfsNodeType::Dir).unwrap();
+    root.create("a/b", VfsNodeType::Dir).unwrap();
+
+    for dst in ["/a/b/a", "/a/a"] {
+        assert_eq!(root.rename("/a", dst), Err(VfsError::InvalidInput));
+    }
+    assert!(root.clone().lookup("a/b").is_ok());
+
+    root.rename("/a", "/c").unwrap();
+    assert!(root.clone().lookup("c/b").is_ok());
+    assert_eq!(root.clone().lookup("a").err(), Some(VfsError::NotFound));
+}
+
+#[test]
+fn test_rename_across_dirs() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    for dir in ["a", "b", "a/sub", "a/sub/deep"] {
+        root.create(dir, VfsNodeType::Dir).unwrap();
+    }
+    root.create("a/f", VfsNodeType::File).unwrap();
+    root.create("a/sub/deep/g", VfsNodeType::File).unwrap();
+    root.create("b/taken", VfsNodeType::File).unwrap();
+
+    // same directory
+    let f = root.clone().lookup("a/f").unwrap();
+    root.rename("a/f", "a/f2").unwrap();
+    assert!(Arc::ptr_eq(&root.clone().lookup("a/f2").unwrap(), &f));
+    assert_eq!(root.clone().lookup("a/f").err(), Some(VfsError::NotFound));
+
+    // another directory, under a new name
+    root.rename("/a/f2", "/b/f").unwrap();
+    assert!(Arc::ptr_eq(&root.clone().lookup("b/f").unwrap(), &f));
+    assert_eq!(root.clone().lookup("a/f2").err(), Some(VfsError::NotFound));
+
+    // a subtree, whose parent links follow
+    let sub = root.clone().lookup("a/sub").unwrap();
+    root.rename("a/sub", "b/moved").unwrap();
+    assert!(Arc::ptr_eq(&root.clone().lookup("b/moved").unwrap(), &sub));
+    assert!(root.clone().lookup("b/moved/deep/g").is_ok());
+    let b = root.clone().lookup("b").unwrap();
+    assert!(Arc::ptr_eq(&sub.parent().unwrap(), &b));
+    assert!(Arc::ptr_eq(
+        &root.clone().lookup("b/moved/deep/../..").unwrap(),
+        &b
+    ));
+    assert_eq!(root.clone().lookup("a/sub").err(), Some(VfsError::NotFound));
+
+    // missing sources
+    assert_eq!(root.rename("a/f", "b/none"), Err(VfsError::NotFound));
+    assert_eq!(root.rename("b/f", "none/f"), Err(VfsError::NotFound));
+    assert!(Arc::ptr_eq(&root.clone().lookup("b/f").unwrap(), &f));
+    root.rename("b/f", "b/f").unwrap();
+    assert!(Arc::ptr_eq(&root.clone().lookup("b/f").unwrap(), &f));
+}
+
+#[test]
+fn test_rename_overwrite() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    for dir in ["d", "d/sub", "empty", "full"] {
+        root.create(dir, VfsNodeType::Dir).unwrap();
+    }
+    for file in ["f", "g", "full/x", "d/sub/y"] {
+        root.create(file, VfsNodeType::File).unwrap();
+    }
+
+    // a file replaces a file
+    let f = root.clone().lookup("f").unwrap();
+    root.rename("f", "g").unwrap();
+    assert!(Arc::ptr_eq(&root.clone().lookup("g").unwrap(), &f));
+    assert_eq!(root.clone().lookup("f").err(), Some(VfsError::NotFound));
+
+    // a directory replaces only an empty directory
+    let err = |old, new| root.rename(old, new).err();
+    assert_eq!(err("d", "full"), Some(VfsError::DirectoryNotEmpty));
+    assert_eq!(err("d/sub", "d"), Some(VfsError::DirectoryNotEmpty));
+    root.create("d/sub/w", VfsNodeType::Dir).unwrap();
+    assert_eq!(err("d/sub/w", "d"), Some(VfsError::DirectoryNotEmpty));
+    root.remove("d/sub/w").unwrap();
+    assert_eq!(err("d", "g"), Some(VfsError::NotADirectory));
+    assert_eq!(err("g", "full"), Some(VfsError::IsADirectory));
+    assert!(root.clone().lookup("full/x").is_ok());
+    let sub = root.clone().lookup("d/sub").unwrap();
+    root.rename("d/sub", "empty").unwrap();
+    assert!(Arc::ptr_eq(&root.clone().lookup("empty").unwrap(), &sub));
+    assert!(root.clone().lookup("empty/y").is_ok());
+
+    // a name renamed onto itself is left alone
+    root.rename("g", "g").unwrap();
+    assert!(Arc::ptr_eq(&root.clone().lookup("g").unwrap(), &f));
+}
+
+#[test]
+fn test_rename_reparents() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    for dir in ["x", "y", "x/sub"] {
+        root.create(dir, VfsNodeType::Dir).unwrap();
+    }
+    root.rename("/x/sub", "/y/sub").unwrap();
+
+    let y = root.clone().lookup("/y").unwrap();
+    assert!(Arc::ptr_eq(&root.clone().lookup("/y/sub/..").unwrap(), &y));
+    // `..` from the moved directory itself goes through its parent link
+    let sub = root.clone().lookup("/y/sub").unwrap();
+    assert!(Arc::ptr_eq(&sub.clone().lookup("..").unwrap(), &y));
+    sub.create("../from_sub", VfsNodeType::File).unwrap();
+    assert!(root.clone().lookup("/y/from_sub").is_ok());
+    assert_eq!(
+        root.clone().lookup("/x/from_sub").err(),
+        Some(VfsError::NotFound)
+    );
+}
+
+#[test]
+fn test_recursive_watch() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("a", VfsNodeType::Dir).unwrap();
+    let a = root.clone().lookup("a").unwrap();
+    let a = a.as_any().downcast_ref::<DirNode>().unwrap();
+    let recursive = a.watch(true);
+    let direct = a.watch(false);
+
+    // directories created after the watch are covered too
+    root.create("a/b", VfsNodeType::Dir).unwrap();
+    root.create("a/b/c", VfsNodeType::Dir).unwrap();
+    root.create("a/b/c/file", VfsNodeType::File).unwrap();
+    root.create("other", VfsNodeType::File).unwrap();
+
+    let created = |path: &str| WatchEvent {
+        kind: WatchEventKind::Create,
+        path: path.into(),
+    };
+    let events: Vec<_> = core::iter::from_fn(|| recursive.poll()).collect();
+    assert_eq!(events, [created("b"), created("b/c"), created("b/c/file")]);
+    assert_eq!(direct.poll(), Some(created("b")));
+    assert_eq!(direct.poll(), None);
+
+    root.remove("a/b/c/file").unwrap();
+    assert_eq!(
+        recursive.poll(),
+        Some(WatchEvent {
+            kind: WatchEventKind::Remove,
+            path: "b/c/file".into(),
+        })
+    );
+    assert_eq!(direct.poll(), None);
+
+    drop(recursive);
+    root.create("a/b/c/again", VfsNodeType::File).unwrap();
+    assert_eq!(direct.poll(), None);
+}
+
+#[test]
+fn test_fallocate() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("f", VfsNodeType::File).unwrap();
+    let node = root.clone().lookup("f").unwrap();
+    let file = node.as_any().downcast_ref::<FileNode>().unwrap();
+    file.write_at(0, b"hello").unwrap();
+
+    file.fallocate(8, 4).unwrap();
+    assert_eq!(node.get_attr().unwrap().size(), 12);
+    let mut buf = [0xff; 12];
+    assert_eq!(file.read_at(0, &mut buf).unwrap(), 12);
+    assert_eq!(&buf, b"hello\0\0\0\0\0\0\0");
+
+    // ranges inside the file change nothing
+    file.fallocate(0, 3).unwrap();
+    assert_eq!(node.get_attr().unwrap().size(), 12);
+    assert_eq!(file.read_at(0, &mut buf[..5]).unwrap(), 5);
+    assert_eq!(&buf[..5], b"hello");
+
+    assert_eq!(file.fallocate(u64::MAX, 1), Err(VfsError::InvalidInput));
+}
+
+#[test]
+fn test_concurrent_rename() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    for name in ["a", "b", "c", "d"] {
+        root.create(name, VfsNodeType::File).unwrap();
+    }
+
+    // each task moves its own file back and forth, racing with the others
+    let tasks: Vec<_> = [("/a", "/x"), ("/b", "/y"), ("/c", "/z"), ("/d", "/w")]
+        .into_iter()
+        .map(|(from, to)| {
+            let root = root.clone();
+            std::thread::spawn(move || {
+                for _ in 0..500 {
+                    root.rename(from, to).unwrap();
+                    root.rename(to, from).unwrap();
+                }
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.join().unwrap();
+    }
+
+    for name in ["a", "b", "c", "d"] {
+        assert!(root.clone().lookup(name).is_ok());
+    }
+    for name in ["x", "y", "z", "w"] {
+        assert_eq!(root.clone().lookup(name).err(), Some(VfsError::NotFound));
+    }
+
+    // reciprocal moves between two directories: each rename locks both,
+    // which would deadlock if the source were always locked first
+    for path in ["m", "n", "m/f", "n/g"] {
+        let ty = if path.contains('/') {
+            VfsNodeType::File
+        } else {
+            VfsNodeType::Dir
+        };
+        root.create(path, ty).unwrap();
+    }
+    let tasks: Vec<_> = [("m/f", "n/f"), ("n/g", "m/g")]
+        .into_iter()
+        .map(|(from, to)| {
+            let root = root.clone();
+            std::thread::spawn(move || {
+                for _ in 0..500 {
+                    root.rename(from, to).unwrap();
+                    root.rename(to, from).unwrap();
+                }
+            })
+        })
+        .collect();
+    for task in tasks {
+        task.join().unwrap();
+    }
+    for (path, found) in [("m/f", true), ("n/g", true), ("n/f", false), ("m/g", false)] {
+        assert_eq!(root.clone().lookup(path).is_ok(), found);
+    }
+    assert_eq!(ramfs.check_consistency(), Ok(()));
+}
+
+#[test]
+fn test_path_components() {
+    let comps = |path| PathComponents::new(path).collect::<Vec<_>>();
+    assert_eq!(comps("//a/./b/../c/"), ["a", "b", "..", "c"]);
+    assert_eq!(comps("a//b///c"), ["a", "b", "c"]);
+    assert_eq!(comps("../a/./.."), ["..", "a", ".."]);
+    assert_eq!(comps(""), [] as [&str; 0]);
+    assert_eq!(comps("./"), [] as [&str; 0]);
+    let mut it = PathComponents::new("a/./b/");
+    assert_eq!(it.next(), Some("a"));
+    assert_eq!(it.as_str(), "./b/");
+
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("//a//", VfsNodeType::Dir).unwrap();
+    root.create("a/./b", VfsNodeType::Dir).unwrap();
+    root.create("a/./b/../c", VfsNodeType::File).unwrap();
+    assert!(root.clone().lookup("//a//c").is_ok());
+    assert!(root.clone().lookup("a/b/../c").is_ok());
+    assert_eq!(
+        root.clone().lookup("a/c/").err(),
+        Some(VfsError::NotADirectory)
+    );
+    // `..` is resolved step by step, not cancelled lexically
+    assert_eq!(
+        root.clone().lookup("a/c/..").err(),
+        Some(VfsError::NotADirectory)
+    );
+    assert_eq!(root.remove("a/x/../c"), Err(VfsError::NotFound));
+    assert_eq!(root.remove("a/b/.."), Err(VfsError::InvalidInput));
+    assert_eq!(root.remove("a/b/."), Err(VfsError::InvalidInput));
+    assert_eq!(root.rename("a/b/..", "d"), Err(VfsError::InvalidInput));
+    assert!(root.clone().lookup("a/b").is_ok());
+    root.remove("a/b/../c").unwrap();
+    assert_eq!(root.clone().lookup("a/c").err(), Some(VfsError::NotFound));
+}
+
+/// A directory of some other filesystem, with its parent, to mount a ramfs
+/// in.
+struct ForeignDir(VfsNodeRef, Option<VfsNodeRef>);
+
+impl VfsNodeOps for ForeignDir {
+    fn get_attr(&self) -> VfsResult<VfsNodeAttr> {
+        self.0.get_attr()
+    }
+
+    fn parent(&self) -> Option<VfsNodeRef> {
+        self.1.clone()
+    }
+
+    fn lookup(self: Arc<Self>, path: &str) -> VfsResult<VfsNodeRef> {
+        self.0.clone().lookup(path)
+    }
+
+    fn create(&self, path: &str, ty: VfsNodeType) -> VfsResult {
+        self.0.create(path, ty)
+    }
+
+    fn remove(&self, path: &str) -> VfsResult {
+        self.0.remove(path)
+    }
+
+    axfs_vfs::impl_vfs_dir_default! {}
+}
+
+#[test]
+fn test_dotdot_above_mount() {
+    // `..` above the root of a mounted ramfs is resolved by the parent
+    let outer = RamFileSystem::new();
+    let outer_root = outer.root_dir_node();
+    outer_root.create("mnt", VfsNodeType::Dir).unwrap();
+    outer_root.create("f", VfsNodeType::File).unwrap();
+    let top = Arc::new(ForeignDir(outer_root.clone(), None));
+    let mnt = outer_root.clone().lookup("mnt").unwrap();
+    let ramfs = RamFileSystem::new();
+    ramfs
+        .mount("/mnt", Arc::new(ForeignDir(mnt, Some(top))))
+        .unwrap();
+    let root = ramfs.root_dir_node();
+    let f = outer_root.clone().lookup("f").unwrap();
+    assert!(Arc::ptr_eq(&root.clone().lookup("../f").unwrap(), &f));
+    assert_eq!(
+        root.clone().lookup("../f/x").err(),
+        Some(VfsError::NotADirectory)
+    );
+    root.create("../g", VfsNodeType::File).unwrap();
+    assert!(outer_root.clone().lookup("g").is_ok());
+    root.remove("../g").unwrap();
+    assert_eq!(root.remove(".."), Err(VfsError::InvalidInput));
+    assert_eq!(root.rename("a", "../a"), Err(VfsError::InvalidInput));
+    assert_eq!(outer_root.get_entries(), ["f", "mnt"]);
+}
+
+#[test]
+fn test_replace_contents() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("old", VfsNodeType::File).unwrap();
+    root.create("olddir", VfsNodeType::Dir).unwrap();
+    root.create("staging", VfsNodeType::Dir).unwrap();
+    root.create("staging/new", VfsNodeType::File).unwrap();
+    root.create("staging/sub", VfsNodeType::Dir).unwrap();
+    let staging = root.clone().lookup("staging").unwrap();
+    let staging = staging.as_any().downcast_ref::<DirNode>().unwrap();
+    let new_children: Vec<(String, VfsNodeRef)> = staging
+        .get_entries()
+        .into_iter()
+        .map(|name| {
+            let node = root.clone().lookup(&format!("staging/{}", name)).unwrap();
+            (name, node)
+        })
+        .collect();
+
+    let root_ref: VfsNodeRef = root.clone();
+    assert_eq!(
+        root.replace_contents(vec![("me".into(), root_ref)]).err(),
+        Some(VfsError::InvalidInput)
+    );
+    assert!(root.clone().lookup("old").is_ok());
+
+    let old = root.replace_contents(new_children).unwrap();
+    let old_names: Vec<_> = old.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(old_names, ["old", "olddir", "staging"]);
+    assert_eq!(root.get_entries(), ["new", "sub"]);
+    assert_eq!(root.clone().lookup("old").err(), Some(VfsError::NotFound));
+
+    // the moved directory now leads back to its new parent
+    let parent = root.clone().lookup("sub").unwrap().parent().unwrap();
+    assert!(core::ptr::eq(
+        Arc::as_ptr(&parent) as *const (),
+        Arc::as_ptr(&root) as *const ()
+    ));
+}
+
+#[test]
+fn test_read_dir_order() {
+    let names = [
+        "delta", "Bravo", "alpha", "charlie", "_x", "a1", "a10", "a2",
+    ];
+    let mut expected = vec![".", ".."];
+    let mut sorted = names;
+    sorted.sort_unstable();
+    expected.extend(sorted);
+
+    // a few fixed shuffles of the same names, each in a fresh filesystem
+    for seed in [1usize, 3, 5, 7] {
+        let ramfs = RamFileSystem::new();
+        let root = ramfs.root_dir_node();
+        assert_eq!(root.sort_mode(), SortMode::Name);
+        for i in 0..names.len() {
+            let name = names[i * seed % names.len()];
+            root.create(name, VfsNodeType::File).unwrap();
+        }
+
+        let mut entries: Vec<_> = (0..16).map(|_| VfsDirEntry::default()).collect();
+        let n = root.read_dir(0, &mut entries).unwrap();
+        let listed: Vec<_> = entries[..n]
+            .iter()
+            .map(|e| core::str::from_utf8(e.name_as_bytes()).unwrap())
+            .collect();
+        assert_eq!(listed, expected);
+    }
+}
+
+#[test]
+fn test_write_vectored() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("f", VfsNodeType::File).unwrap();
+    let node = root.clone().lookup("f").unwrap();
+    let file = node.as_any().downcast_ref::<FileNode>().unwrap();
+    file.write_at(0, b"xx").unwrap();
+
+    let bufs: [&[u8]; 3] = [b"one", b"", b"three"];
+    assert_eq!(file.write_vectored(4, &bufs), Ok(8));
+    let mut buf = [0xff; 16];
+    assert_eq!(file.read_at(0, &mut buf).unwrap(), 12);
+    assert_eq!(&buf[..12], b"xx\0\0onethree");
+
+    // staged as a single write
+    file.enable_staging(8);
+    assert_eq!(file.write_vectored(0, &[b"ab", b"cd"]), Ok(4));
+    assert_eq!(file.staged_bytes(), 4);
+    assert_eq!(
+        file.write_vectored(0, &[b"abc", b"de"]),
+        Err(VfsError::StorageFull)
+    );
+    file.disable_staging().unwrap();
+    assert_eq!(file.read_at(0, &mut buf).unwrap(), 12);
+    assert_eq!(&buf[..12], b"abcdonethree");
+}
+
+#[test]
+fn test_as_slice() {
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("f", VfsNodeType::File).unwrap();
+    let node = root.clone().lookup("f").unwrap();
+    let file = node.as_any().downcast_ref::<FileNode>().unwrap();
+    file.write_at(0, b"hello").unwrap();
+
+    let slice = file.as_slice();
+    assert_eq!(&*slice, b"hello");
+    assert_eq!(slice[1], b'e');
+
+    // a writer waits for the guard
+    let written = Arc::new(AtomicBool::new(false));
+    let writer = {
+        let (node, written) = (node.clone(), written.clone());
+        std::thread::spawn(move || {
+            node.write_at(0, b"J").unwrap();
+            written.store(true, Ordering::SeqCst);
+        })
+    };
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    assert!(!written.load(Ordering::SeqCst));
+    assert_eq!(&*slice, b"hello");
+    drop(slice);
+    writer.join().unwrap();
+    assert!(written.load(Ordering::SeqCst));
+    assert_eq!(&*file.as_slice(), b"Jello");
+}
+
+#[test]
+fn test_check_consistency() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    for dir in ["a", "a/b", "a/b/c", "d"] {
+        root.create(dir, VfsNodeType::Dir).unwrap();
+    }
+    root.create("a/b/f", VfsNodeType::File).unwrap();
+    root.rename("a/b", "d/b").unwrap();
+    let copy = {
+        let d = root.clone().lookup("d").unwrap();
+        d.as_any().downcast_ref::<DirNode>().unwrap().cow_clone()
+    };
+    root.replace_contents(vec![("copy".into(), copy as VfsNodeRef)])
+        .unwrap();
+    assert_eq!(ramfs.check_consistency(), Ok(()));
+
+    // a link count off by one
+    let f = root.clone().lookup("copy/b/f").unwrap();
+    let f = f.as_any().downcast_ref::<FileNode>().unwrap();
+    f.add_link();
+    assert_eq!(
+        ramfs.check_consistency(),
+        Err(vec![
+            "/copy/b/f: link count is 2, but 1 entries refer to it".into()
+        ])
+    );
+    f.remove_link();
+    root.link("g", root.clone().lookup("copy/b/f").unwrap())
+        .unwrap();
+    assert_eq!(ramfs.check_consistency(), Ok(()));
+
+    // a parent link pointing elsewhere
+    root.create("x", VfsNodeType::Dir).unwrap();
+    let x = root.clone().lookup("x").unwrap();
+    let c = root.clone().lookup("copy/b/c").unwrap();
+    c.as_any()
+        .downcast_ref::<DirNode>()
+        .unwrap()
+        .set_parent(Some(&x));
+    let errors = ramfs.check_consistency().unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].starts_with("/copy/b/c: parent link"));
+}
+
+#[test]
+fn test_remove_node_recursive() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    for dir in ["a", "a/b", "a/b/c", "a/d"] {
+        root.create(dir, VfsNodeType::Dir).unwrap();
+    }
+    root.create("a/b/c/f", VfsNodeType::File).unwrap();
+    root.create("a/b/g", VfsNodeType::File).unwrap();
+    let c = Arc::downgrade(&root.clone().lookup("a/b/c").unwrap());
+    let f = Arc::downgrade(&root.clone().lookup("a/b/c/f").unwrap());
+    let b = root.clone().lookup("a/b").unwrap();
+
+    assert_eq!(root.remove_node("a"), Err(VfsError::DirectoryNotEmpty));
+    assert_eq!(root.remove_node_recursive("."), Err(VfsError::InvalidInput));
+    assert_eq!(
+        root.remove_node_recursive(".."),
+        Err(VfsError::InvalidInput)
+    );
+    assert_eq!(root.remove_node_recursive("x"), Err(VfsError::NotFound));
+    root.remove_node_recursive("a").unwrap();
+    assert!(root.get_entries().is_empty());
+    // the subtree is freed even though `b` is still referenced
+    assert!(c.upgrade().is_none());
+    assert!(f.upgrade().is_none());
+    let b = b.as_any().downcast_ref::<DirNode>().unwrap();
+    assert!(b.get_entries().is_empty());
+    assert_eq!(ramfs.check_consistency(), Ok(()));
+}
+
+#[test]
+fn test_inline_contents() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    let (_, file) = root.make_temp("small").unwrap();
+    let data: Vec<u8> = (0..100).collect();
+    let mut buf = [0; 128];
+
+    assert!(file.is_inline());
+    file.write_at(0, &data[..16]).unwrap();
+    assert!(file.is_inline());
+    assert_eq!(file.read_at(0, &mut buf), Ok(16));
+    assert_eq!(&buf[..16], &data[..16]);
+    file.truncate(INLINE_CAPACITY as u64).unwrap();
+    assert!(file.is_inline());
+    assert_eq!(&file.as_slice()[16..], &[0; INLINE_CAPACITY - 16]);
+    file.truncate(8).unwrap();
+    file.truncate(16).unwrap();
+    assert_eq!(&file.as_slice()[8..], &[0; 8]);
+    file.write_at(8, &data[8..16]).unwrap();
+
+    // growing past the threshold moves the contents to the heap
+    file.write_at(16, &data[16..]).unwrap();
+    assert!(!file.is_inline());
+    assert_eq!(file.get_attr().unwrap().size(), 100);
+    assert_eq!(file.read_at(0, &mut buf), Ok(100));
+    assert_eq!(&buf[..100], &data[..]);
+    file.truncate(8).unwrap();
+    assert!(!file.is_inline());
+    assert_eq!(&*file.as_slice(), &data[..8]);
+
+    // small atomic writes are stored inline, large reservations are not
+    root.atomic_write("a", &data[..4]).unwrap();
+    let a = root.clone().lookup("a").unwrap();
+    let a = a.as_any().downcast_ref::<FileNode>().unwrap();
+    assert!(a.is_inline());
+    assert_eq!(&*a.as_slice(), &data[..4]);
+    a.reserve(INLINE_CAPACITY as u64).unwrap();
+    assert!(a.is_inline());
+    a.reserve(INLINE_CAPACITY as u64 + 1).unwrap();
+    assert!(!a.is_inline());
+    assert_eq!(&*a.as_slice(), &data[..4]);
+}
+
+/// Installs a clock shared by the tests, that ticks a second on every reading.
+fn install_test_clock() {
+    use core::sync::atomic::{AtomicU64, Ordering};
+    use core::time::Duration;
+
+    static CLOCK: AtomicU64 = AtomicU64::new(1);
+    set_time_source(|| Duration::from_secs(CLOCK.fetch_add(1, Ordering::Relaxed)));
+}
+
+#[test]
+fn test_times() {
+    use core::time::Duration;
+
+    install_test_clock();
+
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("d", VfsNodeType::Dir).unwrap();
+    root.create("d/f", VfsNodeType::File).unwrap();
+    let d = root.clone().lookup("d").unwrap();
+    let d = d.as_any().downcast_ref::<DirNode>().unwrap();
+    let f = root.clone().lookup("d/f").unwrap();
+    let file = f.as_any().downcast_ref::<FileNode>().unwrap();
+    let created = file.times();
+    assert_ne!(created.mtime, Duration::ZERO);
+    assert_eq!(created.atime, created.mtime);
+    assert!(d.times().mtime >= created.mtime);
+
+    f.write_at(0, b"hello").unwrap();
+    let written = file.times();
+    assert!(written.mtime > created.mtime);
+    assert_eq!(written.ctime, written.mtime);
+    assert_eq!(written.atime, created.atime);
+
+    // renaming changes the node and updates both directories
+    let root_mtime = root.times().mtime;
+    let d_mtime = d.times().mtime;
+    root.rename("d/f", "f").unwrap();
+    let renamed = file.times();
+    assert_eq!(renamed.mtime, written.mtime);
+    assert!(renamed.ctime > written.ctime);
+    assert!(root.times().mtime > root_mtime);
+    assert!(d.times().mtime > d_mtime);
+
+    file.set_times(Some(Duration::from_secs(7)), None);
+    let set = file.times();
+    assert_eq!(set.atime, Duration::from_secs(7));
+    assert_eq!(set.mtime, written.mtime);
+    assert!(set.ctime > renamed.ctime);
+    d.set_times(None, Some(Duration::ZERO));
+    assert_eq!(d.times().mtime, Duration::ZERO);
+}
+
+#[test]
+fn test_mode() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("d", VfsNodeType::Dir).unwrap();
+    root.create("d/f", VfsNodeType::File).unwrap();
+    let d = root.clone().lookup("d").unwrap();
+    let f = root.clone().lookup("d/f").unwrap();
+    assert_eq!(d.get_attr().unwrap().perm().bits(), 0o755);
+    assert_eq!(f.get_attr().unwrap().perm().bits(), 0o644);
+
+    let file = f.as_any().downcast_ref::<FileNode>().unwrap();
+    file.set_mode(0o600);
+    assert_eq!(file.mode(), 0o600);
+    let perm = f.get_attr().unwrap().perm();
+    assert_eq!(perm.bits(), 0o600);
+    assert!(perm.owner_writable());
+    assert!(!perm.contains(VfsNodePerm::GROUP_READ));
+    file.set_mode(0o4777);
+    assert_eq!(file.mode(), 0o777);
+
+    let dir = d.as_any().downcast_ref::<DirNode>().unwrap();
+    dir.set_mode(0o700);
+    assert_eq!(d.get_attr().unwrap().perm().bits(), 0o700);
+    assert_eq!(dir.cow_clone().mode(), 0o700);
+}
+
+#[test]
+fn test_dedup() {
+    let ramfs = RamFileSystem::with_dedup();
+    let store = ramfs.chunk_store().unwrap().clone();
+    let pattern: Vec<u8> = (0..CHUNK_SIZE).map(|i| (i % 251) as u8).collect();
+    ramfs.create("a", VfsNodeType::File).unwrap();
+    ramfs.create("d", VfsNodeType::Dir).unwrap();
+    ramfs.create("d/b", VfsNodeType::File).unwrap();
+    let a = ramfs.lookup("a").unwrap();
+    let b = ramfs.lookup("d/b").unwrap();
+    assert!(a.as_any().is::<ChunkedFileNode>());
+
+    a.write_at(0, &pattern).unwrap();
+    b.write_at(0, &pattern[..100]).unwrap();
+    b.write_at(100, &pattern[100..]).unwrap();
+    assert_eq!(store.share_count(&pattern), 2);
+    assert_eq!(store.len(), 1);
+    let mut buf = vec![0; CHUNK_SIZE + 10];
+    assert_eq!(b.read_at(0, &mut buf), Ok(CHUNK_SIZE));
+    assert_eq!(&buf[..CHUNK_SIZE], &pattern[..]);
+
+    // a chunk is unshared once modified, and reads cross chunk boundaries
+    b.write_at(CHUNK_SIZE as u64 + 1, b"xy").unwrap();
+    b.write_at(0, b"z").unwrap();
+    assert_eq!(store.share_count(&pattern), 1);
+    assert_eq!(b.get_attr().unwrap().size(), CHUNK_SIZE as u64 + 3);
+    assert_eq!(b.read_at(CHUNK_SIZE as u64 - 1, &mut buf), Ok(4));
+    assert_eq!(&buf[..4], &[pattern[CHUNK_SIZE - 1], 0, b'x', b'y']);
+    b.truncate(CHUNK_SIZE as u64 + 2).unwrap();
+    b.truncate(CHUNK_SIZE as u64 + 3).unwrap();
+    assert_eq!(b.read_at(CHUNK_SIZE as u64, &mut buf), Ok(3));
+    assert_eq!(&buf[..3], &[0, b'x', 0]);
+
+    // holes share the zero chunk, and unused chunks are freed
+    a.truncate(3 * CHUNK_SIZE as u64).unwrap();
+    assert_eq!(store.share_count(&[0; CHUNK_SIZE]), 2);
+    ramfs.remove("a").unwrap();
+    drop(a);
+    assert_eq!(store.share_count(&pattern), 0);
+    assert_eq!(store.len(), 2);
+}
+
+#[test]
+fn test_read_dir_paginated() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    for name in ["a", "b", "c", "d", "e"] {
+        root.create(name, VfsNodeType::File).unwrap();
+    }
+    root.create("c", VfsNodeType::Dir).unwrap_err();
+
+    for size in 1..=3 {
+        let mut entries: Vec<_> = (0..size).map(|_| VfsDirEntry::default()).collect();
+        let mut listed = Vec::new();
+        let mut idx = 0;
+        loop {
+            let n = root.read_dir(idx, &mut entries).unwrap();
+            if n == 0 {
+                break;
+            }
+            for ent in &entries[..n] {
+                let name = core::str::from_utf8(ent.name_as_bytes()).unwrap();
+                listed.push((name.to_string(), ent.entry_type()));
+            }
+            idx += n;
+        }
+        let names: Vec<_> = listed.iter().map(|(name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            [".", "..", "a", "b", "c", "d", "e"],
+            "{} per call",
+            size
+        );
+        assert_eq!(listed[1].1, VfsNodeType::Dir);
+        assert_eq!(listed[2].1, VfsNodeType::File);
+    }
+    assert_eq!(root.read_dir(100, &mut [VfsDirEntry::default()]), Ok(0));
+}
+
+#[test]
+fn test_link() {
+    use core::time::Duration;
+
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("a", VfsNodeType::File).unwrap();
+    root.create("d", VfsNodeType::Dir).unwrap();
+    let a = root.clone().lookup("a").unwrap();
+    let file = a.clone();
+    let file = file.as_any().downcast_ref::<FileNode>().unwrap();
+    assert_eq!(file.link_count(), 1);
+
+    root.link("b", a.clone()).unwrap();
+    root.link("d/c", a.clone()).unwrap_err();
+    let d = root.clone().lookup("d").unwrap();
+    let d = d.as_any().downcast_ref::<DirNode>().unwrap();
+    d.link("c", a.clone()).unwrap();
+    assert_eq!(file.link_count(), 3);
+    assert_eq!(root.link("b", a.clone()), Err(VfsError::AlreadyExists));
+    let dir = root.clone().lookup("d").unwrap();
+    assert_eq!(root.link("e", dir), Err(VfsError::IsADirectory));
+
+    root.clone()
+        .lookup("/a")
+        .unwrap()
+        .write_at(0, b"shared")
+        .unwrap();
+    let mut buf = [0; 8];
+    let b = root.clone().lookup("/b").unwrap();
+    assert_eq!(b.read_at(0, &mut buf), Ok(6));
+    assert_eq!(&buf[..6], b"shared");
+    file.set_times(None, Some(Duration::from_secs(86400 * 365)));
+    assert!(root
+        .format_listing("")
+        .unwrap()
+        .contains("-rw-r--r--  3        6 1971-01-01 00:00 a\n"));
+
+    // the file lives on as long as one name is left
+    root.remove("a").unwrap();
+    // renaming onto another link of the same file does nothing
+    root.rename("b", "d/c").unwrap();
+    assert_eq!(file.link_count(), 2);
+    root.remove("b").unwrap();
+    assert_eq!(file.link_count(), 1);
+    assert_eq!(
+        root.clone().lookup("d/c").unwrap().read_at(0, &mut buf),
+        Ok(6)
+    );
+    let copy = d.cow_clone();
+    assert_eq!(file.link_count(), 2);
+    drop(copy);
+    assert_eq!(file.link_count(), 1);
+    root.remove_node_recursive("d").unwrap();
+    assert_eq!(file.link_count(), 0);
+}
+
+#[test]
+fn test_atime_modes() {
+    use core::time::Duration;
+
+    install_test_clock();
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("f", VfsNodeType::File).unwrap();
+    let f = root.clone().lookup("f").unwrap();
+    let file = f.as_any().downcast_ref::<FileNode>().unwrap();
+    let mut buf = [0; 4];
+    let mut read = || {
+        f.read_at(0, &mut buf).unwrap();
+        file.times().atime
+    };
+
+    // only stale access times are updated
+    set_atime_mode(AtimeMode::Relatime);
+    let first = read();
+    assert!(first > file.times().mtime);
+    assert_eq!(read(), first);
+    file.set_mode(0o600);
+    let changed = read();
+    assert!(changed > first);
+    assert_eq!(read(), changed);
+    set_relatime_interval(Duration::from_secs(1));
+    assert!(read() > changed);
+    set_relatime_interval(Duration::from_secs(24 * 60 * 60));
+
+    set_atime_mode(AtimeMode::Strict);
+    let strict = read();
+    assert!(read() > strict);
+
+    set_atime_mode(AtimeMode::Noatime);
+    let before = file.times().atime;
+    f.write_at(0, b"data").unwrap();
+    assert_eq!(read(), before);
+    let mut entries = [VfsDirEntry::default()];
+    let dir_atime = root.times().atime;
+    root.read_dir(0, &mut entries).unwrap();
+    assert_eq!(root.times().atime, dir_atime);
+
+    set_atime_mode(AtimeMode::Relatime);
+    assert!(read() > before);
+    root.read_dir(0, &mut entries).unwrap();
+    assert!(root.times().atime > dir_atime);
+}
+
+#[test]
+fn test_case_insensitive() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    assert!(!root.is_case_insensitive());
+    root.create("Readme", VfsNodeType::File).unwrap();
+    root.create("README", VfsNodeType::File).unwrap();
+    assert!(!root.exist("readme"));
+    assert_eq!(
+        root.clone().lookup("readme").err(),
+        Some(VfsError::NotFound)
+    );
+    root.remove("README").unwrap();
+
+    root.set_case_insensitive(true);
+    root.create("Docs", VfsNodeType::Dir).unwrap();
+    assert!(root.exist("readme"));
+    assert_eq!(
+        root.create("README", VfsNodeType::File),
+        Err(VfsError::AlreadyExists)
+    );
+    assert_eq!(
+        root.create("docs", VfsNodeType::Dir),
+        Err(VfsError::AlreadyExists)
+    );
+    root.create("DOCS/Guide.TXT", VfsNodeType::File).unwrap();
+    let guide = root.clone().lookup("docs/guide.txt").unwrap();
+    guide.write_at(0, b"hi").unwrap();
+    assert_eq!(root.get_entries(), ["Docs", "Readme"]);
+
+    // replacing keeps the stored name, renaming changes it
+    root.atomic_write("docs/GUIDE.txt", b"new").unwrap();
+    let docs = root.clone().lookup("DOCS").unwrap();
+    let docs = docs.as_any().downcast_ref::<DirNode>().unwrap();
+    assert!(docs.is_case_insensitive());
+    assert_eq!(docs.get_entries(), ["Guide.TXT"]);
+    root.rename("readme", "READ.ME").unwrap();
+    root.rename("read.me", "Read.Me").unwrap();
+    assert_eq!(root.get_entries(), ["Docs", "Read.Me"]);
+    root.rename("docs/guide.txt", "read.me").unwrap();
+    assert_eq!(root.get_entries(), ["Docs", "read.me"]);
+    let mut buf = [0; 4];
+    assert_eq!(
+        root.clone().lookup("READ.ME").unwrap().read_at(0, &mut buf),
+        Ok(3)
+    );
+    assert_eq!(&buf[..3], b"new");
+    root.remove("READ.ME").unwrap();
+    root.remove_node_recursive("DOCS").unwrap();
+    assert!(root.get_entries().is_empty());
+    assert_eq!(ramfs.check_consistency(), Ok(()));
+}
+
+#[test]
+fn test_name_len() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    let long = "a".repeat(MAX_NAME_LEN + 1);
+    assert_eq!(
+        root.create_node(&long, VfsNodeType::File),
+        Err(VfsError::InvalidInput)
+    );
+    root.create_node(&long[1..], VfsNodeType::File).unwrap();
+    assert_eq!(
+        root.create_node("a/b", VfsNodeType::File),
+        Err(VfsError::InvalidInput)
+    );
+    assert_eq!(
+        root.create_node("a\0b", VfsNodeType::Dir),
+        Err(VfsError::InvalidInput)
+    );
+
+    let file = root.clone().lookup(&long[1..]).unwrap();
+    assert_eq!(root.link(&long, file.clone()), Err(VfsError::InvalidInput));
+    assert_eq!(root.link("b/c", file), Err(VfsError::InvalidInput));
+    assert!(!root.exist("a"));
+
+    // every way of adding an entry checks its name
+    assert_eq!(root.rename(&long[1..], &long), Err(VfsError::InvalidInput));
+    assert_eq!(root.rename(&long[1..], "a\0b"), Err(VfsError::InvalidInput));
+    assert_eq!(
+        root.get_or_create("a\0b", VfsNodeType::File).err(),
+        Some(VfsError::InvalidInput)
+    );
+    assert_eq!(root.create_symlink(&long, "x"), Err(VfsError::InvalidInput));
+    assert_eq!(
+        root.create_device_node("a\0b", VfsNodeType::CharDevice, 1, 3),
+        Err(VfsError::InvalidInput)
+    );
+    assert_eq!(
+        root.bind_file_ro("..", Arc::new(FileNode::new())),
+        Err(VfsError::InvalidInput)
+    );
+    assert_eq!(root.get_entries(), [&long[1..]]);
+    assert_eq!(ramfs.check_consistency(), Ok(()));
+
+    let ramfs = RamFileSystem::with_dedup();
+    assert_eq!(
+        ramfs.create(&long, VfsNodeType::File),
+        Err(VfsError::InvalidInput)
+    );
+    assert_eq!(
+        ramfs.create("a\0b", VfsNodeType::File),
+        Err(VfsError::InvalidInput)
+    );
+    ramfs.create(&long[1..], VfsNodeType::File).unwrap();
+    assert_eq!(ramfs.root_dir_node().get_entries(), [&long[1..]]);
+}
+
+#[test]
+fn test_journal() {
+    use core::time::Duration;
+
+    let ramfs = RamFileSystem::with_journal();
+    ramfs.create("d", VfsNodeType::Dir).unwrap();
+    ramfs.create("d/a", VfsNodeType::File).unwrap();
+    ramfs.create("d/b", VfsNodeType::File).unwrap();
+    ramfs.create("e", VfsNodeType::Dir).unwrap();
+    ramfs.write("d/a", 0, b"hello").unwrap();
+    ramfs.write("d/a", 3, b"p me").unwrap();
+    ramfs.write("d/b", 2, b"x").unwrap();
+    ramfs.rename("d/a", "e/c").unwrap();
+    ramfs.remove("d/b").unwrap();
+    ramfs.create("d/f", VfsNodeType::File).unwrap();
+    // failed operations are not recorded
+    ramfs.remove("d/b").unwrap_err();
+    ramfs.write("d/b", 0, b"x").unwrap_err();
+
+    let journal = ramfs.journal().unwrap();
+    assert_eq!(journal.len(), 10);
+    assert_eq!(
+        journal[5],
+        Op::Write {
+            path: "d/a".into(),
+            offset: 3,
+            data: b"p me".to_vec(),
+        }
+    );
+    assert_eq!(RamFileSystem::new().journal(), None);
+
+    let replayed = RamFileSystem::new();
+    replayed.replay(&journal).unwrap();
+    assert_eq!(
+        replayed.root_dir_node().tree_hash(),
+        ramfs.root_dir_node().tree_hash()
+    );
+    let mut buf = [0; 8];
+    let c = replayed.lookup("e/c").unwrap();
+    assert_eq!(c.read_at(0, &mut buf), Ok(7));
+    assert_eq!(&buf[..7], b"help me");
+    // the replayed files are newer, if another test installed a clock
+    for fs in [&ramfs, &replayed] {
+        let f = fs.lookup("d/f").unwrap();
+        let f = f.as_any().downcast_ref::<FileNode>().unwrap();
+        f.set_times(None, Some(Duration::ZERO));
+    }
+    assert_eq!(
+        replayed.root_dir_node().format_listing("d").unwrap(),
+        ramfs.root_dir_node().format_listing("d").unwrap()
+    );
+
+    // a journal not matching the tree stops at the first failure
+    assert_eq!(replayed.replay(&journal), Err(VfsError::AlreadyExists));
+}
+
+#[test]
+fn test_total_used_bytes() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    assert_eq!(root.total_used_bytes(), 4096);
+    root.create("a", VfsNodeType::File).unwrap();
+    root.create("d", VfsNodeType::Dir).unwrap();
+    root.create("d/e", VfsNodeType::Dir).unwrap();
+    root.create("d/e/b", VfsNodeType::File).unwrap();
+    root.create("d/c", VfsNodeType::SymLink).unwrap();
+    root.clone()
+        .lookup("a")
+        .unwrap()
+        .write_at(0, &[1; 1000])
+        .unwrap();
+    root.clone()
+        .lookup("d/e/b")
+        .unwrap()
+        .truncate(5000)
+        .unwrap();
+    assert_eq!(root.total_used_bytes(), 3 * 4096 + 1000 + 5000);
+
+    let d = root.clone().lookup("d").unwrap();
+    let d = d.as_any().downcast_ref::<DirNode>().unwrap();
+    assert_eq!(d.total_used_bytes(), 2 * 4096 + 5000);
+
+    // hard links count once
+    let a = root.clone().lookup("a").unwrap();
+    d.link("a2", a.clone()).unwrap();
+    root.link("a3", a).unwrap();
+    assert_eq!(root.total_used_bytes(), 3 * 4096 + 1000 + 5000);
+    assert_eq!(d.total_used_bytes(), 2 * 4096 + 1000 + 5000);
+}
+
+#[test]
+fn test_get_or_create() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    let watch = root.watch(false);
+
+    // created
+    let a = root.get_or_create("a", VfsNodeType::File).unwrap();
+    a.write_at(0, b"data").unwrap();
+    assert!(root.exist("a"));
+    assert_eq!(watch.poll().unwrap().kind, WatchEventKind::Create);
+    let d = root.get_or_create("d", VfsNodeType::Dir).unwrap();
+    let d = d.as_any().downcast_ref::<DirNode>().unwrap();
+    d.get_or_create("e", VfsNodeType::Fifo).unwrap();
+    assert_eq!(
+        root.clone()
+            .lookup("d/e")
+            .unwrap()
+            .get_attr()
+            .unwrap()
+            .file_type(),
+        VfsNodeType::Fifo
+    );
+
+    // reused
+    let again = root.get_or_create("a", VfsNodeType::File).unwrap();
+    assert!(Arc::ptr_eq(&a, &again));
+    assert_eq!(again.get_attr().unwrap().size(), 4);
+    assert_eq!(watch.poll().unwrap().path, "d");
+    assert_eq!(watch.poll(), None);
+    let file = a.as_any().downcast_ref::<FileNode>().unwrap();
+    assert_eq!(file.link_count(), 1);
+
+    // wrong type
+    assert_eq!(
+        root.get_or_create("a", VfsNodeType::Dir).err(),
+        Some(VfsError::AlreadyExists)
+    );
+    assert_eq!(
+        root.get_or_create("d", VfsNodeType::File).err(),
+        Some(VfsError::AlreadyExists)
+    );
+    assert_eq!(
+        root.get_or_create("..", VfsNodeType::Dir).err(),
+        Some(VfsError::InvalidInput)
+    );
+    assert_eq!(ramfs.check_consistency(), Ok(()));
+}
+
+#[test]
+fn test_socket_node() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    root.create("run", VfsNodeType::Dir).unwrap();
+    root.create("run/sock", VfsNodeType::Socket).unwrap();
+    let node = root.clone().lookup("run/sock").unwrap();
+    let attr = node.get_attr().unwrap();
+    assert_eq!(attr.file_type(), VfsNodeType::Socket);
+    assert_eq!(attr.size(), 0);
+    assert!(root
+        .format_listing("run")
+        .unwrap()
+        .starts_with("srw-rw-rw-"));
+
+    // a connect finds the bound endpoint through the path
+    let sock = node.as_any().downcast_ref::<SocketNode>().unwrap();
+    assert!(sock.endpoint().is_none());
+    let endpoint: Arc<dyn core::any::Any + Send + Sync> = Arc::new(42u32);
+    sock.set_endpoint(Some(&endpoint));
+    let found = root.clone().lookup("run/sock").unwrap();
+    let found = found.as_any().downcast_ref::<SocketNode>().unwrap();
+    let peer = found.endpoint().unwrap();
+    assert_eq!(peer.downcast_ref::<u32>(), Some(&42));
+    drop((peer, endpoint));
+    assert!(sock.endpoint().is_none());
+
+    root.remove("run/sock").unwrap();
+    assert_eq!(
+        root.clone().lookup("run/sock").err(),
+        Some(VfsError::NotFound)
+    );
+    root.remove("run").unwrap();
+}
+
+#[test]
+fn test_create_above_root() {
+    let ramfs = RamFileSystem::new();
+    let root = ramfs.root_dir_node();
+    assert!(root.parent().is_none());
+    assert_eq!(
+        root.create("../foo", VfsNodeType::File),
+        Err(VfsError::NotFound)
+    );
+    assert_eq!(root.create("..", VfsNodeType::Dir), Err(VfsError::NotFound));
+    root.create("d", VfsNodeType::Dir).unwrap();
+    assert_eq!(
+        root.create("d/../../foo", VfsNodeType::File),
+        Err(VfsError::NotFound)
+    );
+    assert_eq!(
+        ramfs.create("../foo", VfsNodeType::File),
+        Err(VfsError::NotFound)
+    );
+    assert_eq!(root.get_entries(), ["d"]);
+
+    // `..` below the root still goes up
+    root.create("d/e", VfsNodeType::Dir).unwrap();
+    let d = root.clone().lookup("d/e").unwrap();
+    d.create("../f", VfsNodeType::File).unwrap();
+    assert!(root.clone().lookup("d/f").is_ok());
+}
@@ -0,0 +1,123 @@
+use core::time::Duration;
+use spin::RwLock;
+
+/// Clock used to timestamp nodes, see [`set_time_source`].
+static TIME_SOURCE: RwLock<fn() -> Duration> = RwLock::new(|| Duration::ZERO);
+
+/// Sets the clock used to timestamp nodes, e.g. `axhal::time::wall_time`.
+///
+/// Until it is set, all timestamps are zero.
+pub fn set_time_source(source: fn() -> Duration) {
+    *TIME_SOURCE.write() = source;
+}
+
+fn now() -> Duration {
+    (TIME_SOURCE.read())()
+}
+
+/// When reads update the access time of a node, see [`set_atime_mode`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AtimeMode {
+    /// On every read.
+    Strict,
+    /// Only if the access time is not after the modification or change
+    /// time, or is older than the interval set by
+    /// [`set_relatime_interval`], like Linux's default `relatime`.
+    Relatime,
+    /// Never.
+    Noatime,
+}
+
+static ATIME_MODE: RwLock<AtimeMode> = RwLock::new(AtimeMode::Relatime);
+static RELATIME_INTERVAL: RwLock<Duration> = RwLock::new(Duration::from_secs(24 * 60 * 60));
+
+/// Sets when reads update access times, [`AtimeMode::Relatime`] by default.
+///
+/// Each access time update takes the write lock of the timestamps, so
+/// [`AtimeMode::Strict`] makes concurrent reads of a node contend.
+pub fn set_atime_mode(mode: AtimeMode) {
+    *ATIME_MODE.write() = mode;
+}
+
+/// Sets the age past which [`AtimeMode::Relatime`] updates an access time
+/// anyway, a day by default.
+pub fn set_relatime_interval(interval: Duration) {
+    *RELATIME_INTERVAL.write() = interval;
+}
+
+/// Timestamps of a node, as durations since the epoch of the time source.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct NodeTimes {
+    /// Time of the last access.
+    pub atime: Duration,
+    /// Time of the last modification of the contents.
+    pub mtime: Duration,
+    /// Time of the last change of the contents or of the metadata.
+    pub ctime: Duration,
+}
+
+/// Timestamps of a node, updated in place.
+pub(crate) struct Times(RwLock<NodeTimes>);
+
+impl Times {
+    /// Stamps a node created now.
+    pub fn new() -> Self {
+        let now = now();
+        Self(RwLock::new(NodeTimes {
+            atime: now,
+            mtime: now,
+            ctime: now,
+        }))
+    }
+
+    pub fn get(&self) -> NodeTimes {
+        *self.0.read()
+    }
+
+    /// Records a modification of the contents.
+    pub fn modified(&self) {
+        let now = now();
+        let mut times = self.0.write();
+        times.mtime = now;
+        times.ctime = now;
+    }
+
+    /// Records a read, as allowed by the [`AtimeMode`].
+    pub fn accessed(&self) {
+        let now = match *ATIME_MODE.read() {
+            AtimeMode::Noatime => return,
+            AtimeMode::Strict => now(),
+            AtimeMode::Relatime => {
+                let now = now();
+                let times = self.get();
+                let stale = times.atime <= times.mtime
+                    || times.atime <= times.ctime
+                    || now.saturating_sub(times.atime) >= *RELATIME_INTERVAL.read();
+                if !stale {
+                    return;
+                }
+                now
+            }
+        };
+        self.0.write().atime = now;
+    }
+
+    /// Records a change of the metadata only.
+    pub fn changed(&self) {
+        self.0.write().ctime = now();
+    }
+
+    /// Sets the access and modification times, leaving out the `None` ones,
+    /// like `utimensat` with `UTIME_OMIT`.
+    pub fn set(&self, atime: Option<Duration>, mtime: Option<Duration>) {
+        let now = now();
+        let mut times = self.0.write();
+        if let Some(atime) = atime {
+            times.atime = atime;
+        }
+        if let Some(mtime) = mtime {
+            times.mtime = mtime;
+        }
+        times.ctime = now;
+    }
+}
@@ -0,0 +1,96 @@
+use alloc::collections::VecDeque;
+use alloc::string::String;
+use alloc::sync::Weak;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
+
+/// Number of live watches in all filesystems, so that changes are not
+/// reported at all while nobody watches.
+static WATCHES: AtomicUsize = AtomicUsize::new(0);
+
+/// Kind of change reported by a [`Watch`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchEventKind {
+    /// An entry was created, or moved in by a rename.
+    Create,
+    /// An entry was removed, or moved out by a rename.
+    Remove,
+    /// An existing file was replaced by [`atomic_write`](crate::DirNode::atomic_write).
+    Replace,
+}
+
+/// A change in a watched directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WatchEvent {
+    /// What happened.
+    pub kind: WatchEventKind,
+    /// Path of the changed entry, relative to the watched directory.
+    pub path: String,
+}
+
+/// A watch on a directory, registered with
+/// [`DirNode::watch`](crate::DirNode::watch).
+///
+/// Events are queued until polled. The watch is removed when dropped.
+pub struct Watch {
+    recursive: bool,
+    events: Mutex<VecDeque<WatchEvent>>,
+}
+
+impl Watch {
+    pub(crate) fn new(recursive: bool) -> Self {
+        WATCHES.fetch_add(1, Ordering::Relaxed);
+        Self {
+            recursive,
+            events: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Whether changes in subdirectories are reported too.
+    pub fn is_recursive(&self) -> bool {
+        self.recursive
+    }
+
+    /// Takes the oldest pending event, if any.
+    pub fn poll(&self) -> Option<WatchEvent> {
+        self.events.lock().pop_front()
+    }
+
+    pub(crate) fn push(&self, kind: WatchEventKind, path: &str) {
+        self.events.lock().push_back(WatchEvent {
+            kind,
+            path: path.into(),
+        });
+    }
+}
+
+impl Drop for Watch {
+    fn drop(&mut self) {
+        WATCHES.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Returns true if any watch is alive.
+pub(crate) fn any_watch() -> bool {
+    WATCHES.load(Ordering::Relaxed) != 0
+}
+
+/// Delivers an event to the watches of one directory, dropping dead ones.
+/// `direct` tells whether the change happened in that directory itself.
+pub(crate) fn deliver(
+    watches: &mut Vec<Weak<Watch>>,
+    kind: WatchEventKind,
+    path: &str,
+    direct: bool,
+) {
+    watches.retain(|watch| match watch.upgrade() {
+        Some(watch) => {
+            if direct || watch.recursive {
+                watch.push(kind, path);
+            }
+            true
+        }
+        None => false,
+    });
+}
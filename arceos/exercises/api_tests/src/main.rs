@@ -0,0 +1,473 @@
+#![no_std]
+#![no_main]
+
+#[macro_use]
+#[cfg(feature = "axstd")]
+extern crate axstd as std;
+
+use arceos_api::fs::*;
+use arceos_api::log::*;
+use arceos_api::mem::*;
+use arceos_api::misc::*;
+use arceos_api::stdio::*;
+use arceos_api::sync::*;
+use arceos_api::task::*;
+use arceos_api::time::*;
+use arceos_api::AxError;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use core::time::Duration;
+use std::sync::Arc;
+use std::vec::Vec;
+
+// The tests read the console input fed by `scripts/test-api_tests.sh`, in the
+// order they run.
+#[cfg_attr(feature = "axstd", no_mangle)]
+fn main() {
+    println!("Running API tests...");
+    test_line_discipline();
+    test_read_until();
+    test_buffered_console();
+    test_log_level();
+    test_mem_map();
+    test_clock_getres();
+    test_affinity();
+    test_delay();
+    test_task_cwd();
+    test_event();
+    test_sysinfo();
+    test_signals();
+    test_timers();
+    test_join_all();
+    test_shm();
+    test_sleep_until_nanos();
+    test_unfinished_line();
+    println!("API tests run OK!");
+}
+
+/// Reads a byte, yielding until one is available.
+fn read_byte() -> u8 {
+    loop {
+        if let Some(c) = ax_console_read_byte() {
+            return c;
+        }
+        ax_yield_now();
+    }
+}
+
+fn test_line_discipline() {
+    // raw mode: a keystroke is returned alone, without waiting for a newline
+    assert!(ax_console_is_raw());
+    assert_eq!(read_byte(), b'r');
+
+    // cooked mode: the line is returned once finished, with its edits applied
+    ax_console_set_raw(false);
+    assert!(!ax_console_is_raw());
+    let line: [u8; 3] = core::array::from_fn(|_| read_byte());
+    assert_eq!(&line, b"ab\n");
+    println!("test_line_discipline() OK!");
+}
+
+fn test_read_until() {
+    ax_console_set_raw(true);
+    let mut buf = [0; 8];
+    assert_eq!(ax_console_read_until(b';', &mut buf), Ok(6));
+    assert_eq!(&buf[..6], b"key=1;");
+    // stops when the buffer is full
+    assert_eq!(ax_console_read_until(b';', &mut buf[..1]), Ok(1));
+    assert_eq!(buf[0], b'a');
+    // `\r` is read as `\n`
+    assert_eq!(ax_console_read_until(b'\n', &mut buf), Ok(2));
+    assert_eq!(&buf[..2], b"b\n");
+    println!("test_read_until() OK!");
+}
+
+fn test_buffered_console() {
+    use core::fmt::Write;
+
+    let mut out = BufferedConsole::new();
+    write!(out, "buffered: ").unwrap();
+    write!(out, "a={}", 1).unwrap();
+    write!(out, " b={}", 2).unwrap();
+    // nothing is written out before the newline...
+    assert_eq!(out.buffered(), 17);
+    writeln!(out).unwrap();
+    // ...which writes out the whole line, checked by the test script
+    assert_eq!(out.buffered(), 0);
+
+    write!(out, "flushed").unwrap();
+    assert_eq!(out.buffered(), 7);
+    out.flush().unwrap();
+    assert_eq!(out.buffered(), 0);
+    ax_console_write_bytes(b"\n").unwrap();
+
+    // a full buffer is written out without waiting for a newline
+    for _ in 0..CONSOLE_BUF_SIZE + 1 {
+        out.write_char('.').unwrap();
+    }
+    assert_eq!(out.buffered(), 1);
+    drop(out);
+    ax_console_write_bytes(b"\n").unwrap();
+    println!("test_buffered_console() OK!");
+}
+
+fn test_log_level() {
+    assert_eq!(ax_log_get_level(), AxLogLevel::Info);
+    ax_log_set_level(AxLogLevel::Warn);
+    assert_eq!(ax_log_get_level(), AxLogLevel::Warn);
+    // only the second one is printed, as checked by the test script
+    ax_log(AxLogLevel::Info, format_args!("log: hidden"));
+    ax_log(AxLogLevel::Warn, format_args!("log: shown {}", 1));
+    ax_log(AxLogLevel::Off, format_args!("log: hidden"));
+    ax_log_set_level(AxLogLevel::Info);
+    println!("test_log_level() OK!");
+}
+
+fn test_mem_map() {
+    let size = 3 * 4096;
+    let addr = ax_mem_map(size, AxMapFlags::READ | AxMapFlags::WRITE).unwrap();
+    let region = unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, size) };
+    assert!(region.iter().all(|&b| b == 0));
+    region.fill(0xa5);
+    assert!(region.iter().all(|&b| b == 0xa5));
+
+    // only the regions returned by `ax_mem_map`, whole, can be unmapped
+    let err = Err(AxError::InvalidInput);
+    assert_eq!(ax_mem_unmap(addr + 4096, 4096), err);
+    assert_eq!(ax_mem_unmap(addr, 4096), err);
+    assert_eq!(ax_mem_unmap(0x1000, 4096), err);
+    assert_eq!(ax_mem_unmap(addr, usize::MAX), err);
+    assert_eq!(ax_mem_unmap(addr, size), Ok(()));
+    assert_eq!(ax_mem_unmap(addr, size), err);
+
+    // sizes that would wrap around when rounded up to whole pages
+    let flags = AxMapFlags::READ;
+    assert_eq!(ax_mem_map(usize::MAX, flags), Err(AxError::InvalidInput));
+    assert_eq!(
+        ax_mem_map(usize::MAX - 4094, flags),
+        Err(AxError::InvalidInput)
+    );
+    println!("test_mem_map() OK!");
+}
+
+fn test_clock_getres() {
+    let res = ax_clock_getres();
+    assert!(res > Duration::ZERO && res < Duration::from_secs(1));
+    let tick = arceos_api::modules::axhal::time::ticks_to_nanos(1);
+    assert_eq!(res, Duration::from_nanos(tick.max(1)));
+
+    // the monotonic clock never moves by less than its resolution
+    let start = ax_monotonic_time();
+    let mut now = start;
+    while now == start {
+        now = ax_monotonic_time();
+    }
+    assert!(now - start >= res);
+    println!("test_clock_getres() OK!");
+}
+
+fn test_affinity() {
+    let cpus = arceos_api::config::SMP;
+    let all = ax_sched_getaffinity();
+    assert_eq!(all.count_ones() as usize, cpus.min(usize::BITS as usize));
+    assert_eq!(all & 1, 1);
+    assert_eq!(ax_sched_setaffinity(all), Ok(()));
+    assert_eq!(ax_sched_setaffinity(0), Err(AxError::InvalidInput));
+    // pinning to one CPU is only possible when there is no other
+    let pinned = if cpus == 1 {
+        Ok(())
+    } else {
+        Err(AxError::Unsupported)
+    };
+    assert_eq!(ax_sched_setaffinity(1), pinned);
+    assert_eq!(ax_sched_getaffinity(), all);
+    println!("test_affinity() OK!");
+}
+
+fn test_delay() {
+    let start = ax_monotonic_time();
+    ax_delay_us(500);
+    assert!(ax_monotonic_time() - start >= Duration::from_micros(500));
+
+    let start = ax_monotonic_time();
+    ax_delay_ns(20_000);
+    assert!(ax_monotonic_time() - start >= Duration::from_nanos(20_000));
+    println!("test_delay() OK!");
+}
+
+fn test_task_cwd() {
+    let mut opts = AxOpenOptions::new();
+    opts.write(true);
+    opts.create(true);
+    ax_create_dir("/api_tests").unwrap();
+    ax_create_dir("/api_tests/sub").unwrap();
+    let mut file = ax_open_file("/api_tests/sub/f", &opts).unwrap();
+    ax_write_file(&mut file, b"in sub").unwrap();
+    drop(file);
+
+    let cwd = ax_getcwd().unwrap();
+    let task = ax_spawn(
+        || {
+            ax_chdir("/api_tests").unwrap();
+            assert_eq!(ax_getcwd().unwrap(), "/api_tests");
+            // relative to the directory set just before
+            ax_chdir("sub").unwrap();
+            assert_eq!(ax_getcwd().unwrap(), "/api_tests/sub");
+            assert_eq!(ax_chdir("f").err(), Some(AxError::NotADirectory));
+            assert_eq!(ax_getcwd().unwrap(), "/api_tests/sub");
+
+            let mut opts = AxOpenOptions::new();
+            opts.read(true);
+            let mut file = ax_open_file("f", &opts).unwrap();
+            let mut buf = [0; 8];
+            let len = ax_read_file(&mut file, &mut buf).unwrap();
+            assert_eq!(&buf[..len], b"in sub");
+            ax_rename("f", "g").unwrap();
+        },
+        "cwd".into(),
+        arceos_api::config::TASK_STACK_SIZE,
+    );
+    ax_wait_for_exit(task);
+    // the other tasks keep their own directory
+    assert_eq!(ax_getcwd().unwrap(), cwd);
+    opts.create(false);
+    assert!(ax_open_file("/api_tests/sub/g", &opts).is_ok());
+    assert!(ax_open_file("/api_tests/sub/f", &opts).is_err());
+    println!("test_task_cwd() OK!");
+}
+
+fn test_event() {
+    let event = Arc::new(ax_event_create());
+
+    // a signal before the wait is not lost, and counts once
+    ax_event_signal(&event);
+    assert_eq!(ax_event_wait(&event, None), Ok(()));
+    let timeout = Some(Duration::from_millis(10));
+    assert_eq!(ax_event_wait(&event, timeout), Err(AxError::TimedOut));
+
+    let waiter = {
+        let event = event.clone();
+        ax_spawn(
+            move || {
+                ax_event_wait(&event, None).unwrap();
+                // too long to be added to the current time
+                ax_event_wait(&event, Some(Duration::MAX)).unwrap();
+            },
+            "event".into(),
+            arceos_api::config::TASK_STACK_SIZE,
+        )
+    };
+    ax_event_signal(&event);
+    ax_yield_now();
+    ax_event_signal(&event);
+    ax_wait_for_exit(waiter);
+    assert_eq!(ax_event_wait(&event, timeout), Err(AxError::TimedOut));
+    println!("test_event() OK!");
+}
+
+fn test_sysinfo() {
+    let before = ax_sysinfo();
+    assert!(before.uptime > Duration::ZERO);
+    assert!(before.free_mem > 0 && before.free_mem <= before.total_mem);
+    assert_eq!(before.cpu_count, arceos_api::config::SMP);
+
+    let task = ax_spawn(
+        || ax_sleep_until(ax_wall_time() + Duration::from_millis(10)),
+        "sysinfo".into(),
+        arceos_api::config::TASK_STACK_SIZE,
+    );
+    let during = ax_sysinfo();
+    assert!(during.uptime >= before.uptime);
+    assert_eq!(during.total_mem, before.total_mem);
+    assert!(during.free_mem <= during.total_mem);
+    assert_eq!(during.task_count, before.task_count + 1);
+    ax_wait_for_exit(task);
+    assert_eq!(ax_sysinfo().task_count, before.task_count);
+    println!("test_sysinfo() OK!");
+}
+
+fn test_signals() {
+    static READY: AtomicBool = AtomicBool::new(false);
+    static RECEIVED: AtomicU32 = AtomicU32::new(0);
+
+    let task = ax_spawn(
+        || {
+            ax_signal_register(|sig| RECEIVED.store(sig, Ordering::Release));
+            READY.store(true, Ordering::Release);
+            while RECEIVED.load(Ordering::Acquire) == 0 {
+                ax_yield_now();
+            }
+        },
+        "signals".into(),
+        arceos_api::config::TASK_STACK_SIZE,
+    );
+    while !READY.load(Ordering::Acquire) {
+        ax_yield_now();
+    }
+    assert_eq!(ax_signal_raise(&task, 7), Ok(()));
+    let tasks = [task];
+    assert_eq!(ax_join_all(&tasks), [Ok(0)]);
+    assert_eq!(RECEIVED.load(Ordering::Acquire), 7);
+    // the handler is dropped once the task returns...
+    assert_eq!(ax_signal_raise(&tasks[0], 7), Err(AxError::BadState));
+
+    let task = ax_spawn(
+        || {
+            ax_signal_register(|_| {});
+            ax_exit(3);
+        },
+        "signals".into(),
+        arceos_api::config::TASK_STACK_SIZE,
+    );
+    let tasks = [task];
+    assert_eq!(ax_join_all(&tasks), [Ok(3)]);
+    // ...or exits
+    assert_eq!(ax_signal_raise(&tasks[0], 7), Err(AxError::BadState));
+    println!("test_signals() OK!");
+}
+
+fn test_timers() {
+    static FIRED: AtomicU32 = AtomicU32::new(0);
+    static CANCELLED_FIRED: AtomicBool = AtomicBool::new(false);
+
+    let start = ax_monotonic_time();
+    let _timer = ax_timer_after(Duration::from_millis(10), || {
+        FIRED.fetch_add(1, Ordering::AcqRel);
+    });
+    let cancelled = ax_timer_after(Duration::from_millis(5), || {
+        CANCELLED_FIRED.store(true, Ordering::Release);
+    });
+    assert!(ax_timer_cancel(cancelled));
+    while FIRED.load(Ordering::Acquire) == 0 {
+        ax_yield_now();
+    }
+    assert!(ax_monotonic_time() - start >= Duration::from_millis(10));
+
+    // well past the deadline of the cancelled one
+    ax_sleep_until(ax_wall_time() + Duration::from_millis(20));
+    assert!(!CANCELLED_FIRED.load(Ordering::Acquire));
+    assert_eq!(FIRED.load(Ordering::Acquire), 1);
+
+    // a timer that never fires
+    let never = ax_timer_after(Duration::MAX, || {
+        CANCELLED_FIRED.store(true, Ordering::Release);
+    });
+    ax_sleep_until(ax_wall_time() + Duration::from_millis(5));
+    assert!(ax_timer_cancel(never));
+    assert!(!CANCELLED_FIRED.load(Ordering::Acquire));
+
+    // too late to cancel a timer that fired
+    let fired = ax_timer_after(Duration::ZERO, || {});
+    ax_sleep_until(ax_wall_time() + Duration::from_millis(5));
+    assert!(!ax_timer_cancel(fired));
+    println!("test_timers() OK!");
+}
+
+fn test_join_all() {
+    // the later the task, the sooner it exits
+    let tasks: Vec<_> = (0..5)
+        .map(|i| {
+            ax_spawn(
+                move || {
+                    ax_sleep_until(ax_wall_time() + Duration::from_millis(5 * (5 - i)));
+                    ax_exit(i as i32 * 10);
+                },
+                format!("join_all {i}"),
+                arceos_api::config::TASK_STACK_SIZE,
+            )
+        })
+        .collect();
+    assert_eq!(ax_join_all(&tasks), [Ok(0), Ok(10), Ok(20), Ok(30), Ok(40)]);
+    println!("test_join_all() OK!");
+}
+
+fn test_shm() {
+    const SIZE: usize = 2 * 4096;
+    let handle = ax_shm_create(SIZE);
+    let addr = ax_shm_map(&handle).unwrap();
+    assert_eq!(ax_shm_map(&handle), Ok(addr));
+    let region = unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, SIZE) };
+    assert!(region.iter().all(|&b| b == 0));
+    region[..5].copy_from_slice(b"hello");
+
+    // another task gets its own mapping of the same pages
+    let task = {
+        let handle = handle.clone();
+        ax_spawn(
+            move || {
+                let addr = ax_shm_map(&handle).unwrap();
+                let region = unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, SIZE) };
+                assert_eq!(&region[..5], b"hello");
+                region[4096..4101].copy_from_slice(b"world");
+                assert_eq!(ax_shm_unmap(&handle), Ok(()));
+                assert_eq!(ax_shm_unmap(&handle), Err(AxError::BadState));
+            },
+            "shm".into(),
+            arceos_api::config::TASK_STACK_SIZE,
+        )
+    };
+    ax_wait_for_exit(task);
+    assert_eq!(&region[4096..4101], b"world");
+
+    // a task still mapping the region when the last handle is dropped
+    let task = {
+        let handle = handle.clone();
+        ax_spawn(
+            move || {
+                ax_shm_map(&handle).unwrap();
+            },
+            "shm".into(),
+            arceos_api::config::TASK_STACK_SIZE,
+        )
+    };
+    ax_wait_for_exit(task);
+    drop(handle);
+    println!("test_shm() OK!");
+}
+
+fn test_sleep_until_nanos() {
+    let deadline = ax_monotonic_time_nanos() + 5_000_000;
+    ax_sleep_until_nanos(deadline);
+    assert!(ax_monotonic_time_nanos() >= deadline);
+
+    // a deadline in the past returns at once
+    let start = ax_monotonic_time_nanos();
+    ax_sleep_until_nanos(start / 2);
+    assert!(ax_monotonic_time_nanos() - start < 5_000_000);
+
+    // a periodic loop advancing an absolute deadline only lags by the last
+    // wakeup, while relative sleeps add up the lag of every wakeup
+    const N: u64 = 10;
+    let period = 2_000_000;
+    let start = ax_monotonic_time_nanos();
+    let mut deadline = start;
+    for _ in 0..N {
+        deadline += period;
+        ax_sleep_until_nanos(deadline);
+        assert!(ax_monotonic_time_nanos() >= deadline);
+    }
+    let absolute = ax_monotonic_time_nanos() - start;
+
+    let start = ax_monotonic_time_nanos();
+    for _ in 0..N {
+        ax_sleep_until(ax_wall_time() + Duration::from_nanos(period));
+    }
+    let relative = ax_monotonic_time_nanos() - start;
+
+    let tick = ax_clock_getres().as_nanos() as u64;
+    assert!(absolute <= N * period + tick);
+    assert!(absolute <= relative);
+    println!("test_sleep_until_nanos() OK!");
+}
+
+fn test_unfinished_line() {
+    ax_console_set_raw(false);
+    // without a newline, no byte of the line is ever delivered
+    for _ in 0..100 {
+        assert_eq!(ax_console_read_byte(), None);
+        ax_yield_now();
+    }
+    // switching modes drops it
+    ax_console_set_raw(true);
+    assert_eq!(ax_console_read_byte(), None);
+    println!("test_unfinished_line() OK!");
+}
@@ -5,12 +5,30 @@
 #[cfg(feature = "axstd")]
 extern crate axstd as std;
 
+use core::borrow::Borrow;
 use std::collection::HashMap;
+use std::string::String;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::vec::Vec;
 
 #[cfg_attr(feature = "axstd", no_mangle)]
 fn main() {
     println!("Running memory tests...");
     test_hashmap();
+    test_fold_values();
+    test_max_capacity();
+    test_incremental_rehash();
+    test_new_const();
+    test_insert_or_merge();
+    test_stable_remove();
+    test_sorted_iter();
+    test_get_many();
+    test_stats();
+    test_entry_ref();
+    test_auto_shrink();
+    test_eq_pairs();
+    test_size_overflow();
     println!("Memory tests run OK!");
 }
 
@@ -28,3 +46,286 @@ fn test_hashmap() {
     }
     println!("test_hashmap() OK!");
 }
+
+fn test_fold_values() {
+    let mut m = HashMap::new();
+    for value in 1..=10u32 {
+        m.insert(value, value * 2);
+    }
+    assert_eq!(m.sum_values::<u32>(), 110);
+    assert_eq!(m.fold_values(0, |max, &v| max.max(v)), 20);
+    assert_eq!(HashMap::<u32, u32>::new().sum_values::<u32>(), 0);
+    println!("test_fold_values() OK!");
+}
+
+fn test_max_capacity() {
+    const N: u32 = 10_000;
+    let mut m = HashMap::new();
+    m.set_max_capacity(Some(128));
+    for value in 0..N {
+        m.insert(value, value);
+    }
+    assert_eq!(m.capacity(), 128);
+    assert_eq!(m.len(), N as usize);
+    assert_eq!(m.iter().filter(|(k, v)| k == v).count(), N as usize);
+
+    let mut m = HashMap::new();
+    for value in 0..N {
+        m.insert(value, value);
+    }
+    assert!(m.capacity() > 128);
+    println!("test_max_capacity() OK!");
+}
+
+fn test_incremental_rehash() {
+    const N: u32 = 100_000;
+    let mut m = HashMap::new();
+    let mut capacity = m.capacity();
+    for value in 0..N {
+        m.insert(value, value);
+        if m.capacity() != capacity {
+            // growing leaves the old buckets to be moved by later inserts
+            assert!(m.is_rehashing());
+            capacity = m.capacity();
+            // an existing key is replaced, not duplicated
+            m.insert(0, 0);
+            assert_eq!(m.len(), value as usize + 1);
+        }
+        if value % 10_000 == 0 {
+            let count = m.iter().filter(|(k, v)| k == v).count();
+            assert_eq!(count, value as usize + 1);
+        }
+    }
+    assert_eq!(m.len(), N as usize);
+    assert_eq!(m.iter().filter(|(k, v)| k == v).count(), N as usize);
+    println!("test_incremental_rehash() OK!");
+}
+
+static CONFIG: Mutex<HashMap<&str, u32>> = Mutex::new(HashMap::new_const(0x5eed));
+
+fn test_new_const() {
+    let mut config = CONFIG.lock();
+    assert!(config.is_empty());
+    assert_eq!(config.capacity(), 0);
+    for (i, name) in ["alpha", "beta", "gamma"].into_iter().enumerate() {
+        config.insert(name, i as u32);
+    }
+    assert_eq!(config.len(), 3);
+    assert_eq!(config.sum_values::<u32>(), 3);
+    println!("test_new_const() OK!");
+}
+
+fn test_insert_or_merge() {
+    let mut counts = HashMap::new();
+    let words = ["a", "b", "a", "c", "a", "b"];
+    for word in words {
+        counts.insert_or_merge(word, 1u32, |count, n| *count += n);
+    }
+    assert_eq!(counts.len(), 3);
+    let mut pairs: Vec<_> = counts.iter().map(|(&k, &v)| (k, v)).collect();
+    pairs.sort();
+    assert_eq!(pairs, [("a", 3), ("b", 2), ("c", 1)]);
+
+    // merging keeps working across growth and incremental rehashing
+    let mut sums = HashMap::new();
+    for value in 0..10_000u32 {
+        sums.insert_or_merge(value % 1000, value as u64, |sum, v| *sum += v);
+    }
+    assert_eq!(sums.len(), 1000);
+    assert_eq!(sums.sum_values::<u64>(), (0..10_000u64).sum());
+    println!("test_insert_or_merge() OK!");
+}
+
+fn test_stable_remove() {
+    let mut m = HashMap::new();
+    // few buckets, so that entries chain
+    m.set_max_capacity(Some(64));
+    for value in 0..256u32 {
+        m.insert(value, value);
+    }
+    let mut order: Vec<u32> = m.iter().map(|(&k, _)| k).collect();
+    for step in 0..64 {
+        let key = order[order.len() / 2 + step % 3];
+        assert_eq!(m.remove(&key), Some(key));
+        assert_eq!(m.remove(&key), None);
+        order.retain(|&k| k != key);
+        let now: Vec<u32> = m.iter().map(|(&k, _)| k).collect();
+        assert_eq!(now, order);
+    }
+    assert_eq!(m.len(), 256 - 64);
+    assert_eq!(HashMap::<u32, u32>::new_const(0).remove(&0), None);
+    println!("test_stable_remove() OK!");
+}
+
+fn test_sorted_iter() {
+    let mut a = HashMap::new_const(1);
+    let mut b = HashMap::new_const(0xdead_beef);
+    for value in (0..1000u32).rev() {
+        a.insert(value * 7 % 1000, value);
+        b.insert(value * 7 % 1000, value);
+    }
+    let keys_a: Vec<u32> = a.sorted_iter().map(|(&k, _)| k).collect();
+    let keys_b: Vec<u32> = b.sorted_iter().map(|(&k, _)| k).collect();
+    assert_eq!(keys_a, (0..1000).collect::<Vec<_>>());
+    assert_eq!(keys_a, keys_b);
+    assert!(a.sorted_iter().eq(b.sorted_iter()));
+    println!("test_sorted_iter() OK!");
+}
+
+fn test_get_many() {
+    let mut m = HashMap::new();
+    let mut value = 0u32;
+    // stop right after a growth, with most keys still in the old buckets
+    while value < 100 || !m.is_rehashing() {
+        m.insert(value, value * 3);
+        value += 1;
+    }
+    let results = m.get_many(&[&3, &value, &(value - 1), &0, &3]);
+    let last = (value - 1) * 3;
+    assert_eq!(results, [Some(&9), None, Some(&last), Some(&0), Some(&9)]);
+    let keys: Vec<u32> = (0..value).collect();
+    let refs: Vec<&u32> = keys.iter().collect();
+    assert!(m.get_many(&refs).iter().all(|v| v.is_some()));
+    assert!(m.get_many(&[]).is_empty());
+    assert_eq!(HashMap::<u32, u32>::new_const(0).get_many(&[&1]), [None]);
+    println!("test_get_many() OK!");
+}
+
+fn test_stats() {
+    let stats = HashMap::<u32, u32>::new_const(0).stats();
+    assert_eq!((stats.len, stats.capacity, stats.load_factor), (0, 0, 0.0));
+
+    let mut m = HashMap::new();
+    for i in 0..100u32 {
+        m.insert(i, i);
+    }
+    let stats = m.stats();
+    assert_eq!(stats.len, 100);
+    assert_eq!(stats.capacity, m.capacity());
+    assert!(stats.empty_buckets < stats.capacity);
+    assert!(stats.max_bucket_len >= 1 && stats.max_bucket_len <= 100);
+    assert_eq!(stats.load_factor, 100.0 / stats.capacity as f32);
+    if !m.is_rehashing() {
+        // the 100 entries fill at least this many buckets
+        let used = stats.capacity - stats.empty_buckets;
+        assert!(used * stats.max_bucket_len >= 100);
+    }
+    println!("test_stats() OK!");
+}
+
+static KEY_CLONES: AtomicUsize = AtomicUsize::new(0);
+
+/// A string key counting how often it is cloned
+#[derive(PartialEq, Eq, Hash)]
+struct CountedKey(String);
+
+impl Clone for CountedKey {
+    fn clone(&self) -> Self {
+        KEY_CLONES.fetch_add(1, Ordering::Relaxed);
+        Self(self.0.clone())
+    }
+}
+
+impl Borrow<str> for CountedKey {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for CountedKey {
+    fn from(s: &str) -> Self {
+        Self(s.into())
+    }
+}
+
+fn test_entry_ref() {
+    let mut m: HashMap<CountedKey, u32> = HashMap::new();
+    for word in "a b a c a b".split(' ') {
+        m.entry_ref(word).and_modify(|n| *n += 1).or_insert(1);
+    }
+    assert_eq!(KEY_CLONES.load(Ordering::Relaxed), 0);
+    assert_eq!(m.len(), 3);
+    let counts: Vec<u32> = ["a", "b", "c"]
+        .iter()
+        .map(|&k| *m.entry_ref(k).or_insert(0))
+        .collect();
+    assert_eq!(counts, [3, 2, 1]);
+
+    // vacant entries keep working across growth
+    for i in 0..200u32 {
+        let key = format!("k{i}");
+        *m.entry_ref(key.as_str()).or_insert_with(|| i) += 1;
+    }
+    assert_eq!(m.len(), 203);
+    assert_eq!(*m.entry_ref("k150").or_insert(0), 151);
+    assert_eq!(KEY_CLONES.load(Ordering::Relaxed), 0);
+    println!("test_entry_ref() OK!");
+}
+
+fn test_auto_shrink() {
+    const N: u32 = 10_000;
+    let mut kept = HashMap::new();
+    let mut shrunk = HashMap::new();
+    shrunk.set_auto_shrink(true);
+    for value in 0..N {
+        kept.insert(value, value);
+        shrunk.insert(value, value);
+    }
+    let capacity = kept.capacity();
+    assert_eq!(shrunk.capacity(), capacity);
+
+    for value in 0..N - 10 {
+        assert_eq!(kept.remove(&value), Some(value));
+        assert_eq!(shrunk.remove(&value), Some(value));
+    }
+    assert_eq!(kept.capacity(), capacity);
+    assert_eq!(shrunk.capacity(), 64);
+    assert_eq!(shrunk.len(), 10);
+    let mut left: Vec<u32> = shrunk.iter().map(|(k, _)| *k).collect();
+    left.sort_unstable();
+    assert_eq!(left, (N - 10..N).collect::<Vec<_>>());
+
+    // emptying it never goes under the initial buckets
+    for value in N - 10..N {
+        shrunk.remove(&value);
+    }
+    assert!(shrunk.is_empty());
+    assert_eq!(shrunk.capacity(), 64);
+    println!("test_auto_shrink() OK!");
+}
+
+fn test_eq_pairs() {
+    let mut m = HashMap::new();
+    for value in 0..100u32 {
+        m.insert(value, value * 2);
+    }
+    let expected: Vec<_> = (0..100u32).map(|k| (k, k * 2)).collect();
+    assert!(m.eq_pairs(expected.iter().rev().copied()));
+
+    let mut wrong_value = expected.clone();
+    wrong_value[42].1 += 1;
+    assert!(!m.eq_pairs(wrong_value));
+    assert!(!m.eq_pairs(expected[1..].iter().copied()));
+    let mut wrong_key = expected.clone();
+    wrong_key[99] = (0, 0);
+    assert!(!m.eq_pairs(wrong_key));
+    assert!(HashMap::<u32, u32>::new_const(0).eq_pairs([]));
+    println!("test_eq_pairs() OK!");
+}
+
+fn test_size_overflow() {
+    let mut m = HashMap::new();
+    m.insert(1u32, 1u32);
+    m.set_len_for_test(usize::MAX);
+    // updating an existing key adds no entry
+    assert_eq!(m.try_insert(1, 2), Ok(()));
+    assert_eq!(m.try_insert(2, 2), Err((2, 2)));
+    assert_eq!(m.len(), usize::MAX);
+    assert_eq!(m.capacity(), 64);
+    assert_eq!(m.iter().count(), 1);
+    assert!(m.stats().load_factor.is_finite());
+
+    let empty: HashMap<u32, u32> = HashMap::new_const(0);
+    assert_eq!(empty.stats().load_factor, 0.0);
+    println!("test_size_overflow() OK!");
+}
@@ -15,6 +15,9 @@
 //!    to create and initialize other filesystems. This feature is **disabled** by
 //!    by default, but it will override other filesystem selection features if
 //!    both are enabled.
+//! - `multitask`: Make the FIFOs of the RAM filesystems block the calling task
+//!    on a wait queue when empty or full. This feature is **disabled** by
+//!    default.
 //!
 //! [FAT]: https://en.wikipedia.org/wiki/File_Allocation_Table
 //! [`MyFileSystemIf`]: fops::MyFileSystemIf
@@ -158,6 +158,21 @@ pub(crate) fn init_rootfs(disk: crate::dev::Disk) {
 
     let mut root_dir = RootDirectory::new(main_fs);
 
+    #[cfg(any(feature = "ramfs", feature = "procfs", feature = "sysfs"))]
+    axfs_ramfs::set_time_source(axhal::time::wall_time);
+
+    #[cfg(all(
+        feature = "multitask",
+        any(feature = "ramfs", feature = "procfs", feature = "sysfs")
+    ))]
+    {
+        static FIFO_WQ: axtask::WaitQueue = axtask::WaitQueue::new();
+        axfs_ramfs::set_fifo_waiter(axfs_ramfs::FifoWaiter {
+            wait_until: |ready| FIFO_WQ.wait_until(ready),
+            wake: || FIFO_WQ.notify_all(true),
+        });
+    }
+
     #[cfg(feature = "devfs")]
     root_dir
         .mount("/dev", mounts::devfs())
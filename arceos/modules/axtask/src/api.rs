@@ -119,6 +119,12 @@ where
     spawn_raw(f, "".into(), axconfig::TASK_STACK_SIZE)
 }
 
+/// Returns the number of tasks that have not exited, including the idle and
+/// init tasks of every CPU.
+pub fn num_tasks() -> usize {
+    crate::task::LIVE_TASKS.load(core::sync::atomic::Ordering::Relaxed)
+}
+
 /// Set the priority for current task.
 ///
 /// The range of the priority is dependent on the underlying scheduler. For
@@ -91,6 +91,7 @@ impl AxRunQueue {
             axhal::misc::terminate();
         } else {
             curr.set_state(TaskState::Exited);
+            crate::task::LIVE_TASKS.fetch_sub(1, core::sync::atomic::Ordering::Relaxed);
             curr.notify_exit(exit_code, self);
             EXITED_TASKS.lock().push_back(curr.clone());
             WAIT_FOR_EXIT.notify_one_locked(false, self);
@@ -1,11 +1,8 @@
 use alloc::{boxed::Box, string::String, sync::Arc};
 use core::ops::Deref;
-use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, AtomicU8, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, AtomicU8, AtomicUsize, Ordering};
 use core::{alloc::Layout, cell::UnsafeCell, fmt, ptr::NonNull};
 
-#[cfg(feature = "preempt")]
-use core::sync::atomic::AtomicUsize;
-
 #[cfg(feature = "tls")]
 use axhal::tls::TlsArea;
 
@@ -59,6 +56,9 @@ pub struct TaskInner {
     tls: TlsArea,
 }
 
+/// Number of tasks created and not exited yet.
+pub(crate) static LIVE_TASKS: AtomicUsize = AtomicUsize::new(0);
+
 impl TaskId {
     fn new() -> Self {
         static ID_COUNTER: AtomicU64 = AtomicU64::new(1);
@@ -164,6 +164,7 @@ impl TaskInner {
 // private methods
 impl TaskInner {
     fn new_common(id: TaskId, name: String) -> Self {
+        LIVE_TASKS.fetch_add(1, Ordering::Relaxed);
         Self {
             id,
             name,
@@ -1,6 +1,196 @@
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
-use allocator::{BaseAllocator, ByteAllocator, PageAllocator};
+use allocator::{AllocError, AllocResult, BaseAllocator, ByteAllocator, PageAllocator};
+use core::alloc::Layout;
+use core::marker::PhantomData;
+use core::ptr::NonNull;
+
+#[cfg(test)]
+mod tests;
+
+/// Maximum number of free gaps in the bytes area remembered for reuse.
+const MAX_GAPS: usize = 8;
+
+/// Which end of the arena each area grows from, see [`Forward`] and
+/// [`Reversed`].
+pub trait Direction {
+    /// True if bytes grow down from the end and pages up from the start.
+    const REVERSED: bool;
+}
+
+/// The default layout: bytes grow up from the start of the arena, pages
+/// down from its end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Forward;
+
+/// The mirrored layout: bytes grow down from the end of the arena, like a
+/// stack, and pages up from its start.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reversed;
+
+impl Direction for Forward {
+    const REVERSED: bool = false;
+}
+
+impl Direction for Reversed {
+    const REVERSED: bool = true;
+}
+
+/// Kind of memory reported to the allocation hooks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocKind {
+    /// Memory from the bytes area.
+    Bytes,
+    /// Memory from the pages area.
+    Pages,
+}
+
+/// Allocation hook, called with the kind, size in bytes and address of a
+/// block.
+pub type AllocHook = fn(kind: AllocKind, size: usize, addr: usize);
+
+/// A block allocated through [`EarlyAllocator::apply`], to be passed back to
+/// free it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EarlyHandle {
+    addr: usize,
+    size: usize,
+    align: usize,
+}
+
+impl EarlyHandle {
+    /// Start address of the block.
+    pub fn addr(&self) -> usize {
+        self.addr
+    }
+
+    /// Size of the block, in bytes for byte allocations and in pages for
+    /// page allocations.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+}
+
+/// A snapshot of the state of an [`EarlyAllocator`], see
+/// [`EarlyAllocator::stats`].
+///
+/// Its `Display` output draws the layout of the arena with the concrete
+/// addresses, e.g. to log it before panicking on an allocation failure.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EarlyStats {
+    /// Start of the arena.
+    pub start: usize,
+    /// End of the arena.
+    pub end: usize,
+    /// Inner edge of the bytes area.
+    pub b_pos: usize,
+    /// Inner edge of the pages area.
+    pub p_pos: usize,
+    /// Size of the bytes area, in bytes.
+    pub bytes_used: usize,
+    /// Size of the pages area, in pages.
+    pub pages_used: usize,
+    /// Size of the free middle, in bytes.
+    pub available: usize,
+}
+
+impl core::fmt::Display for EarlyStats {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        // the bytes area is above the pages area only when reversed
+        if self.b_pos > self.p_pos {
+            write!(
+                f,
+                "[ pages-used {} | avail {:#x} | bytes-used {:#x} ] \
+                 start {:#x} p_pos {:#x} b_pos {:#x} end {:#x}",
+                self.pages_used,
+                self.available,
+                self.bytes_used,
+                self.start,
+                self.p_pos,
+                self.b_pos,
+                self.end
+            )
+        } else {
+            write!(
+                f,
+                "[ bytes-used {:#x} | avail {:#x} | pages-used {} ] \
+                 start {:#x} b_pos {:#x} p_pos {:#x} end {:#x}",
+                self.bytes_used,
+                self.available,
+                self.pages_used,
+                self.start,
+                self.b_pos,
+                self.p_pos,
+                self.end
+            )
+        }
+    }
+}
+
+/// Running totals of an [`EarlyAllocator`] at some point, see
+/// [`EarlyAllocator::snapshot`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EarlySnapshot {
+    bytes_allocated: usize,
+    bytes_freed: usize,
+    pages_allocated: usize,
+    pages_freed: usize,
+}
+
+/// What happened between an [`EarlySnapshot`] and a later point, see
+/// [`EarlyAllocator::diff`].
+///
+/// Sizes are requested sizes, without alignment padding.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EarlySnapshotDiff {
+    /// Bytes handed out by `alloc`.
+    pub bytes_allocated: usize,
+    /// Bytes given back to `dealloc`.
+    pub bytes_freed: usize,
+    /// Pages handed out by `alloc_pages`, DMA pages included.
+    pub pages_allocated: usize,
+    /// Pages given back to `dealloc_pages`.
+    pub pages_freed: usize,
+}
+
+impl EarlySnapshotDiff {
+    /// Bytes allocated and not freed since the snapshot, negative if more
+    /// were freed than allocated.
+    pub fn net_bytes(&self) -> isize {
+        self.bytes_allocated.wrapping_sub(self.bytes_freed) as isize
+    }
+
+    /// Pages allocated and not freed since the snapshot, negative if more
+    /// were freed than allocated.
+    pub fn net_pages(&self) -> isize {
+        self.pages_allocated.wrapping_sub(self.pages_freed) as isize
+    }
+}
+
+/// An allocator operation, for driving the allocator from generated
+/// sequences (e.g. property-based tests or fuzzers).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EarlyOp {
+    /// [`ByteAllocator::alloc`] with the given layout.
+    AllocBytes { size: usize, align: usize },
+    /// [`ByteAllocator::dealloc`] of a block returned by `AllocBytes`.
+    DeallocBytes { handle: EarlyHandle },
+    /// [`PageAllocator::alloc_pages`] of `n` pages aligned to `align` bytes.
+    AllocPages { n: usize, align: usize },
+    /// [`PageAllocator::dealloc_pages`] of a block returned by `AllocPages`.
+    DeallocPages { handle: EarlyHandle },
+}
+
+/// Outcome of an [`EarlyOp`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EarlyOpResult {
+    /// The allocation succeeded.
+    Allocated(EarlyHandle),
+    /// The block was freed.
+    Deallocated,
+    /// The allocation failed.
+    Failed(AllocError),
+}
 
 /// Early memory allocator
 /// Use it before formal bytes-allocator and pages-allocator can work!
@@ -12,90 +202,637 @@ use allocator::{BaseAllocator, ByteAllocator, PageAllocator};
 /// |            | -->    <-- |            |
 /// start       b_pos        p_pos       end
 ///
+/// With the [`Reversed`] direction the two areas swap ends:
+///
+/// [ pages-used | avail-area | bytes-used ]
+/// |            | -->    <-- |            |
+/// start       p_pos        b_pos       end
+///
 /// For bytes area, 'count' records number of allocations.
-/// When it goes down to ZERO, free bytes-used area.
+/// When it goes down to ZERO, free bytes-used area, whatever the order of
+/// the frees was.
 /// For pages area, it will never be freed!
 ///
-pub struct EarlyAllocator<const PAGE_SIZE: usize> {
+/// Free holes below `b_pos` (alignment padding skipped by `alloc`, or blocks
+/// freed out of order) are kept in a small bounded list, and consulted before
+/// bumping `b_pos`.
+///
+pub struct EarlyAllocator<const PAGE_SIZE: usize, D: Direction = Forward> {
     start: usize,
     end: usize,
     b_pos: usize,
     p_pos: usize,
+    /// Free `(start, end)` holes in the bytes area, empty if `start == end`.
+    gaps: [(usize, usize); MAX_GAPS],
+    /// Number of live byte allocations.
+    count: usize,
+    on_alloc: Option<AllocHook>,
+    on_dealloc: Option<AllocHook>,
+    /// Maximum number of bytes `alloc_pages` may skip to honor an alignment.
+    max_align_waste: Option<usize>,
+    /// Inner edge of the DMA blocks, i.e. the start of the lowest one (the
+    /// end of the highest one when reversed), or the pages end of the arena
+    /// if there is none. Page frees can never move `p_pos` past it.
+    dma_floor: usize,
+    /// Number of pages allocated for DMA.
+    dma_pages: usize,
+    /// Running totals, never reset.
+    totals: EarlySnapshot,
+    direction: PhantomData<D>,
 }
 
-impl<const PAGE_SIZE: usize> EarlyAllocator<PAGE_SIZE> {
+impl<const PAGE_SIZE: usize, D: Direction> EarlyAllocator<PAGE_SIZE, D> {
     pub const fn new() -> Self {
         Self {
             start: 0,
             end: 0,
             b_pos: 0,
             p_pos: 0,
+            gaps: [(0, 0); MAX_GAPS],
+            count: 0,
+            on_alloc: None,
+            on_dealloc: None,
+            max_align_waste: None,
+            dma_floor: 0,
+            dma_pages: 0,
+            totals: EarlySnapshot {
+                bytes_allocated: 0,
+                bytes_freed: 0,
+                pages_allocated: 0,
+                pages_freed: 0,
+            },
+            direction: PhantomData,
+        }
+    }
+
+    /// The end of the arena the bytes area grows from.
+    fn bytes_base(&self) -> usize {
+        if D::REVERSED {
+            self.end
+        } else {
+            self.start
+        }
+    }
+
+    /// The end of the arena the pages area grows from.
+    fn pages_base(&self) -> usize {
+        if D::REVERSED {
+            self.start
+        } else {
+            self.end
+        }
+    }
+
+    /// Sets the hook called after each successful byte or page allocation,
+    /// or removes it with `None`.
+    pub fn set_on_alloc(&mut self, hook: Option<AllocHook>) {
+        self.on_alloc = hook;
+    }
+
+    /// Sets the hook called after each byte or page deallocation, or removes
+    /// it with `None`.
+    pub fn set_on_dealloc(&mut self, hook: Option<AllocHook>) {
+        self.on_dealloc = hook;
+    }
+
+    /// Limits the number of bytes that a page allocation may skip below the
+    /// previous page allocation to reach the requested alignment, or lifts
+    /// the limit with `None`.
+    ///
+    /// A request whose alignment would waste more fails with
+    /// [`AllocError::NoMemory`] and leaves the allocator untouched, instead of
+    /// silently consuming up to `align_pow2` bytes of the arena (e.g. 2 MiB
+    /// for a huge-page aligned page).
+    pub fn set_max_align_waste(&mut self, max_waste: Option<usize>) {
+        self.max_align_waste = max_waste;
+    }
+
+    /// Creates an allocator managing the given statically-allocated buffer,
+    /// without a separate [`init`](BaseAllocator::init) call.
+    pub fn from_slice(buf: &'static mut [u8]) -> Self {
+        let mut early = Self::new();
+        early.init(buf.as_mut_ptr() as usize, buf.len());
+        early
+    }
+
+    /// Panics if any bytes or pages are still allocated.
+    ///
+    /// Meant to be called at the end of a test to catch leaks in higher-level
+    /// code built on top of this allocator.
+    pub fn assert_no_leaks(&self) {
+        let used_bytes = self.used_bytes();
+        let used_pages = self.used_pages();
+        if used_bytes != 0 || used_pages != 0 {
+            panic!(
+                "EarlyAllocator: leak detected: {} bytes and {} pages still used \
+                 (b_pos = {:#x}, p_pos = {:#x}, range = [{:#x}, {:#x}))",
+                used_bytes, used_pages, self.b_pos, self.p_pos, self.start, self.end
+            );
+        }
+    }
+
+    /// Frees every byte and page allocation at once, DMA blocks included,
+    /// as if the arena had just been passed to
+    /// [`init`](BaseAllocator::init). Hooks and limits are kept.
+    ///
+    /// Nothing tracks the blocks handed out so far: the caller must make sure
+    /// none of them is used after the reset, including by devices doing DMA,
+    /// as they will be handed out again.
+    ///
+    /// Does nothing on an allocator that was never initialized.
+    pub fn reset(&mut self) {
+        if self.start == self.end {
+            return;
+        }
+        self.init(self.start, self.end - self.start);
+    }
+
+    /// Returns true if no bytes are allocated, i.e. every byte allocation has
+    /// been freed and the whole bytes area went back to the free middle.
+    ///
+    /// From then on, [`available_pages`](PageAllocator::available_pages)
+    /// counts the reclaimed bytes area too, as pages are carved down to
+    /// `b_pos`.
+    pub fn bytes_fully_freed(&self) -> bool {
+        self.b_pos == self.bytes_base()
+    }
+
+    /// Hands the untouched middle of the arena over to another allocator.
+    ///
+    /// Returns the free gap as `(start, end)`, i.e. `[b_pos, p_pos)` (or
+    /// `[p_pos, b_pos)` when reversed), and
+    /// marks it as consumed so that neither bytes nor pages can be allocated
+    /// from it afterwards. Typically called during boot to initialize the
+    /// formal allocator over the remaining memory without overlap.
+    pub fn split_off_free(&mut self) -> (usize, usize) {
+        let free = if D::REVERSED {
+            (self.p_pos, self.b_pos)
+        } else {
+            (self.b_pos, self.p_pos)
+        };
+        self.b_pos = self.p_pos;
+        // a block that is never freed, so that freeing every other one
+        // cannot reclaim the area handed over
+        self.count += 1;
+        free
+    }
+
+    /// Moves the arena to `new_start`, shifting every cursor and recorded
+    /// address by the same delta, e.g. after the arena has been remapped to
+    /// another virtual address.
+    ///
+    /// Only the bookkeeping moves: blocks handed out earlier keep their old
+    /// addresses, which callers must translate themselves.
+    ///
+    /// # Safety
+    ///
+    /// The caller must have copied the whole arena to (or mapped it at)
+    /// `[new_start, new_start + total_bytes)` beforehand. Nothing here checks
+    /// that the new range is valid memory; allocations made afterwards are
+    /// carved from it blindly.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the delta is not a multiple of `PAGE_SIZE`, which would break
+    /// the alignment of the page blocks.
+    pub unsafe fn rebase(&mut self, new_start: usize) {
+        let delta = new_start.wrapping_sub(self.start);
+        assert!(
+            delta % PAGE_SIZE == 0,
+            "EarlyAllocator: rebase by {:#x} is not page aligned",
+            delta
+        );
+        let shift = |addr: &mut usize| *addr = addr.wrapping_add(delta);
+        shift(&mut self.start);
+        shift(&mut self.end);
+        shift(&mut self.b_pos);
+        shift(&mut self.p_pos);
+        shift(&mut self.dma_floor);
+        for gap in self.gaps.iter_mut().filter(|gap| gap.0 < gap.1) {
+            shift(&mut gap.0);
+            shift(&mut gap.1);
+        }
+    }
+
+    /// Allocates physically contiguous pages for DMA, aligned to `align_pow2`
+    /// bytes.
+    ///
+    /// Pages from this allocator are always contiguous; DMA blocks are in
+    /// addition pinned, and are never freed: passing one to
+    /// [`dealloc_pages`](PageAllocator::dealloc_pages) panics.
+    pub fn alloc_dma_pages(
+        &mut self,
+        num_pages: usize,
+        align_pow2: usize,
+    ) -> allocator::AllocResult<usize> {
+        let addr = self.alloc_pages(num_pages, align_pow2)?;
+        self.dma_floor = self.p_pos;
+        self.dma_pages += num_pages;
+        Ok(addr)
+    }
+
+    /// Returns the number of pages allocated with
+    /// [`alloc_dma_pages`](Self::alloc_dma_pages).
+    pub fn used_dma_pages(&self) -> usize {
+        self.dma_pages
+    }
+
+    /// Returns how many single pages aligned to `align_pow2` bytes
+    /// [`alloc_pages`](PageAllocator::alloc_pages) can hand out one by one.
+    ///
+    /// Each page aligned above `PAGE_SIZE` skips the padding down (up when
+    /// reversed) to the next aligned address, so this is lower than
+    /// [`available_pages`](PageAllocator::available_pages) in general. The
+    /// limit set by [`set_max_align_waste`](Self::set_max_align_waste) is
+    /// taken into account. Returns 0 for an alignment `alloc_pages` rejects.
+    pub fn available_pages_aligned(&self, align_pow2: usize) -> usize {
+        if align_pow2 % PAGE_SIZE != 0 || !(align_pow2 / PAGE_SIZE).is_power_of_two() {
+            return 0;
+        }
+        // the first page, and the room left past it for the others
+        let first = if D::REVERSED {
+            self.p_pos
+                .checked_add(align_pow2 - 1)
+                .map(|pos| pos & !(align_pow2 - 1))
+                .filter(|&pos| {
+                    pos.checked_add(PAGE_SIZE)
+                        .is_some_and(|end| end <= self.b_pos)
+                })
+                .map(|pos| (pos - self.p_pos, self.b_pos - PAGE_SIZE - pos))
+        } else {
+            self.p_pos
+                .checked_sub(PAGE_SIZE)
+                .map(|pos| (pos, pos & !(align_pow2 - 1)))
+                .filter(|&(_, aligned)| aligned >= self.b_pos)
+                .map(|(pos, aligned)| (pos - aligned, aligned - self.b_pos))
+        };
+        let Some((first_waste, room)) = first else {
+            return 0;
+        };
+        match self.max_align_waste {
+            Some(max) if first_waste > max => 0,
+            // every following page wastes `align_pow2 - PAGE_SIZE`
+            Some(max) if align_pow2 - PAGE_SIZE > max => 1,
+            _ => room / align_pow2 + 1,
+        }
+    }
+
+    /// Returns a snapshot of the cursors and usage of the allocator.
+    pub fn stats(&self) -> EarlyStats {
+        EarlyStats {
+            start: self.start,
+            end: self.end,
+            b_pos: self.b_pos,
+            p_pos: self.p_pos,
+            bytes_used: self.used_bytes(),
+            pages_used: self.used_pages(),
+            available: self.available_bytes(),
+        }
+    }
+
+    /// Captures the running allocation totals, to be compared later with
+    /// [`diff`](Self::diff).
+    pub fn snapshot(&self) -> EarlySnapshot {
+        self.totals
+    }
+
+    /// Reports what was allocated and freed since `earlier` was taken.
+    ///
+    /// The totals survive [`reset`](Self::reset), so a reset in between does
+    /// not count as freeing anything.
+    pub fn diff(&self, earlier: &EarlySnapshot) -> EarlySnapshotDiff {
+        let now = self.totals;
+        EarlySnapshotDiff {
+            bytes_allocated: now.bytes_allocated.wrapping_sub(earlier.bytes_allocated),
+            bytes_freed: now.bytes_freed.wrapping_sub(earlier.bytes_freed),
+            pages_allocated: now.pages_allocated.wrapping_sub(earlier.pages_allocated),
+            pages_freed: now.pages_freed.wrapping_sub(earlier.pages_freed),
+        }
+    }
+
+    /// Opens a scope for scratch byte allocations, all reclaimed at once
+    /// when the returned guard is dropped, see [`Scope`].
+    pub fn scope(&mut self) -> Scope<'_, PAGE_SIZE, D> {
+        Scope {
+            b_pos: self.b_pos,
+            gaps: self.gaps,
+            count: self.count,
+            totals: self.totals,
+            early: self,
+        }
+    }
+
+    /// Performs `op`, as the corresponding allocator method would.
+    ///
+    /// Invalid layouts fail with [`AllocError::InvalidParam`]; invalid frees
+    /// panic like [`dealloc`](ByteAllocator::dealloc) and
+    /// [`dealloc_pages`](PageAllocator::dealloc_pages) do.
+    pub fn apply(&mut self, op: EarlyOp) -> EarlyOpResult {
+        match op {
+            EarlyOp::AllocBytes { size, align } => {
+                let layout = match Layout::from_size_align(size, align) {
+                    Ok(layout) => layout,
+                    Err(_) => return EarlyOpResult::Failed(AllocError::InvalidParam),
+                };
+                match self.alloc(layout) {
+                    Ok(ptr) => EarlyOpResult::Allocated(EarlyHandle {
+                        addr: ptr.as_ptr() as usize,
+                        size,
+                        align,
+                    }),
+                    Err(err) => EarlyOpResult::Failed(err),
+                }
+            }
+            EarlyOp::DeallocBytes { handle } => {
+                let layout = Layout::from_size_align(handle.size, handle.align).unwrap();
+                let ptr = NonNull::new(handle.addr as *mut u8).unwrap();
+                self.dealloc(ptr, layout);
+                EarlyOpResult::Deallocated
+            }
+            EarlyOp::AllocPages { n, align } => match self.alloc_pages(n, align) {
+                Ok(addr) => EarlyOpResult::Allocated(EarlyHandle {
+                    addr,
+                    size: n,
+                    align,
+                }),
+                Err(err) => EarlyOpResult::Failed(err),
+            },
+            EarlyOp::DeallocPages { handle } => {
+                self.dealloc_pages(handle.addr, handle.size);
+                EarlyOpResult::Deallocated
+            }
+        }
+    }
+
+    /// Allocates a block like [`alloc`](ByteAllocator::alloc), and fills it
+    /// with zeros before returning it.
+    pub fn alloc_zeroed(&mut self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        let ptr = self.alloc(layout)?;
+        // SAFETY: the block was just carved from the arena, which the caller
+        // of `init` handed over as valid memory.
+        unsafe { ptr.as_ptr().write_bytes(0, layout.size()) };
+        Ok(ptr)
+    }
+
+    /// Frees a block like [`dealloc`](ByteAllocator::dealloc), but fails with
+    /// [`AllocError::InvalidParam`] instead of panicking if `pos` was not
+    /// allocated from the bytes area, e.g. lies past its allocated part.
+    pub fn try_dealloc(&mut self, pos: NonNull<u8>, layout: Layout) -> AllocResult<()> {
+        self.dealloc_bytes(pos.as_ptr() as usize, layout.size())
+            .map_err(|_| AllocError::InvalidParam)
+    }
+
+    /// Frees pages like [`dealloc_pages`](PageAllocator::dealloc_pages), but
+    /// fails with [`AllocError::InvalidParam`] instead of panicking for DMA
+    /// pages or anything but the most recent page allocation.
+    pub fn try_dealloc_pages(&mut self, pos: usize, num_pages: usize) -> AllocResult<()> {
+        self.dealloc_pages_at(pos, num_pages)
+            .map_err(|_| AllocError::InvalidParam)
+    }
+
+    /// Frees a block of the bytes area, or tells why it cannot be freed
+    /// without changing anything.
+    fn dealloc_bytes(&mut self, ptr: usize, size: usize) -> Result<(), &'static str> {
+        // 检查基本合法性
+        if ptr < self.start || ptr >= self.end {
+            return Err("invalid deallocation, out of memory bounds");
+        }
+
+        // 不允许 dealloc Page 区域分配出来的内存
+        if (!D::REVERSED && ptr >= self.p_pos) || (D::REVERSED && ptr < self.p_pos) {
+            return Err("cannot deallocate memory allocated by PageAllocator");
+        }
+
+        // 只允许回滚最近一次分配的内存 (LIFO)，其余的块记录为空洞以便复用
+        // 最后一个块释放时整个 bytes 区域回收
+        let (last, beyond) = if D::REVERSED {
+            (ptr == self.b_pos, ptr < self.b_pos)
+        } else {
+            (ptr + size == self.b_pos, ptr + size > self.b_pos)
+        };
+        if beyond || self.count == 0 {
+            return Err("invalid deallocation order (non-LIFO dealloc)");
+        } else if self.count == 1 {
+            // the last live block, whatever its position
+            self.b_pos = self.bytes_base();
+            self.gaps = [(0, 0); MAX_GAPS];
+        } else if last {
+            self.b_pos = if D::REVERSED { ptr + size } else { ptr };
+            self.reclaim_gaps();
+        } else {
+            // if the list is full, the block is only reclaimed once the count
+            // drops to zero
+            self.add_gap(ptr, ptr + size);
+        }
+        self.count -= 1;
+        self.totals.bytes_freed = self.totals.bytes_freed.wrapping_add(size);
+        if let Some(hook) = self.on_dealloc {
+            hook(AllocKind::Bytes, size, ptr);
+        }
+        Ok(())
+    }
+
+    /// Frees pages, or tells why they cannot be freed without changing
+    /// anything.
+    fn dealloc_pages_at(&mut self, pos: usize, num_pages: usize) -> Result<(), &'static str> {
+        let size = num_pages * PAGE_SIZE;
+        if (!D::REVERSED && pos >= self.dma_floor) || (D::REVERSED && pos < self.dma_floor) {
+            return Err("cannot deallocate DMA pages");
+        }
+        // only the most recent page allocation, which ends at `p_pos` when
+        // reversed and starts there otherwise
+        if D::REVERSED {
+            if pos + size != self.p_pos {
+                return Err("invalid deallocation order (non-LIFO dealloc)");
+            }
+            self.p_pos = pos;
+        } else {
+            if pos != self.p_pos {
+                return Err("invalid deallocation order (non-LIFO dealloc)");
+            }
+            self.p_pos += size;
+        }
+        self.totals.pages_freed = self.totals.pages_freed.wrapping_add(num_pages);
+        if let Some(hook) = self.on_dealloc {
+            hook(AllocKind::Pages, size, pos);
+        }
+        Ok(())
+    }
+
+    /// Carves a block from a recorded gap, if one is large enough.
+    fn take_gap(&mut self, size: usize, align: usize) -> Option<usize> {
+        let align_up = |pos: usize| pos.checked_add(align - 1).map(|pos| pos & !(align - 1));
+        let fits = |&(start, end): &(usize, usize)| {
+            start < end
+                && align_up(start)
+                    .and_then(|pos| pos.checked_add(size))
+                    .is_some_and(|block_end| block_end <= end)
+        };
+        let idx = self.gaps.iter().position(fits)?;
+        let (start, end) = self.gaps[idx];
+        let aligned = align_up(start)?;
+        self.gaps[idx] = (start, aligned);
+        // the tail is dropped if the list is full
+        self.add_gap(aligned + size, end);
+        Some(aligned)
+    }
+
+    /// Records a free gap, returns false if the list is full.
+    fn add_gap(&mut self, start: usize, end: usize) -> bool {
+        if start == end {
+            return true;
+        }
+        match self.gaps.iter_mut().find(|gap| gap.0 == gap.1) {
+            Some(slot) => {
+                *slot = (start, end);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Called after `b_pos` moved back: merges gaps that now touch `b_pos`
+    /// into the available area, and drops those beyond it.
+    fn reclaim_gaps(&mut self) {
+        while let Some(gap) = self
+            .gaps
+            .iter_mut()
+            .find(|gap| gap.0 < gap.1 && self.b_pos == if D::REVERSED { gap.0 } else { gap.1 })
+        {
+            self.b_pos = if D::REVERSED { gap.1 } else { gap.0 };
+            *gap = (0, 0);
+        }
+        for gap in self.gaps.iter_mut() {
+            if D::REVERSED {
+                gap.0 = gap.0.max(self.b_pos);
+                gap.1 = gap.1.max(gap.0);
+            } else {
+                gap.1 = gap.1.min(self.b_pos);
+                gap.0 = gap.0.min(gap.1);
+            }
         }
     }
 }
 
-impl<const PAGE_SIZE: usize> BaseAllocator for EarlyAllocator<PAGE_SIZE> {
+impl<const PAGE_SIZE: usize, D: Direction> BaseAllocator for EarlyAllocator<PAGE_SIZE, D> {
+    /// Panics if the region is empty or wraps around the address space.
     fn init(&mut self, start: usize, size: usize) {
+        assert!(size > 0, "EarlyAllocator: cannot init with an empty region");
+        let end = start
+            .checked_add(size)
+            .expect("EarlyAllocator: init region overflows the address space");
         self.start = start;
-        self.end = start + size;
-        self.b_pos = start;
-        self.p_pos = start + size;
+        self.end = end;
+        self.b_pos = self.bytes_base();
+        self.p_pos = self.pages_base();
+        self.gaps = [(0, 0); MAX_GAPS];
+        self.count = 0;
+        self.dma_floor = self.pages_base();
+        self.dma_pages = 0;
     }
-    fn add_memory(&mut self, _start: usize, _size: usize) -> allocator::AllocResult {
-        Err(allocator::AllocError::NoMemory)
+    /// Extends the arena with a region adjacent to it, below `start` or
+    /// above `end`.
+    ///
+    /// Next to an empty area, the region simply widens the free middle.
+    /// Next to a bytes area in use, it is recorded as a free gap, or rejected
+    /// with [`AllocError::NoMemory`] if the gap list is full. Next to a pages
+    /// area in use, which cannot skip it, it is rejected with
+    /// [`AllocError::InvalidParam`], as are non-adjacent regions.
+    fn add_memory(&mut self, start: usize, size: usize) -> allocator::AllocResult {
+        let end = start.checked_add(size).ok_or(AllocError::InvalidParam)?;
+        let (below, above) = (end == self.start, start == self.end);
+        if size == 0 {
+            return Ok(());
+        } else if !below && !above {
+            return Err(AllocError::InvalidParam);
+        }
+        // `below` is the bytes side unless reversed
+        if below != D::REVERSED {
+            if self.bytes_fully_freed() {
+                self.b_pos = if below { start } else { end };
+            } else if !self.add_gap(start, end) {
+                return Err(AllocError::NoMemory);
+            }
+        } else {
+            if self.p_pos != self.pages_base() {
+                return Err(AllocError::InvalidParam);
+            }
+            self.p_pos = if below { start } else { end };
+            self.dma_floor = self.p_pos;
+        }
+        if below {
+            self.start = start;
+        } else {
+            self.end = end;
+        }
+        Ok(())
     }
 }
 
-impl<const PAGE_SIZE: usize> ByteAllocator for EarlyAllocator<PAGE_SIZE> {
+impl<const PAGE_SIZE: usize, D: Direction> ByteAllocator for EarlyAllocator<PAGE_SIZE, D> {
     fn alloc(
         &mut self,
         layout: core::alloc::Layout,
     ) -> allocator::AllocResult<core::ptr::NonNull<u8>> {
         let align = layout.align();
         let size = layout.size();
-        let aligned_cursor = (self.b_pos + align - 1) & !(align - 1);
-        if aligned_cursor + size > self.p_pos {
-            return Err(allocator::AllocError::NoMemory);
+        // always true for a `Layout`, but cheap compared to a bogus pointer
+        if !align.is_power_of_two() {
+            return Err(allocator::AllocError::InvalidParam);
+        }
+        let ptr = match self.take_gap(size, align) {
+            Some(ptr) => ptr,
+            None if D::REVERSED => {
+                let aligned_cursor = self
+                    .b_pos
+                    .checked_sub(size)
+                    .map(|pos| pos & !(align - 1))
+                    .filter(|&pos| pos >= self.p_pos)
+                    .ok_or(allocator::AllocError::NoMemory)?;
+                // remember the skipped padding for a later smaller allocation
+                self.add_gap(aligned_cursor + size, self.b_pos);
+                self.b_pos = aligned_cursor;
+                aligned_cursor
+            }
+            None => {
+                let aligned_cursor = self
+                    .b_pos
+                    .checked_add(align - 1)
+                    .map(|pos| pos & !(align - 1))
+                    .filter(|&pos| pos.checked_add(size).is_some_and(|end| end <= self.p_pos))
+                    .ok_or(allocator::AllocError::NoMemory)?;
+                // remember the skipped padding for a later smaller allocation
+                self.add_gap(self.b_pos, aligned_cursor);
+                self.b_pos = aligned_cursor + size;
+                aligned_cursor
+            }
+        };
+        self.count += 1;
+        self.totals.bytes_allocated = self.totals.bytes_allocated.wrapping_add(size);
+        if let Some(hook) = self.on_alloc {
+            hook(AllocKind::Bytes, size, ptr);
         }
-        let ptr = aligned_cursor as *mut u8;
-        self.b_pos = aligned_cursor + size;
-        Ok(unsafe { core::ptr::NonNull::new_unchecked(ptr) })
+        Ok(unsafe { core::ptr::NonNull::new_unchecked(ptr as *mut u8) })
     }
     fn dealloc(&mut self, pos: core::ptr::NonNull<u8>, layout: core::alloc::Layout) {
-        let size = layout.size();
-        let ptr = pos.as_ptr() as usize;
-    
-        // 检查基本合法性
-        if ptr < self.start || ptr >= self.end {
-            panic!("EarlyAllocator: invalid deallocation, out of memory bounds");
-        }
-    
-        // 不允许 dealloc Page 区域分配出来的内存
-        if ptr >= self.p_pos {
-            panic!("EarlyAllocator: cannot deallocate memory allocated by PageAllocator");
-        }
-    
-        // 只允许回滚最近一次分配的内存 (LIFO)
-        if ptr + size == self.b_pos {
-            self.b_pos = ptr;
-        } else {
-            panic!("EarlyAllocator: invalid deallocation order (non-LIFO dealloc)");
+        if let Err(msg) = self.dealloc_bytes(pos.as_ptr() as usize, layout.size()) {
+            panic!("EarlyAllocator: {}", msg);
         }
     }
-    
+
     fn used_bytes(&self) -> usize {
-        self.b_pos - self.start
+        self.b_pos.abs_diff(self.bytes_base())
     }
     fn available_bytes(&self) -> usize {
-        self.p_pos - self.b_pos
+        self.p_pos.abs_diff(self.b_pos)
     }
     fn total_bytes(&self) -> usize {
         self.end - self.start
     }
 }
 
-impl<const PAGE_SIZE: usize> PageAllocator for EarlyAllocator<PAGE_SIZE> {
-    const PAGE_SIZE: usize = 4096; // Define the page size, e.g., 4KB
+impl<const PAGE_SIZE: usize, D: Direction> PageAllocator for EarlyAllocator<PAGE_SIZE, D> {
+    const PAGE_SIZE: usize = PAGE_SIZE;
 
     fn alloc_pages(
         &mut self,
@@ -112,33 +849,182 @@ impl<const PAGE_SIZE: usize> PageAllocator for EarlyAllocator<PAGE_SIZE> {
     
         let total_size = num_pages * PAGE_SIZE;
     
-        let mut alloc_start = self.p_pos.checked_sub(total_size)
-            .ok_or(allocator::AllocError::NoMemory)?;
-        // 做对齐：alloc_start 向下对齐 align_pow2
-        alloc_start = alloc_start & !(align_pow2 - 1);
-        if alloc_start < self.b_pos {
+        let (alloc_start, waste) = if D::REVERSED {
+            // 反向布局：alloc_start 向上对齐 align_pow2
+            let alloc_start = self
+                .p_pos
+                .checked_add(align_pow2 - 1)
+                .map(|pos| pos & !(align_pow2 - 1))
+                .filter(|&pos| {
+                    pos.checked_add(total_size)
+                        .is_some_and(|end| end <= self.b_pos)
+                })
+                .ok_or(allocator::AllocError::NoMemory)?;
+            (alloc_start, alloc_start - self.p_pos)
+        } else {
+            let mut alloc_start = self.p_pos.checked_sub(total_size)
+                .ok_or(allocator::AllocError::NoMemory)?;
+            // 做对齐：alloc_start 向下对齐 align_pow2
+            let unaligned_start = alloc_start;
+            alloc_start = alloc_start & !(align_pow2 - 1);
+            if alloc_start < self.b_pos {
+                return Err(allocator::AllocError::NoMemory);
+            }
+            (alloc_start, unaligned_start - alloc_start)
+        };
+        if self.max_align_waste.is_some_and(|max| waste > max) {
             return Err(allocator::AllocError::NoMemory);
         }
-        self.p_pos = alloc_start;
+        self.p_pos = if D::REVERSED {
+            alloc_start + total_size
+        } else {
+            alloc_start
+        };
+        self.totals.pages_allocated = self.totals.pages_allocated.wrapping_add(num_pages);
+        if let Some(hook) = self.on_alloc {
+            hook(AllocKind::Pages, total_size, alloc_start);
+        }
         Ok(alloc_start)
     }
     
+    /// Counts only whole pages of the free middle, i.e. as many as
+    /// `alloc_pages` can hand out one by one.
     fn available_pages(&self) -> usize {
-        (self.p_pos - self.b_pos) >> Self::PAGE_SIZE.trailing_zeros() as usize
+        let (lo, hi) = if D::REVERSED {
+            (self.p_pos, self.b_pos)
+        } else {
+            (self.b_pos, self.p_pos)
+        };
+        let lo = lo.div_ceil(PAGE_SIZE);
+        let hi = hi / PAGE_SIZE;
+        hi.saturating_sub(lo)
     }
     fn dealloc_pages(&mut self, pos: usize, num_pages: usize) {
-        let size = num_pages * PAGE_SIZE;
-        let expected_pos = self.p_pos + size;
-        if pos != expected_pos {
-            panic!("EarlyAllocator: invalid deallocation order (non-LIFO dealloc)");
+        if let Err(msg) = self.dealloc_pages_at(pos, num_pages) {
+            panic!("EarlyAllocator: {}", msg);
         }
-        self.p_pos += size;
     }
-    
+
     fn used_pages(&self) -> usize {
-        (self.p_pos - self.end) >> Self::PAGE_SIZE.trailing_zeros() as usize
+        self.p_pos.abs_diff(self.pages_base()) / PAGE_SIZE
     }
     fn total_pages(&self) -> usize {
-        (self.end - self.start) >> Self::PAGE_SIZE.trailing_zeros() as usize
+        (self.end - self.start) / PAGE_SIZE
+    }
+}
+
+/// A scope of byte allocations of an [`EarlyAllocator`], see
+/// [`EarlyAllocator::scope`].
+///
+/// The state of the bytes area is captured when the scope is opened and
+/// restored when it is dropped, so every block allocated through the scope
+/// is freed, without calling the dealloc hook. The pages area is left
+/// alone.
+///
+/// Blocks allocated before the scope must not be freed through it, as they
+/// would count as allocated again once the scope ends.
+pub struct Scope<'a, const PAGE_SIZE: usize, D: Direction = Forward> {
+    early: &'a mut EarlyAllocator<PAGE_SIZE, D>,
+    b_pos: usize,
+    gaps: [(usize, usize); MAX_GAPS],
+    count: usize,
+    totals: EarlySnapshot,
+}
+
+impl<const PAGE_SIZE: usize, D: Direction> Scope<'_, PAGE_SIZE, D> {
+    /// See [`ByteAllocator::alloc`].
+    pub fn alloc(&mut self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        self.early.alloc(layout)
+    }
+
+    /// See [`EarlyAllocator::alloc_zeroed`].
+    pub fn alloc_zeroed(&mut self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        self.early.alloc_zeroed(layout)
+    }
+
+    /// See [`ByteAllocator::dealloc`].
+    pub fn dealloc(&mut self, pos: NonNull<u8>, layout: Layout) {
+        self.early.dealloc(pos, layout);
+    }
+
+    /// See [`EarlyAllocator::try_dealloc`].
+    pub fn try_dealloc(&mut self, pos: NonNull<u8>, layout: Layout) -> AllocResult<()> {
+        self.early.try_dealloc(pos, layout)
+    }
+
+    /// See [`ByteAllocator::used_bytes`].
+    pub fn used_bytes(&self) -> usize {
+        self.early.used_bytes()
+    }
+
+    /// See [`ByteAllocator::available_bytes`].
+    pub fn available_bytes(&self) -> usize {
+        self.early.available_bytes()
+    }
+}
+
+impl<const PAGE_SIZE: usize, D: Direction> Drop for Scope<'_, PAGE_SIZE, D> {
+    fn drop(&mut self) {
+        let early = &mut *self.early;
+        early.b_pos = self.b_pos;
+        early.gaps = self.gaps;
+        early.count = self.count;
+        // the blocks still live count as freed
+        let totals = &mut early.totals;
+        let live = totals
+            .bytes_allocated
+            .wrapping_sub(self.totals.bytes_allocated)
+            .wrapping_sub(totals.bytes_freed.wrapping_sub(self.totals.bytes_freed));
+        totals.bytes_freed = totals.bytes_freed.wrapping_add(live);
+    }
+}
+
+/// An [`EarlyAllocator`] behind a spin lock, usable through a shared
+/// reference, e.g. from a `static`.
+///
+/// Each method locks the allocator for the duration of the call. Use
+/// [`lock`](Self::lock) for anything else.
+pub struct LockedEarlyAllocator<const PAGE_SIZE: usize>(spin::Mutex<EarlyAllocator<PAGE_SIZE>>);
+
+impl<const PAGE_SIZE: usize> LockedEarlyAllocator<PAGE_SIZE> {
+    /// Creates an uninitialized allocator, see [`init`](Self::init).
+    pub const fn new() -> Self {
+        Self(spin::Mutex::new(EarlyAllocator::new()))
+    }
+
+    /// Locks the allocator.
+    pub fn lock(&self) -> spin::MutexGuard<'_, EarlyAllocator<PAGE_SIZE>> {
+        self.0.lock()
+    }
+
+    /// See [`BaseAllocator::init`].
+    pub fn init(&self, start: usize, size: usize) {
+        self.lock().init(start, size);
+    }
+
+    /// See [`ByteAllocator::alloc`].
+    pub fn alloc(&self, layout: Layout) -> AllocResult<NonNull<u8>> {
+        self.lock().alloc(layout)
+    }
+
+    /// See [`ByteAllocator::dealloc`].
+    pub fn dealloc(&self, pos: NonNull<u8>, layout: Layout) {
+        self.lock().dealloc(pos, layout);
+    }
+
+    /// See [`PageAllocator::alloc_pages`].
+    pub fn alloc_pages(&self, num_pages: usize, align_pow2: usize) -> AllocResult<usize> {
+        self.lock().alloc_pages(num_pages, align_pow2)
+    }
+
+    /// See [`PageAllocator::dealloc_pages`].
+    pub fn dealloc_pages(&self, pos: usize, num_pages: usize) {
+        self.lock().dealloc_pages(pos, num_pages);
+    }
+}
+
+impl<const PAGE_SIZE: usize> Default for LockedEarlyAllocator<PAGE_SIZE> {
+    fn default() -> Self {
+        Self::new()
     }
 }
@@ -0,0 +1,768 @@
+use core::alloc::Layout;
+use core::ptr::NonNull;
+use std::sync::Mutex;
+
+use allocator::{AllocError, BaseAllocator, ByteAllocator, PageAllocator};
+
+use crate::{
+    AllocKind, Direction, EarlyAllocator, EarlyOp, EarlyOpResult, Forward, LockedEarlyAllocator,
+    Reversed, MAX_GAPS,
+};
+
+const PAGE_SIZE: usize = 0x1000;
+
+/// Leaks a page-aligned heap region to back an allocator under test.
+fn arena(size: usize) -> usize {
+    unsafe { std::alloc::alloc(layout(size, PAGE_SIZE)) as usize }
+}
+
+fn layout(size: usize, align: usize) -> Layout {
+    Layout::from_size_align(size, align).unwrap()
+}
+
+fn new_allocator(size: usize) -> EarlyAllocator<PAGE_SIZE> {
+    let mut early = EarlyAllocator::new();
+    early.init(arena(size), size);
+    early
+}
+
+#[test]
+fn test_no_leaks_balanced() {
+    let mut early = new_allocator(4 * PAGE_SIZE);
+    let ptr = early.alloc(layout(64, 8)).unwrap();
+    early.dealloc(ptr, layout(64, 8));
+    early.assert_no_leaks();
+}
+
+#[test]
+#[should_panic(expected = "leak detected")]
+fn test_no_leaks_detects_byte_leak() {
+    let mut early = new_allocator(4 * PAGE_SIZE);
+    early.alloc(layout(64, 8)).unwrap();
+    early.assert_no_leaks();
+}
+
+#[test]
+fn test_split_off_free() {
+    let mut early = new_allocator(4 * PAGE_SIZE);
+    early.alloc(layout(100, 8)).unwrap();
+    let page = early.alloc_pages(1, PAGE_SIZE).unwrap();
+    let b_pos = early.start + 100;
+
+    assert_eq!(early.split_off_free(), (b_pos, page));
+    assert_eq!(early.available_bytes(), 0);
+    assert_eq!(early.alloc(layout(1, 1)).err(), Some(AllocError::NoMemory));
+    assert_eq!(
+        early.alloc_pages(1, PAGE_SIZE).err(),
+        Some(AllocError::NoMemory)
+    );
+    assert_eq!(early.split_off_free(), (page, page));
+}
+
+#[test]
+fn test_reuse_padding_gap() {
+    let mut early = new_allocator(4 * PAGE_SIZE);
+    let start = early.start;
+    let a = early.alloc(layout(1, 1)).unwrap();
+    let b = early.alloc(layout(8, 64)).unwrap();
+    assert_eq!(b.as_ptr() as usize, start + 64);
+    let used = early.used_bytes();
+
+    // fits in the padding skipped before `b`
+    let c = early.alloc(layout(8, 8)).unwrap();
+    assert_eq!(c.as_ptr() as usize, start + 8);
+    assert_eq!(early.used_bytes(), used);
+
+    // freeing everything merges the gaps back
+    early.dealloc(c, layout(8, 8));
+    early.dealloc(b, layout(8, 64));
+    early.dealloc(a, layout(1, 1));
+    early.assert_no_leaks();
+}
+
+#[test]
+fn test_from_slice() {
+    static mut ARENA: [u8; 4096] = [0; 4096];
+
+    let buf = unsafe { &mut *core::ptr::addr_of_mut!(ARENA) };
+    let start = buf.as_ptr() as usize;
+    let mut early = EarlyAllocator::<PAGE_SIZE>::from_slice(buf);
+    assert_eq!(early.total_bytes(), 4096);
+
+    let ptr = early.alloc(layout(16, 8)).unwrap().as_ptr() as usize;
+    assert!(ptr >= start && ptr + 16 <= start + 4096);
+    assert_eq!(
+        early.alloc(layout(4096, 1)).err(),
+        Some(AllocError::NoMemory)
+    );
+}
+
+static EVENTS: Mutex<Vec<(&str, AllocKind, usize, usize)>> = Mutex::new(Vec::new());
+
+#[test]
+fn test_alloc_hooks() {
+    let mut early = new_allocator(4 * PAGE_SIZE);
+    early.set_on_alloc(Some(|kind, size, addr| {
+        EVENTS.lock().unwrap().push(("alloc", kind, size, addr))
+    }));
+    early.set_on_dealloc(Some(|kind, size, addr| {
+        EVENTS.lock().unwrap().push(("dealloc", kind, size, addr))
+    }));
+
+    let ptr = early.alloc(layout(16, 8)).unwrap();
+    let page = early.alloc_pages(1, PAGE_SIZE).unwrap();
+    early.dealloc(ptr, layout(16, 8));
+    let addr = ptr.as_ptr() as usize;
+    assert_eq!(
+        *EVENTS.lock().unwrap(),
+        [
+            ("alloc", AllocKind::Bytes, 16, addr),
+            ("alloc", AllocKind::Pages, PAGE_SIZE, page),
+            ("dealloc", AllocKind::Bytes, 16, addr),
+        ]
+    );
+
+    early.set_on_alloc(None);
+    early.alloc(layout(16, 8)).unwrap();
+    assert_eq!(EVENTS.lock().unwrap().len(), 3);
+}
+
+#[test]
+fn test_apply_ops() {
+    use EarlyOp::*;
+
+    let mut early = new_allocator(8 * PAGE_SIZE);
+    let (start, end) = (early.start, early.end);
+    let mut apply = |op| {
+        let result = early.apply(op);
+        assert!(start <= early.b_pos && early.b_pos <= early.p_pos && early.p_pos <= end);
+        assert!(early.used_bytes() + early.available_bytes() <= early.total_bytes());
+        result
+    };
+    let allocated = |result| match result {
+        EarlyOpResult::Allocated(handle) => handle,
+        other => panic!("expected an allocation, got {:?}", other),
+    };
+
+    let a = allocated(apply(AllocBytes { size: 24, align: 8 }));
+    let b = allocated(apply(AllocBytes {
+        size: 100,
+        align: 64,
+    }));
+    assert_eq!(b.addr() % 64, 0);
+    let p = allocated(apply(AllocPages {
+        n: 1,
+        align: 4 * PAGE_SIZE,
+    }));
+    assert_eq!(p.addr() % (4 * PAGE_SIZE), 0);
+    let q = allocated(apply(AllocPages {
+        n: 2,
+        align: PAGE_SIZE,
+    }));
+    assert_eq!(q.addr() + 2 * PAGE_SIZE, p.addr());
+    assert!(q.addr() >= b.addr() + 100);
+
+    let invalid = EarlyOpResult::Failed(AllocError::InvalidParam);
+    assert_eq!(apply(AllocBytes { size: 8, align: 3 }), invalid);
+    assert_eq!(apply(AllocPages { n: 1, align: 100 }), invalid);
+    let no_memory = EarlyOpResult::Failed(AllocError::NoMemory);
+    assert_eq!(
+        apply(AllocBytes {
+            size: 8 * PAGE_SIZE,
+            align: 8
+        }),
+        no_memory
+    );
+
+    for handle in [q, p] {
+        assert_eq!(apply(DeallocPages { handle }), EarlyOpResult::Deallocated);
+    }
+    for handle in [b, a] {
+        assert_eq!(apply(DeallocBytes { handle }), EarlyOpResult::Deallocated);
+    }
+    assert_eq!(early.used_bytes(), 0);
+}
+
+#[test]
+fn test_max_align_waste() {
+    const HUGE_PAGE: usize = 0x20_0000;
+    let start = unsafe { std::alloc::alloc(layout(2 * HUGE_PAGE, HUGE_PAGE)) as usize };
+    let mut early = EarlyAllocator::<PAGE_SIZE>::new();
+    early.init(start, 2 * HUGE_PAGE);
+    early.set_max_align_waste(Some(16 * PAGE_SIZE));
+
+    let top = early.alloc_pages(1, PAGE_SIZE).unwrap();
+    assert_eq!(top, start + 2 * HUGE_PAGE - PAGE_SIZE);
+    // reaching the 2 MiB boundary would skip almost 2 MiB
+    assert_eq!(early.alloc_pages(1, HUGE_PAGE), Err(AllocError::NoMemory));
+    assert_eq!(early.available_pages(), 2 * HUGE_PAGE / PAGE_SIZE - 1);
+    assert_eq!(early.alloc_pages(1, 4 * PAGE_SIZE), Ok(top - 3 * PAGE_SIZE));
+
+    early.set_max_align_waste(None);
+    assert_eq!(early.alloc_pages(1, HUGE_PAGE), Ok(start + HUGE_PAGE));
+}
+
+#[test]
+fn test_alloc_dma_pages() {
+    let mut early = new_allocator(16 * PAGE_SIZE);
+    let end = early.end;
+    let dma = early.alloc_dma_pages(3, 4 * PAGE_SIZE).unwrap();
+    assert_eq!(dma % (4 * PAGE_SIZE), 0);
+    assert!(dma >= early.start && dma + 3 * PAGE_SIZE <= end);
+    assert_eq!(early.used_dma_pages(), 3);
+
+    // ordinary pages below the DMA block are still freed normally
+    let page = early.alloc_pages(1, PAGE_SIZE).unwrap();
+    assert_eq!(page + PAGE_SIZE, dma);
+    early.dealloc_pages(page, 1);
+    assert_eq!(early.p_pos, dma);
+}
+
+#[test]
+#[should_panic(expected = "cannot deallocate DMA pages")]
+fn test_dma_pages_never_freed() {
+    let mut early = new_allocator(16 * PAGE_SIZE);
+    let dma = early.alloc_dma_pages(1, PAGE_SIZE).unwrap();
+    early.dealloc_pages(dma, 1);
+}
+
+#[test]
+fn test_bytes_fully_freed() {
+    let mut early = new_allocator(16 * PAGE_SIZE);
+    assert!(early.bytes_fully_freed());
+
+    let blocks: Vec<_> = (1..=64)
+        .map(|i| {
+            let layout = layout(8 * i, 8);
+            (early.alloc(layout).unwrap(), layout)
+        })
+        .collect();
+    early.alloc_pages(1, PAGE_SIZE).unwrap();
+    let available = early.available_pages();
+    assert!(!early.bytes_fully_freed());
+
+    // a few blocks out of order, then the rest from the top down
+    let (holes, rest): (Vec<_>, Vec<_>) = blocks.iter().enumerate().partition(|(i, _)| i % 8 == 0);
+    for (_, (ptr, layout)) in holes.into_iter().chain(rest.into_iter().rev()) {
+        early.dealloc(*ptr, *layout);
+    }
+    assert!(early.bytes_fully_freed());
+    assert_eq!(early.used_bytes(), 0);
+    assert!(early.available_pages() > available);
+    assert_eq!(early.available_pages(), 15);
+}
+
+#[test]
+fn test_rebase() {
+    let size = 16 * PAGE_SIZE;
+    let mut early = new_allocator(size);
+    let a = early.alloc(layout(1, 1)).unwrap();
+    early.alloc(layout(8, 64)).unwrap();
+    let dma = early.alloc_dma_pages(1, PAGE_SIZE).unwrap();
+    let page = early.alloc_pages(2, PAGE_SIZE).unwrap();
+    let old = (early.start, early.end, early.b_pos, early.p_pos);
+    let used = early.used_bytes();
+    let available = (early.available_bytes(), early.available_pages());
+
+    // pretend the arena was copied to a new place
+    let new_start = arena(size);
+    let delta = new_start.wrapping_sub(old.0);
+    unsafe { early.rebase(new_start) };
+
+    let new = (early.start, early.end, early.b_pos, early.p_pos);
+    assert_eq!(
+        new,
+        (
+            old.0.wrapping_add(delta),
+            old.1.wrapping_add(delta),
+            old.2.wrapping_add(delta),
+            old.3.wrapping_add(delta),
+        )
+    );
+    assert_eq!(early.used_bytes(), used);
+    assert_eq!(
+        (early.available_bytes(), early.available_pages()),
+        available
+    );
+
+    // the padding gap after `a` moved along
+    let c = early.alloc(layout(8, 8)).unwrap().as_ptr() as usize;
+    assert_eq!(c, (a.as_ptr() as usize).wrapping_add(delta) + 8);
+    // so did the DMA floor
+    assert_eq!(early.dma_floor, dma.wrapping_add(delta));
+    early.dealloc_pages(page.wrapping_add(delta), 2);
+    assert_eq!(early.p_pos, early.dma_floor);
+}
+
+#[test]
+fn test_directions() {
+    let size = 16 * PAGE_SIZE;
+
+    let mut forward: EarlyAllocator<PAGE_SIZE, Forward> = EarlyAllocator::new();
+    let start = arena(size);
+    forward.init(start, size);
+    let bytes = forward.alloc(layout(16, 8)).unwrap().as_ptr() as usize;
+    let page = forward.alloc_pages(1, PAGE_SIZE).unwrap();
+    assert_eq!(bytes, start);
+    assert_eq!(page, start + size - PAGE_SIZE);
+
+    let mut reversed: EarlyAllocator<PAGE_SIZE, Reversed> = EarlyAllocator::new();
+    let start = arena(size);
+    reversed.init(start, size);
+    let a = reversed.alloc(layout(16, 8)).unwrap();
+    let b = reversed.alloc(layout(8, 64)).unwrap();
+    let p = reversed.alloc_pages(1, PAGE_SIZE).unwrap();
+    let q = reversed.alloc_pages(2, PAGE_SIZE).unwrap();
+    assert_eq!(a.as_ptr() as usize, start + size - 16);
+    assert_eq!(b.as_ptr() as usize, start + size - 64);
+    assert_eq!((p, q), (start, start + PAGE_SIZE));
+    assert_eq!(reversed.used_bytes(), 64);
+    assert_eq!(reversed.used_pages(), 3);
+    assert_eq!(reversed.available_bytes(), size - 64 - 3 * PAGE_SIZE);
+
+    // the padding between `b` and `a` is reused, and all frees merge back
+    let c = reversed.alloc(layout(8, 8)).unwrap();
+    assert_eq!(c.as_ptr() as usize, start + size - 56);
+    reversed.dealloc(c, layout(8, 8));
+    reversed.dealloc(b, layout(8, 64));
+    reversed.dealloc(a, layout(16, 8));
+    assert!(reversed.bytes_fully_freed());
+    reversed.dealloc_pages(q, 2);
+    reversed.dealloc_pages(p, 1);
+    reversed.assert_no_leaks();
+}
+
+#[test]
+fn test_used_pages_fresh() {
+    let early = new_allocator(4 * PAGE_SIZE);
+    assert_eq!(early.used_pages(), 0);
+    early.assert_no_leaks();
+}
+
+#[test]
+fn test_used_pages() {
+    let mut early = new_allocator(8 * PAGE_SIZE);
+    let pages: Vec<_> = (0..3)
+        .map(|_| early.alloc_pages(1, PAGE_SIZE).unwrap())
+        .collect();
+    assert_eq!(early.used_pages(), 3);
+    assert_eq!(early.available_pages(), 5);
+    for page in pages.into_iter().rev() {
+        early.dealloc_pages(page, 1);
+    }
+    assert_eq!(early.used_pages(), 0);
+}
+
+#[test]
+fn test_page_size_param() {
+    let size = 16 * PAGE_SIZE;
+    let mut small = EarlyAllocator::<0x1000>::new();
+    small.init(arena(size), size);
+    let mut large = EarlyAllocator::<0x2000>::new();
+    large.init(
+        unsafe { std::alloc::alloc(layout(size, 0x2000)) as usize },
+        size,
+    );
+    assert_eq!(<EarlyAllocator<0x2000> as PageAllocator>::PAGE_SIZE, 0x2000);
+
+    assert_eq!((small.total_pages(), large.total_pages()), (16, 8));
+    small.alloc_pages(2, 0x1000).unwrap();
+    large.alloc_pages(2, 0x2000).unwrap();
+    assert_eq!((small.used_pages(), large.used_pages()), (2, 2));
+    assert_eq!((small.available_pages(), large.available_pages()), (14, 6));
+    assert_eq!(large.alloc_pages(1, 0x1000), Err(AllocError::InvalidParam));
+}
+
+#[test]
+fn test_add_memory() {
+    let size = 8 * PAGE_SIZE;
+    let start = arena(2 * size);
+    let mut early = EarlyAllocator::<PAGE_SIZE>::new();
+    early.init(start, size);
+    early.alloc(layout(64, 8)).unwrap();
+    assert_eq!(early.alloc_pages(12, PAGE_SIZE), Err(AllocError::NoMemory));
+
+    assert_eq!(
+        early.add_memory(start + size + PAGE_SIZE, size - PAGE_SIZE),
+        Err(AllocError::InvalidParam)
+    );
+    early.add_memory(start + size, size).unwrap();
+    assert_eq!(early.total_bytes(), 2 * size);
+    assert_eq!(early.alloc_pages(12, PAGE_SIZE), Ok(start + 4 * PAGE_SIZE));
+    assert_eq!(early.used_pages(), 12);
+    assert_eq!(early.used_bytes(), 64);
+
+    // the pages area is in use now, so it cannot grow any more
+    assert_eq!(
+        early.add_memory(start + 2 * size, PAGE_SIZE),
+        Err(AllocError::InvalidParam)
+    );
+}
+
+#[test]
+fn test_add_memory_below_bytes() {
+    let size = 4 * PAGE_SIZE;
+    let low = arena(2 * size);
+    let mut early = EarlyAllocator::<PAGE_SIZE>::new();
+    early.init(low + size, size);
+    let ptr = early.alloc(layout(64, 8)).unwrap();
+
+    // below bytes in use, the region waits in a gap
+    early.add_memory(low, size).unwrap();
+    assert_eq!(early.start, low);
+    let small = early.alloc(layout(32, 8)).unwrap();
+    assert_eq!(small.as_ptr() as usize, low);
+    early.dealloc(small, layout(32, 8));
+    early.dealloc(ptr, layout(64, 8));
+    assert!(early.bytes_fully_freed());
+    assert_eq!(early.available_bytes(), 2 * size);
+}
+
+#[test]
+fn test_reset() {
+    let mut early = new_allocator(8 * PAGE_SIZE);
+    let first = early.alloc(layout(8, 1)).unwrap();
+    early.alloc(layout(100, 64)).unwrap();
+    early.alloc_pages(2, PAGE_SIZE).unwrap();
+    early.alloc_dma_pages(1, PAGE_SIZE).unwrap();
+
+    early.reset();
+    assert_eq!(early.used_bytes(), 0);
+    assert_eq!(early.used_pages(), 0);
+    assert_eq!(early.used_dma_pages(), 0);
+    assert_eq!(early.available_pages(), 8);
+    early.assert_no_leaks();
+    // allocation starts over from the beginning
+    assert_eq!(early.alloc(layout(8, 1)), Ok(first));
+
+    // nothing to reset before init
+    let mut early = EarlyAllocator::<PAGE_SIZE>::new();
+    early.reset();
+    assert_eq!(early.total_bytes(), 0);
+    assert_eq!(early.alloc(layout(8, 1)), Err(AllocError::NoMemory));
+}
+
+#[test]
+fn test_try_dealloc() {
+    let mut early = new_allocator(16 * PAGE_SIZE);
+    let a = early.alloc(layout(64, 8)).unwrap();
+    let b = early.alloc(layout(64, 8)).unwrap();
+
+    // a foreign pointer
+    let mut local = 0u8;
+    let foreign = NonNull::from(&mut local);
+    assert_eq!(
+        early.try_dealloc(foreign, layout(1, 1)),
+        Err(AllocError::InvalidParam)
+    );
+
+    // freeing past the last allocation, here a double free
+    early.try_dealloc(b, layout(64, 8)).unwrap();
+    let used = early.used_bytes();
+    assert_eq!(
+        early.try_dealloc(b, layout(64, 8)),
+        Err(AllocError::InvalidParam)
+    );
+    assert_eq!(early.used_bytes(), used);
+    early.try_dealloc(a, layout(64, 8)).unwrap();
+    assert_eq!(early.used_bytes(), 0);
+
+    // pages out of LIFO order, and DMA pages
+    let dma = early.alloc_dma_pages(1, PAGE_SIZE).unwrap();
+    let p = early.alloc_pages(1, PAGE_SIZE).unwrap();
+    let q = early.alloc_pages(1, PAGE_SIZE).unwrap();
+    assert_eq!(early.try_dealloc_pages(p, 1), Err(AllocError::InvalidParam));
+    assert_eq!(
+        early.try_dealloc_pages(dma, 1),
+        Err(AllocError::InvalidParam)
+    );
+    assert_eq!(early.used_pages(), 3);
+    early.try_dealloc_pages(q, 1).unwrap();
+    early.try_dealloc_pages(p, 1).unwrap();
+    assert_eq!(early.used_pages(), 1);
+}
+
+#[test]
+#[should_panic(expected = "empty region")]
+fn test_init_empty() {
+    let mut early = EarlyAllocator::<PAGE_SIZE>::new();
+    early.init(arena(PAGE_SIZE), 0);
+}
+
+#[test]
+#[should_panic(expected = "overflows the address space")]
+fn test_init_overflow() {
+    let mut early = EarlyAllocator::<PAGE_SIZE>::new();
+    early.init(usize::MAX - PAGE_SIZE + 1, 2 * PAGE_SIZE);
+}
+
+#[test]
+fn test_free_in_any_order() {
+    for reversed in [false, true] {
+        let size = 16 * PAGE_SIZE;
+        let (mut forward, mut backward) = (
+            new_allocator(size),
+            EarlyAllocator::<PAGE_SIZE, Reversed>::new(),
+        );
+        backward.init(arena(size), size);
+        let early: &mut dyn ByteAllocator = if reversed {
+            &mut backward
+        } else {
+            &mut forward
+        };
+
+        // interleaved: the first block is freed first, far from `b_pos`
+        let a = early.alloc(layout(64, 8)).unwrap();
+        let b = early.alloc(layout(128, 8)).unwrap();
+        early.dealloc(a, layout(64, 8));
+        let c = early.alloc(layout(256, 8)).unwrap();
+        early.dealloc(c, layout(256, 8));
+        assert!(early.used_bytes() > 0);
+        early.dealloc(b, layout(128, 8));
+        assert_eq!(early.used_bytes(), 0);
+
+        // more blocks than the gap list holds, all freed out of order
+        let blocks: Vec<_> = (0..2 * MAX_GAPS + 1)
+            .map(|_| early.alloc(layout(32, 8)).unwrap())
+            .collect();
+        for &ptr in blocks
+            .iter()
+            .step_by(2)
+            .chain(blocks.iter().skip(1).step_by(2))
+        {
+            early.dealloc(ptr, layout(32, 8));
+        }
+        assert_eq!(early.used_bytes(), 0);
+        assert_eq!(early.available_bytes(), size);
+    }
+}
+
+#[test]
+fn test_split_off_free_not_reclaimed() {
+    let mut early = new_allocator(16 * PAGE_SIZE);
+    let a = early.alloc(layout(64, 8)).unwrap();
+    let (free_start, free_end) = early.split_off_free();
+    early.dealloc(a, layout(64, 8));
+    assert_eq!(early.available_bytes(), 0);
+    assert!(early.alloc(layout(PAGE_SIZE, 8)).is_err());
+    assert!(free_start < free_end);
+}
+
+#[test]
+fn test_alloc_overflow() {
+    let mut early = new_allocator(16 * PAGE_SIZE);
+    let huge = layout(isize::MAX as usize, 1);
+    assert_eq!(early.alloc(huge), Err(AllocError::NoMemory));
+    // large enough to wrap once aligned
+    let align = 1 << (usize::BITS - 2);
+    assert_eq!(early.alloc(layout(align, align)), Err(AllocError::NoMemory));
+
+    // a padding gap, then a huge request that would wrap inside it
+    early.alloc(layout(8, 8)).unwrap();
+    early.alloc(layout(64, 64)).unwrap();
+    assert_eq!(early.alloc(huge), Err(AllocError::NoMemory));
+    assert_eq!(early.used_bytes(), 128);
+
+    assert_eq!(
+        early.apply(EarlyOp::AllocBytes { size: 8, align: 3 }),
+        EarlyOpResult::Failed(AllocError::InvalidParam)
+    );
+    let mut reversed = EarlyAllocator::<PAGE_SIZE, Reversed>::new();
+    reversed.init(arena(16 * PAGE_SIZE), 16 * PAGE_SIZE);
+    assert_eq!(reversed.alloc(huge), Err(AllocError::NoMemory));
+}
+
+#[test]
+fn test_stats() {
+    let mut early = new_allocator(16 * PAGE_SIZE);
+    let start = early.start;
+    early.alloc(layout(0x40, 8)).unwrap();
+    early.alloc_pages(2, PAGE_SIZE).unwrap();
+    let stats = early.stats();
+    assert_eq!(stats.start, start);
+    assert_eq!(stats.end, start + 16 * PAGE_SIZE);
+    assert_eq!(stats.b_pos, start + 0x40);
+    assert_eq!(stats.p_pos, start + 14 * PAGE_SIZE);
+    assert_eq!(stats.bytes_used, 0x40);
+    assert_eq!(stats.pages_used, 2);
+    assert_eq!(stats.available, 14 * PAGE_SIZE - 0x40);
+    assert_eq!(
+        std::format!("{}", stats),
+        std::format!(
+            "[ bytes-used 0x40 | avail 0xdfc0 | pages-used 2 ] \
+             start {:#x} b_pos {:#x} p_pos {:#x} end {:#x}",
+            start,
+            start + 0x40,
+            start + 14 * PAGE_SIZE,
+            start + 16 * PAGE_SIZE
+        )
+    );
+
+    let mut reversed = EarlyAllocator::<PAGE_SIZE, Reversed>::new();
+    reversed.init(start, 16 * PAGE_SIZE);
+    reversed.alloc(layout(0x40, 8)).unwrap();
+    let text = std::format!("{}", reversed.stats());
+    assert!(text.starts_with("[ pages-used 0 | avail 0xffc0 | bytes-used 0x40 ]"));
+}
+
+#[test]
+fn test_alloc_zeroed() {
+    let mut early = new_allocator(4 * PAGE_SIZE);
+    // leave garbage behind in the arena first
+    let dirty = early.alloc(layout(128, 8)).unwrap();
+    unsafe { dirty.as_ptr().write_bytes(0xa5, 128) };
+    early.dealloc(dirty, layout(128, 8));
+
+    let ptr = early.alloc_zeroed(layout(128, 8)).unwrap();
+    assert_eq!(ptr, dirty);
+    let bytes = unsafe { core::slice::from_raw_parts(ptr.as_ptr(), 128) };
+    assert!(bytes.iter().all(|&b| b == 0));
+}
+
+#[test]
+fn test_available_pages_mid_page() {
+    /// Allocates single pages until failure, with `b_pos` left mid-page.
+    fn check<A: ByteAllocator + PageAllocator>(early: &mut A) {
+        early.alloc(layout(PAGE_SIZE + 8, 8)).unwrap();
+        let available = early.available_pages();
+        assert_eq!(available, 6);
+        let mut allocated = 0;
+        while early.alloc_pages(1, PAGE_SIZE).is_ok() {
+            allocated += 1;
+        }
+        assert_eq!(allocated, available);
+        assert_eq!(early.available_pages(), 0);
+    }
+
+    // neither end of the arena is page aligned either
+    let (start, size) = (arena(9 * PAGE_SIZE) + 8, 9 * PAGE_SIZE - 16);
+    let mut forward = EarlyAllocator::<PAGE_SIZE>::new();
+    forward.init(start, size);
+    check(&mut forward);
+    let mut reversed = EarlyAllocator::<PAGE_SIZE, Reversed>::new();
+    reversed.init(start, size);
+    check(&mut reversed);
+}
+
+#[test]
+fn test_locked() {
+    let locked = std::sync::Arc::new(LockedEarlyAllocator::<PAGE_SIZE>::new());
+    locked.init(arena(16 * PAGE_SIZE), 16 * PAGE_SIZE);
+    let threads: Vec<_> = (0..4)
+        .map(|_| {
+            let locked = locked.clone();
+            std::thread::spawn(move || {
+                for _ in 0..16 {
+                    locked.alloc(layout(64, 8)).unwrap();
+                }
+                locked.alloc_pages(1, PAGE_SIZE).unwrap()
+            })
+        })
+        .collect();
+    let mut pages: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+    pages.sort_unstable();
+    pages.dedup();
+    assert_eq!(pages.len(), 4);
+
+    let early = locked.lock();
+    assert_eq!(early.used_bytes(), 4 * 16 * 64);
+    assert_eq!(early.used_pages(), 4);
+}
+
+#[test]
+fn test_snapshot_diff() {
+    let mut early = new_allocator(16 * PAGE_SIZE);
+    early.alloc(layout(16, 8)).unwrap();
+    let before = early.snapshot();
+
+    // balanced
+    let pages = early.alloc_pages(3, PAGE_SIZE).unwrap();
+    let ptr = early.alloc(layout(100, 4)).unwrap();
+    early.dealloc(ptr, layout(100, 4));
+    early.dealloc_pages(pages, 3);
+    let diff = early.diff(&before);
+    assert_eq!((diff.pages_allocated, diff.pages_freed), (3, 3));
+    assert_eq!((diff.bytes_allocated, diff.bytes_freed), (100, 100));
+    assert_eq!((diff.net_bytes(), diff.net_pages()), (0, 0));
+
+    // unbalanced
+    let mid = early.snapshot();
+    early.alloc(layout(24, 8)).unwrap();
+    early.alloc_pages(2, PAGE_SIZE).unwrap();
+    assert_eq!(early.diff(&before).net_bytes(), 24);
+    assert_eq!(early.diff(&before).net_pages(), 2);
+    let diff = early.diff(&mid);
+    assert_eq!((diff.bytes_allocated, diff.pages_allocated), (24, 2));
+    assert_eq!((diff.bytes_freed, diff.pages_freed), (0, 0));
+}
+
+#[test]
+fn test_available_pages_aligned() {
+    /// Compares the count with single aligned page allocations until failure.
+    fn check<A: ByteAllocator + PageAllocator>(mut early: A, available: usize, align: usize) {
+        let mut allocated = 0;
+        while early.alloc_pages(1, align).is_ok() {
+            allocated += 1;
+        }
+        assert_eq!(allocated, available, "align {:#x}", align);
+    }
+
+    let (start, size) = (arena(40 * PAGE_SIZE) + 8, 37 * PAGE_SIZE);
+    for align in [
+        PAGE_SIZE,
+        2 * PAGE_SIZE,
+        4 * PAGE_SIZE,
+        16 * PAGE_SIZE,
+        64 * PAGE_SIZE,
+    ] {
+        let mut forward = EarlyAllocator::<PAGE_SIZE>::new();
+        forward.init(start, size);
+        forward.alloc(layout(3 * PAGE_SIZE + 8, 8)).unwrap();
+        let available = forward.available_pages_aligned(align);
+        check(forward, available, align);
+
+        let mut reversed = EarlyAllocator::<PAGE_SIZE, Reversed>::new();
+        reversed.init(start, size);
+        reversed.alloc(layout(3 * PAGE_SIZE + 8, 8)).unwrap();
+        let available = reversed.available_pages_aligned(align);
+        check(reversed, available, align);
+    }
+
+    let mut early = EarlyAllocator::<PAGE_SIZE>::new();
+    early.init(start, size);
+    assert!(early.available_pages_aligned(4 * PAGE_SIZE) < early.available_pages());
+    assert_eq!(early.available_pages_aligned(3 * PAGE_SIZE), 0);
+    early.set_max_align_waste(Some(PAGE_SIZE));
+    let available = early.available_pages_aligned(4 * PAGE_SIZE);
+    assert!(available <= 1);
+    check(early, available, 4 * PAGE_SIZE);
+}
+
+#[test]
+fn test_scope() {
+    fn check<D: Direction>(mut early: EarlyAllocator<PAGE_SIZE, D>) {
+        let kept = early.alloc(layout(24, 8)).unwrap();
+        let used = early.used_bytes();
+        let before = early.snapshot();
+        {
+            let mut scope = early.scope();
+            let a = scope.alloc(layout(100, 4)).unwrap();
+            scope.alloc_zeroed(layout(16, 64)).unwrap();
+            scope.alloc(layout(8, 8)).unwrap();
+            scope.dealloc(a, layout(100, 4));
+            assert!(scope.used_bytes() > used);
+        }
+        assert_eq!(early.used_bytes(), used);
+        assert_eq!(early.diff(&before).net_bytes(), 0);
+
+        // the state before the scope is intact
+        early.dealloc(kept, layout(24, 8));
+        assert!(early.bytes_fully_freed());
+        early.assert_no_leaks();
+    }
+    check(new_allocator(4 * PAGE_SIZE));
+    let size = 4 * PAGE_SIZE;
+    let mut early = EarlyAllocator::<PAGE_SIZE, Reversed>::new();
+    early.init(arena(size), size);
+    check(early);
+}
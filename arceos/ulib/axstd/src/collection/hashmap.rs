@@ -2,49 +2,358 @@ extern crate alloc;
 use alloc::{vec::Vec, vec};
 use arceos_api::misc::ax_random;
 
+/// Number of buckets of a newly created map
+const INITIAL_BUCKETS: usize = 64;
+/// Number of old buckets moved to the new table on each insert while rehashing
+const MIGRATE_STEP: usize = 8;
+
+type Bucket<K, V> = Option<Vec<(K, V)>>;
+
+/// Occupancy statistics of a [`HashMap`], see [`HashMap::stats`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HashMapStats {
+    /// Number of entries
+    pub len: usize,
+    /// Number of buckets
+    pub capacity: usize,
+    /// Entries per bucket, `0.0` before the first insert
+    pub load_factor: f32,
+    /// Number of entries in the longest bucket chain
+    pub max_bucket_len: usize,
+    /// Number of buckets holding no entry
+    pub empty_buckets: usize,
+}
+
 /// Simple HashMap
+///
+/// Growing is incremental: the old buckets are kept next to the new ones and
+/// moved over a few at a time by the following inserts, so that no single
+/// insert pays for rehashing the whole table.
 pub struct HashMap<K, V> {
-    buckets: Vec<Option<Vec<(K, V)>>>,
+    buckets: Vec<Bucket<K, V>>,
+    /// Buckets of the table being migrated from, empty when not rehashing
+    old_buckets: Vec<Bucket<K, V>>,
+    /// Next old bucket to migrate
+    migrate_pos: usize,
     size: usize,
     secret: u128,
+    max_capacity: Option<usize>,
+    auto_shrink: bool,
 }
 
 impl<K: core::hash::Hash + Eq + core::clone::Clone, V: core::clone::Clone> HashMap<K, V> {
     /// Create a new empty HashMap
     pub fn new() -> Self {
-        const INITIAL_BUCKETS: usize = 64;
-
         Self {
             buckets: vec![None; INITIAL_BUCKETS],
+            old_buckets: Vec::new(),
+            migrate_pos: 0,
             size: 0,
             secret: ax_random(),
+            max_capacity: None,
+            auto_shrink: false,
+        }
+    }
+
+    /// Create an empty HashMap usable in `static` items, with a fixed hash
+    /// `secret` instead of a random one
+    ///
+    /// No buckets are allocated until the first insert, so this needs neither
+    /// the allocator nor the random source at compile time. A fixed secret
+    /// makes collisions predictable, so only use it for trusted keys such as
+    /// static configuration tables.
+    pub const fn new_const(secret: u128) -> Self {
+        Self {
+            buckets: Vec::new(),
+            old_buckets: Vec::new(),
+            migrate_pos: 0,
+            size: 0,
+            secret,
+            max_capacity: None,
+            auto_shrink: false,
         }
     }
 
+    /// Returns the number of entries
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    /// Overrides the entry count, to exercise the overflow guards without
+    /// inserting `usize::MAX` entries. Only available with the `test-hooks`
+    /// feature.
+    #[cfg(feature = "test-hooks")]
+    #[doc(hidden)]
+    pub fn set_len_for_test(&mut self, len: usize) {
+        self.size = len;
+    }
+
+    /// Returns true if the map holds no entry
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Returns the number of buckets
+    pub fn capacity(&self) -> usize {
+        self.buckets.len()
+    }
+
+    /// Collects occupancy statistics in a single pass over the buckets
+    ///
+    /// While rehashing, `capacity` and `empty_buckets` describe the new table
+    /// only, and `max_bucket_len` covers the old one too.
+    pub fn stats(&self) -> HashMapStats {
+        let bucket_len = |bucket: &Bucket<K, V>| bucket.as_ref().map_or(0, |b| b.len());
+        let mut max_bucket_len = 0;
+        let mut empty_buckets = 0;
+        for bucket in &self.buckets {
+            let len = bucket_len(bucket);
+            max_bucket_len = max_bucket_len.max(len);
+            if len == 0 {
+                empty_buckets += 1;
+            }
+        }
+        for bucket in &self.old_buckets {
+            max_bucket_len = max_bucket_len.max(bucket_len(bucket));
+        }
+        let capacity = self.capacity();
+        HashMapStats {
+            len: self.size,
+            capacity,
+            load_factor: if capacity == 0 {
+                0.0
+            } else {
+                self.size as f32 / capacity as f32
+            },
+            max_bucket_len,
+            empty_buckets,
+        }
+    }
+
+    /// Returns true if entries are still being moved to a grown table
+    pub fn is_rehashing(&self) -> bool {
+        !self.old_buckets.is_empty()
+    }
+
+    /// Limits the number of buckets the map may grow to, or lifts the limit
+    /// with `None`.
+    ///
+    /// Once the limit is reached, inserts keep working but chain into the
+    /// existing buckets, so an attacker feeding keys cannot exhaust memory
+    /// through the bucket array. The map never shrinks to honor the limit.
+    pub fn set_max_capacity(&mut self, max_capacity: Option<usize>) {
+        self.max_capacity = max_capacity;
+    }
+
+    /// Enables or disables halving the buckets on `remove` once the load
+    /// factor drops below 1/4, never going under the initial 64 buckets.
+    ///
+    /// Off by default, so that removing never reallocates unless asked for.
+    pub fn set_auto_shrink(&mut self, auto_shrink: bool) {
+        self.auto_shrink = auto_shrink;
+    }
+
     /// Insert a key-value pair
+    ///
+    /// # Panics
+    ///
+    /// Panics if `k` is new and the map already holds `usize::MAX` entries,
+    /// see [`try_insert`](Self::try_insert).
     pub fn insert(&mut self, k: K, v: V) {
-        let idx = self.hash(&k) % self.buckets.len();
+        if self.try_insert(k, v).is_err() {
+            panic!("HashMap: too many entries");
+        }
+    }
+
+    /// Insert a key-value pair, or give it back if `k` is new and the map
+    /// already holds `usize::MAX` entries, which could not be counted
+    pub fn try_insert(&mut self, k: K, v: V) -> Result<(), (K, V)> {
+        let idx = self.prepare_insert(&k);
+        let new_size = self.size.checked_add(1);
 
         match &mut self.buckets[idx] {
             Some(bucket) => {
                 for &mut (ref existing_key, ref mut existing_value) in bucket.iter_mut() {
                     if existing_key == &k {
                         *existing_value = v;
-                        return;
+                        return Ok(());
                     }
                 }
+                if new_size.is_none() {
+                    return Err((k, v));
+                }
                 bucket.push((k, v));
             }
+            None if new_size.is_none() => return Err((k, v)),
             None => {
                 self.buckets[idx] = Some(vec![(k, v)]);
             }
         }
 
-        self.size += 1;
+        self.size = new_size.unwrap();
+        self.grow_if_needed();
+        Ok(())
+    }
+
+    /// Inserts `v` if `k` is absent, otherwise folds it into the existing
+    /// value with `merge(&mut existing, v)`
+    pub fn insert_or_merge<F: FnOnce(&mut V, V)>(&mut self, k: K, v: V, merge: F) {
+        let idx = self.prepare_insert(&k);
+        let bucket = self.buckets[idx].get_or_insert_with(Vec::new);
+        if let Some((_, existing)) = bucket.iter_mut().find(|(key, _)| *key == k) {
+            merge(existing, v);
+            return;
+        }
+        let new_size = self.size.checked_add(1).expect("HashMap: too many entries");
+        bucket.push((k, v));
+
+        self.size = new_size;
+        self.grow_if_needed();
+    }
+
+    /// Looks up several keys at once, returning one result per key in the
+    /// same order
+    pub fn get_many<'a>(&'a self, keys: &[&K]) -> Vec<Option<&'a V>> {
+        keys.iter().map(|k| self.find(k)).collect()
+    }
+
+    /// Gets the entry of a borrowed key, for in-place lookup or insertion
+    ///
+    /// Unlike taking the key by value, the owned key is only built, with
+    /// `K::from(k)`, when a vacant entry is filled, so hits never clone it.
+    pub fn entry_ref<'q, Q>(&mut self, k: &'q Q) -> EntryRef<'_, 'q, K, V, Q>
+    where
+        K: core::borrow::Borrow<Q> + From<&'q Q>,
+        Q: core::hash::Hash + Eq + ?Sized,
+    {
+        let idx = self.prepare_insert(k);
+        let pos = self.buckets[idx]
+            .as_ref()
+            .and_then(|bucket| bucket.iter().position(|(key, _)| key.borrow() == k));
+        match pos {
+            Some(pos) => EntryRef::Occupied(OccupiedEntryRef {
+                value: &mut self.buckets[idx].as_mut().unwrap()[pos].1,
+            }),
+            None => EntryRef::Vacant(VacantEntryRef { map: self, key: k }),
+        }
+    }
+
+    /// Returns the value of `k`, looking in the old table too while rehashing
+    fn find(&self, k: &K) -> Option<&V> {
+        [&self.buckets, &self.old_buckets]
+            .into_iter()
+            .filter(|buckets| !buckets.is_empty())
+            .find_map(|buckets| {
+                let bucket = buckets[self.hash(k) % buckets.len()].as_ref()?;
+                bucket.iter().find(|(key, _)| key == k).map(|(_, v)| v)
+            })
+    }
+
+    /// Removes a key, returning its value if it was present
+    ///
+    /// Entries sharing the bucket keep their relative order, so iteration
+    /// order of the remaining entries is unchanged.
+    pub fn remove(&mut self, k: &K) -> Option<V> {
+        if self.buckets.is_empty() {
+            return None;
+        }
+        if self.is_rehashing() {
+            let old_idx = self.hash(k) % self.old_buckets.len();
+            self.migrate_bucket(old_idx);
+            // keep going without inserts, so that a shrink frees the old table
+            self.migrate_step();
+        }
+        let idx = self.hash(k) % self.buckets.len();
+        let bucket = self.buckets[idx].as_mut()?;
+        let pos = bucket.iter().position(|(key, _)| key == k)?;
+        let (_, v) = bucket.remove(pos);
+        if bucket.is_empty() {
+            self.buckets[idx] = None;
+        }
+        self.size -= 1;
+        self.shrink_if_needed();
+        Some(v)
+    }
+
+    /// Allocates the buckets if needed and makes sure `k` is no longer in the
+    /// old table, returns the bucket index of `k`
+    fn prepare_insert<Q: core::hash::Hash + ?Sized>(&mut self, k: &Q) -> usize {
+        if self.buckets.is_empty() {
+            self.buckets = vec![None; INITIAL_BUCKETS];
+        }
+        if self.is_rehashing() {
+            // the key may still live in the old table
+            let old_idx = self.hash(k) % self.old_buckets.len();
+            self.migrate_bucket(old_idx);
+            self.migrate_step();
+        }
+        self.hash(k) % self.buckets.len()
+    }
+
+    /// Doubles the buckets once the load factor exceeds 3/4, unless that
+    /// would exceed `max_capacity`
+    fn grow_if_needed(&mut self) {
+        if self.size.saturating_mul(4) <= self.buckets.len().saturating_mul(3) {
+            return;
+        }
+        let new_len = self.buckets.len() * 2;
+        if self.max_capacity.is_some_and(|max| new_len > max) {
+            return;
+        }
+        self.finish_rehash();
+        self.old_buckets = core::mem::replace(&mut self.buckets, vec![None; new_len]);
+        self.migrate_pos = 0;
+    }
+
+    /// Halves the buckets once the load factor drops below 1/4, if
+    /// `auto_shrink` is set
+    ///
+    /// The entries are then migrated incrementally just like after a growth.
+    fn shrink_if_needed(&mut self) {
+        let new_len = self.buckets.len() / 2;
+        if !self.auto_shrink
+            || new_len < INITIAL_BUCKETS
+            || self.size.saturating_mul(4) >= self.buckets.len()
+        {
+            return;
+        }
+        self.finish_rehash();
+        self.old_buckets = core::mem::replace(&mut self.buckets, vec![None; new_len]);
+        self.migrate_pos = 0;
+    }
+
+    /// Moves the entries of an old bucket to the new table
+    fn migrate_bucket(&mut self, old_idx: usize) {
+        if let Some(bucket) = self.old_buckets[old_idx].take() {
+            for (k, v) in bucket {
+                let idx = self.hash(&k) % self.buckets.len();
+                self.buckets[idx].get_or_insert_with(Vec::new).push((k, v));
+            }
+        }
+    }
+
+    /// Migrates the next `MIGRATE_STEP` old buckets
+    fn migrate_step(&mut self) {
+        let end = (self.migrate_pos + MIGRATE_STEP).min(self.old_buckets.len());
+        while self.migrate_pos < end {
+            self.migrate_bucket(self.migrate_pos);
+            self.migrate_pos += 1;
+        }
+        if self.migrate_pos == self.old_buckets.len() {
+            self.old_buckets = Vec::new();
+            self.migrate_pos = 0;
+        }
+    }
+
+    /// Migrates all the remaining old buckets
+    fn finish_rehash(&mut self) {
+        while self.is_rehashing() {
+            self.migrate_step();
+        }
     }
 
     /// Simple hash function
-    fn hash(&self, k: &K) -> usize {
+    fn hash<Q: core::hash::Hash + ?Sized>(&self, k: &Q) -> usize {
         use core::hash::{Hash, Hasher};
 
         let mut hasher = SimpleHasher(self.secret);
@@ -54,10 +363,143 @@ impl<K: core::hash::Hash + Eq + core::clone::Clone, V: core::clone::Clone> HashM
     /// iter()
     pub fn iter(&self) -> Iter<'_, K, V> {
         Iter {
-            buckets: self.buckets.iter(),
+            buckets: self.buckets.iter().chain(self.old_buckets.iter()),
             current_bucket: None,
         }
     }
+
+    /// Iterates over the entries in ascending key order
+    ///
+    /// Unlike [`iter`](Self::iter), the order does not depend on the hash
+    /// secret, which makes output reproducible. Sorts a list of all the
+    /// entries on each call.
+    pub fn sorted_iter(&self) -> impl Iterator<Item = (&K, &V)>
+    where
+        K: Ord,
+    {
+        let mut entries: Vec<_> = self.iter().collect();
+        entries.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        entries.into_iter()
+    }
+
+    /// Returns true if the map holds exactly the given pairs, whatever their
+    /// order
+    ///
+    /// Handy to compare against an expected list in tests, as the order of
+    /// [`iter`](Self::iter) depends on the hash secret. A key given twice
+    /// never matches, as the map holds each key once.
+    pub fn eq_pairs<I: IntoIterator<Item = (K, V)>>(&self, pairs: I) -> bool
+    where
+        K: Ord,
+        V: PartialEq,
+    {
+        let mut expected: Vec<_> = pairs.into_iter().collect();
+        if expected.len() != self.size {
+            return false;
+        }
+        expected.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        self.sorted_iter()
+            .zip(&expected)
+            .all(|((k, v), (ek, ev))| k == ek && v == ev)
+    }
+
+    /// Folds every value into an accumulator with `f`, starting from `init`
+    pub fn fold_values<B, F: FnMut(B, &V) -> B>(&self, init: B, mut f: F) -> B {
+        self.iter().fold(init, |acc, (_, v)| f(acc, v))
+    }
+
+    /// Sums all the values
+    pub fn sum_values<'a, S: core::iter::Sum<&'a V>>(&'a self) -> S {
+        self.iter().map(|(_, v)| v).sum()
+    }
+}
+
+/// An entry of a [`HashMap`] looked up by a borrowed key, see
+/// [`HashMap::entry_ref`]
+pub enum EntryRef<'a, 'q, K, V, Q: ?Sized> {
+    Occupied(OccupiedEntryRef<'a, V>),
+    Vacant(VacantEntryRef<'a, 'q, K, V, Q>),
+}
+
+/// An entry holding a value
+pub struct OccupiedEntryRef<'a, V> {
+    value: &'a mut V,
+}
+
+/// An entry with no value yet, remembering the borrowed key
+pub struct VacantEntryRef<'a, 'q, K, V, Q: ?Sized> {
+    map: &'a mut HashMap<K, V>,
+    key: &'q Q,
+}
+
+impl<'a, 'q, K, V, Q> EntryRef<'a, 'q, K, V, Q>
+where
+    K: core::hash::Hash + Eq + Clone + core::borrow::Borrow<Q> + From<&'q Q>,
+    V: Clone,
+    Q: core::hash::Hash + Eq + ?Sized,
+{
+    /// Returns the value, inserting `default` first if the entry is vacant
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+
+    /// Returns the value, inserting the result of `default` first if the
+    /// entry is vacant
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            EntryRef::Occupied(entry) => entry.into_mut(),
+            EntryRef::Vacant(entry) => entry.insert(default()),
+        }
+    }
+
+    /// Calls `f` on the value if the entry is occupied
+    pub fn and_modify<F: FnOnce(&mut V)>(mut self, f: F) -> Self {
+        if let EntryRef::Occupied(entry) = &mut self {
+            f(entry.get_mut());
+        }
+        self
+    }
+}
+
+impl<'a, V> OccupiedEntryRef<'a, V> {
+    /// Returns the value
+    pub fn get(&self) -> &V {
+        self.value
+    }
+
+    /// Returns the value mutably
+    pub fn get_mut(&mut self) -> &mut V {
+        self.value
+    }
+
+    /// Returns the value with the lifetime of the map borrow
+    pub fn into_mut(self) -> &'a mut V {
+        self.value
+    }
+}
+
+impl<'a, 'q, K, V, Q> VacantEntryRef<'a, 'q, K, V, Q>
+where
+    K: core::hash::Hash + Eq + Clone + From<&'q Q>,
+    V: Clone,
+    Q: core::hash::Hash + ?Sized,
+{
+    /// Returns the borrowed key
+    pub fn key(&self) -> &'q Q {
+        self.key
+    }
+
+    /// Inserts `v` under an owned copy of the key and returns it
+    pub fn insert(self, v: V) -> &'a mut V {
+        let map = self.map;
+        // grow first, so that the new entry is not moved to the old table
+        map.size = map.size.checked_add(1).expect("HashMap: too many entries");
+        map.grow_if_needed();
+        let idx = map.hash(self.key) % map.buckets.len();
+        let bucket = map.buckets[idx].get_or_insert_with(Vec::new);
+        bucket.push((K::from(self.key), v));
+        &mut bucket.last_mut().unwrap().1
+    }
 }
 
 /// A simple hasher using secret
@@ -76,7 +518,8 @@ impl core::hash::Hasher for SimpleHasher {
 }
 
 pub struct Iter<'a, K, V> {
-    buckets: core::slice::Iter<'a, Option<Vec<(K, V)>>>,
+    buckets:
+        core::iter::Chain<core::slice::Iter<'a, Bucket<K, V>>, core::slice::Iter<'a, Bucket<K, V>>>,
     current_bucket: Option<core::slice::Iter<'a, (K, V)>>,
 }
 
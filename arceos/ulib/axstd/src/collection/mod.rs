@@ -1,5 +1,5 @@
 #[cfg(feature = "alloc")]
-pub use self::hashmap::HashMap;
+pub use self::hashmap::{EntryRef, HashMap, HashMapStats};
 
 #[cfg(feature = "alloc")]
 pub mod hashmap;
@@ -0,0 +1,39 @@
+#!/bin/bash
+
+tmp_file=api_tests_output.txt
+grep_content="API tests run OK!"
+
+# console input, consumed in order by the tests:
+# - "r": a keystroke in raw mode
+# - "ax<DEL>b\n": a line edited in cooked mode
+# - "key=1;ab\r": fields read until a delimiter
+# - "cd": a line never finished
+input='rax\177b\nkey=1;ab\rcd'
+
+cd arceos/ || exit
+
+
+rm pflash.img -f
+rm disk.img -f
+
+make pflash_img
+make disk_img
+
+
+printf "$input" | make run A=exercises/api_tests/ BLK=y > $tmp_file 2>/dev/null
+
+output=$(tail -n1 ./$tmp_file | grep -a "$grep_content")
+# console output that the app cannot read back
+buffered=$(grep -a "buffered: a=1 b=2" ./$tmp_file)
+log=$(grep -a "log: " ./$tmp_file)
+
+rm -rf $tmp_file
+
+if [[ -z "$output" || -z "$buffered" ]] ||
+    [[ "$log" != *"[WARN] log: shown 1"* || "$log" == *"hidden"* ]]; then
+    echo "api_tests fault"
+    exit 1
+else
+    echo "api_tests pass"
+    exit 0
+fi